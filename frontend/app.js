@@ -1,10 +1,14 @@
 const API_BASE = '/api';
 let authToken = localStorage.getItem('token');
+let refreshToken = localStorage.getItem('refreshToken');
 let currentUser = null;
 let currentAuthMode = 'login';
 
 // --- Initialization ---
-document.addEventListener('DOMContentLoaded', () => {
+document.addEventListener('DOMContentLoaded', async () => {
+    await handleEmailLinks();
+    await handleSsoCallback();
+    setupSso();
     if (authToken) {
         verifyToken();
     } else {
@@ -12,6 +16,43 @@ document.addEventListener('DOMContentLoaded', () => {
     }
 });
 
+// Links from verification / password reset / email change emails land on
+// /?verify_email=..., /?reset_password=... or /?confirm_email=...
+async function handleEmailLinks() {
+    const params = new URLSearchParams(window.location.search);
+    const verifyToken = params.get('verify_email');
+    const resetToken = params.get('reset_password');
+    const confirmToken = params.get('confirm_email');
+    if (!verifyToken && !resetToken && !confirmToken) return;
+    
+    window.history.replaceState({}, '', window.location.pathname);
+    
+    if (confirmToken) {
+        const response = await fetch(`${API_BASE}/auth/email/confirm`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ token: confirmToken })
+        });
+        alert(response.ok ? 'Your email address has been changed.' : 'This confirmation link is invalid or has expired.');
+    } else if (verifyToken) {
+        const response = await fetch(`${API_BASE}/auth/verify-email`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ token: verifyToken })
+        });
+        alert(response.ok ? 'Email address confirmed!' : 'This verification link is invalid or has expired.');
+    } else {
+        const password = prompt('Choose a new password (at least 8 characters):');
+        if (!password) return;
+        const response = await fetch(`${API_BASE}/auth/password/reset`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ token: resetToken, password })
+        });
+        alert(response.ok ? 'Password updated. Please sign in.' : 'This reset link is invalid or has expired.');
+    }
+}
+
 // --- Navigation ---
 function showPage(page) {
     if (page === 'auth') {
@@ -56,14 +97,17 @@ async function handleAuth(event) {
             body: JSON.stringify({ email, password })
         });
         
-        const data = await response.json();
+        let data = await response.json();
         
         if (!response.ok) throw new Error(data.message || 'Authentication failed');
         
+        if (data.two_factor_required) {
+            data = await completeTwoFactor(data.challenge_token);
+        }
+        
         if (currentAuthMode === 'login') {
-            authToken = data.token;
+            storeTokens(data);
             currentUser = data.user;
-            localStorage.setItem('token', authToken);
             document.getElementById('user-email').textContent = currentUser.email;
             showPage('dashboard');
         } else {
@@ -75,11 +119,112 @@ async function handleAuth(event) {
     }
 }
 
-async function verifyToken() {
+// --- Single Sign-On ---
+async function setupSso() {
+    const response = await fetch(`${API_BASE}/auth/sso`);
+    if (!response.ok) return;
+    const config = await response.json();
+    if (!config.enabled) return;
+    
+    const button = document.getElementById('sso-login');
+    button.textContent = `Sign in with ${config.provider_name}`;
+    button.classList.remove('hidden');
+}
+
+async function startSso() {
+    const response = await fetch(`${API_BASE}/auth/sso/start`, { method: 'POST' });
+    if (!response.ok) {
+        document.getElementById('auth-error').textContent = await response.text();
+        return;
+    }
+    const { authorization_url } = await response.json();
+    window.location.href = authorization_url;
+}
+
+// The identity provider redirects back to /?code=...&state=...
+async function handleSsoCallback() {
+    const params = new URLSearchParams(window.location.search);
+    const code = params.get('code');
+    const state = params.get('state');
+    if (!code || !state) return;
+    
+    window.history.replaceState({}, '', window.location.pathname);
+    
     try {
-        const response = await fetch(`${API_BASE}/auth/me`, {
-            headers: { 'Authorization': `Bearer ${authToken}` }
+        const response = await fetch(`${API_BASE}/auth/sso/callback`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ code, state })
         });
+        if (!response.ok) throw new Error(await response.text());
+        
+        let data = await response.json();
+        if (data.two_factor_required) {
+            data = await completeTwoFactor(data.challenge_token);
+        }
+        storeTokens(data);
+    } catch (err) {
+        alert(`Single sign-on failed: ${err.message}`);
+    }
+}
+
+async function completeTwoFactor(challengeToken) {
+    const code = prompt('Enter the 6-digit code from your authenticator app (or a recovery code):');
+    if (!code) throw new Error('Two-factor code required');
+    
+    const isTotp = /^\d{6}$/.test(code.trim());
+    const response = await fetch(`${API_BASE}/auth/2fa/verify`, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({
+            challenge_token: challengeToken,
+            ...(isTotp ? { code: code.trim() } : { recovery_code: code.trim() })
+        })
+    });
+    if (!response.ok) throw new Error('Invalid two-factor code');
+    return response.json();
+}
+
+function storeTokens(data) {
+    authToken = data.token;
+    refreshToken = data.refresh_token;
+    localStorage.setItem('token', authToken);
+    localStorage.setItem('refreshToken', refreshToken);
+}
+
+async function refreshSession() {
+    if (!refreshToken) return false;
+    try {
+        const response = await fetch(`${API_BASE}/auth/refresh`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ refresh_token: refreshToken })
+        });
+        if (!response.ok) return false;
+        storeTokens(await response.json());
+        return true;
+    } catch {
+        return false;
+    }
+}
+
+// Authenticated fetch that transparently refreshes an expired access token once
+async function apiFetch(path, options = {}) {
+    const send = () => fetch(`${API_BASE}${path}`, {
+        ...options,
+        headers: { ...(options.headers || {}), 'Authorization': `Bearer ${authToken}` }
+    });
+
+    let response = await send();
+    if (response.status === 401 && await refreshSession()) {
+        response = await send();
+    }
+    return response;
+}
+
+async function verifyToken() {
+    try {
+        const response = await apiFetch('/auth/me');
         if (response.ok) {
             currentUser = await response.json();
             document.getElementById('user-email').textContent = currentUser.email;
@@ -93,9 +238,17 @@ async function verifyToken() {
 }
 
 function logout() {
+    if (authToken) {
+        fetch(`${API_BASE}/auth/logout`, {
+            method: 'POST',
+            headers: { 'Authorization': `Bearer ${authToken}` }
+        }).catch(() => {});
+    }
     authToken = null;
+    refreshToken = null;
     currentUser = null;
     localStorage.removeItem('token');
+    localStorage.removeItem('refreshToken');
     showPage('auth');
 }
 
@@ -106,13 +259,30 @@ async function loadDashboardData() {
 }
 
 // --- Task Management ---
+function escapeHtml(text) {
+    const el = document.createElement('span');
+    el.textContent = text;
+    return el.innerHTML;
+}
+
+// The API pages its task list, so follow X-Next-Cursor until the last page
+async function fetchAllTasks() {
+    const tasks = [];
+    let cursor = null;
+    do {
+        const query = cursor ? `?limit=200&cursor=${encodeURIComponent(cursor)}` : '?limit=200';
+        const response = await apiFetch(`/tasks${query}`);
+        if (!response.ok) throw new Error(await response.text());
+        tasks.push(...await response.json());
+        cursor = response.headers.get('X-Next-Cursor');
+    } while (cursor);
+    return tasks;
+}
+
 async function loadTasks() {
     const list = document.getElementById('task-list');
     try {
-        const response = await fetch(`${API_BASE}/tasks`, {
-            headers: { 'Authorization': `Bearer ${authToken}` }
-        });
-        const tasks = await response.json();
+        const tasks = await fetchAllTasks();
         
         list.innerHTML = tasks.length ? '' : '<p class="text-dim">No tasks yet.</p>';
         tasks.forEach(task => {
@@ -121,7 +291,10 @@ async function loadTasks() {
             el.innerHTML = `
                 <div class="task-info">
                     <input type="checkbox" ${task.completed ? 'checked' : ''} onchange="toggleTask('${task.id}', this.checked)">
-                    <h3>${task.title}</h3>
+                    <h3>${escapeHtml(task.title)}${task.recurrence ? ' 🔁' : ''}</h3>
+                    ${task.priority !== 'medium' ? `<span class="text-dim">${task.priority}</span>` : ''}
+                    ${task.due_at ? `<span class="text-dim">Due ${new Date(task.due_at).toLocaleString()}</span>` : ''}
+                    ${task.tags.map(tag => `<span class="text-dim">#${escapeHtml(tag)}</span>`).join(' ')}
                 </div>
                 <button class="delete-btn" onclick="deleteTask('${task.id}')">🗑️</button>
             `;
@@ -134,20 +307,26 @@ async function loadTasks() {
 
 async function addTask() {
     const input = document.getElementById('new-task-title');
+    const dueInput = document.getElementById('new-task-due');
     const title = input.value.trim();
     if (!title) return;
     
+    // Local due times are sent with the browser's zone so "today" means the user's today
+    const body = { title };
+    if (dueInput.value) {
+        body.due_at = dueInput.value;
+        body.time_zone = Intl.DateTimeFormat().resolvedOptions().timeZone;
+    }
+    
     try {
-        const response = await fetch(`${API_BASE}/tasks`, {
+        const response = await apiFetch(`/tasks`, {
             method: 'POST',
-            headers: { 
-                'Content-Type': 'application/json',
-                'Authorization': `Bearer ${authToken}`
-            },
-            body: JSON.stringify({ title })
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(body)
         });
         if (response.ok) {
             input.value = '';
+            dueInput.value = '';
             loadTasks();
         }
     } catch (err) {
@@ -157,12 +336,9 @@ async function addTask() {
 
 async function toggleTask(id, completed) {
     try {
-        await fetch(`${API_BASE}/tasks/${id}`, {
+        await apiFetch(`/tasks/${id}`, {
             method: 'PATCH',
-            headers: { 
-                'Content-Type': 'application/json',
-                'Authorization': `Bearer ${authToken}`
-            },
+            headers: { 'Content-Type': 'application/json' },
             body: JSON.stringify({ completed })
         });
         loadTasks();
@@ -174,9 +350,8 @@ async function toggleTask(id, completed) {
 async function deleteTask(id) {
     if (!confirm('Are you sure?')) return;
     try {
-        await fetch(`${API_BASE}/tasks/${id}`, {
-            method: 'DELETE',
-            headers: { 'Authorization': `Bearer ${authToken}` }
+        await apiFetch(`/tasks/${id}`, {
+            method: 'DELETE'
         });
         loadTasks();
     } catch (err) {
@@ -192,8 +367,8 @@ async function loadExpenses() {
     
     try {
         const [expRes, sumRes] = await Promise.all([
-            fetch(`${API_BASE}/expenses`, { headers: { 'Authorization': `Bearer ${authToken}` } }),
-            fetch(`${API_BASE}/expenses/summary`, { headers: { 'Authorization': `Bearer ${authToken}` } })
+            apiFetch(`/expenses`),
+            apiFetch(`/expenses/summary`)
         ]);
         
         const expenses = await expRes.json();
@@ -243,12 +418,9 @@ async function addExpense() {
     if (!category || isNaN(amount)) return;
     
     try {
-        const response = await fetch(`${API_BASE}/expenses`, {
+        const response = await apiFetch(`/expenses`, {
             method: 'POST',
-            headers: { 
-                'Content-Type': 'application/json',
-                'Authorization': `Bearer ${authToken}`
-            },
+            headers: { 'Content-Type': 'application/json' },
             body: JSON.stringify({ category, amount })
         });
         if (response.ok) {
@@ -265,9 +437,8 @@ async function addExpense() {
 async function getAISuggestion() {
     showModal('Thinking...');
     try {
-        const response = await fetch(`${API_BASE}/ai/suggest`, {
-            method: 'POST',
-            headers: { 'Authorization': `Bearer ${authToken}` }
+        const response = await apiFetch(`/ai/suggest`, {
+            method: 'POST'
         });
         const data = await response.json();
         showModal(data.suggestion, 'AI Smart Suggestion');
@@ -280,12 +451,9 @@ async function getAIBudgetAnalysis() {
     showModal('Analyzing your spending...');
     try {
         // We reuse the chat endpoint for custom analysis if specific endpoint not available
-        const response = await fetch(`${API_BASE}/ai/chat`, {
+        const response = await apiFetch(`/ai/chat`, {
             method: 'POST',
-            headers: { 
-                'Content-Type': 'application/json',
-                'Authorization': `Bearer ${authToken}`
-            },
+            headers: { 'Content-Type': 'application/json' },
             body: JSON.stringify({ message: "Analyze my budget based on my recorded expenses and give me brief advice." })
         });
         const data = await response.json();
@@ -311,12 +479,9 @@ async function sendChat() {
     messages.scrollTop = messages.scrollHeight;
 
     try {
-        const response = await fetch(`${API_BASE}/ai/chat`, {
+        const response = await apiFetch(`/ai/chat`, {
             method: 'POST',
-            headers: { 
-                'Content-Type': 'application/json',
-                'Authorization': `Bearer ${authToken}` 
-            },
+            headers: { 'Content-Type': 'application/json' },
             body: JSON.stringify({ message })
         });
         const data = await response.json();
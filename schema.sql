@@ -2,7 +2,14 @@
 CREATE TABLE IF NOT EXISTS users (
     id TEXT PRIMARY KEY,
     email TEXT UNIQUE NOT NULL,
-    password_hash TEXT NOT NULL,
+    password_hash TEXT, -- NULL for accounts that only sign in through OpenID Connect
+    totp_secret TEXT,
+    totp_enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    totp_last_step INTEGER,
+    email_verified_at DATETIME,
+    role TEXT NOT NULL DEFAULT 'user', -- 'user', 'admin'
+    disabled_at DATETIME,
+    deletion_scheduled_at DATETIME, -- self-deletion pending; purged after the grace period
     created_at DATETIME DEFAULT CURRENT_TIMESTAMP
 );
 
@@ -11,21 +18,188 @@ CREATE TABLE IF NOT EXISTS tasks (
     id TEXT PRIMARY KEY,
     user_id TEXT NOT NULL,
     title TEXT NOT NULL,
-    completed BOOLEAN DEFAULT FALSE,
+    notes TEXT,
+    description TEXT, -- markdown
+    completed BOOLEAN DEFAULT FALSE, -- derived: whether the workflow state is done or cancelled
+    state_id TEXT REFERENCES workflow_states(id) ON DELETE SET NULL,
+    priority INTEGER NOT NULL DEFAULT 2, -- 1 low, 2 medium, 3 high, 4 urgent
+    start_at DATETIME, -- stored in UTC
+    due_at DATETIME,
+    time_zone TEXT, -- IANA zone the dates were entered in
+    project_id TEXT REFERENCES projects(id) ON DELETE SET NULL,
+    parent_id TEXT REFERENCES tasks(id) ON DELETE CASCADE, -- subtasks go with their parent
+    series_id TEXT REFERENCES task_series(id) ON DELETE SET NULL, -- set on each occurrence of a recurring task
+    position TEXT, -- fractional index ordering the user's tasks in every list
+    deleted_at DATETIME, -- in the trash until purged
+    updated_at DATETIME, -- last change other than going in or out of the trash, kept by triggers (see db.rs)
     created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-    FOREIGN KEY (user_id) REFERENCES users(id)
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
 );
 
+-- Workflow states a user's tasks move through, shown as board columns
+CREATE TABLE IF NOT EXISTS workflow_states (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL COLLATE NOCASE,
+    category TEXT NOT NULL, -- 'open', 'done' or 'cancelled'
+    position INTEGER NOT NULL DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (user_id, name),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Moves allowed between workflow states
+CREATE TABLE IF NOT EXISTS workflow_transitions (
+    from_state_id TEXT NOT NULL,
+    to_state_id TEXT NOT NULL,
+    PRIMARY KEY (from_state_id, to_state_id),
+    FOREIGN KEY (from_state_id) REFERENCES workflow_states(id) ON DELETE CASCADE,
+    FOREIGN KEY (to_state_id) REFERENCES workflow_states(id) ON DELETE CASCADE
+);
+
+-- Projects grouping a user's tasks
+CREATE TABLE IF NOT EXISTS projects (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL COLLATE NOCASE,
+    color TEXT NOT NULL DEFAULT '#6366f1',
+    position INTEGER NOT NULL DEFAULT 0,
+    archived_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (user_id, name),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Recurrence rules shared by the occurrences of a recurring task
+CREATE TABLE IF NOT EXISTS task_series (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    rrule TEXT NOT NULL, -- RFC 5545 RRULE, e.g. FREQ=WEEKLY;BYDAY=MO,TH
+    dtstart DATETIME NOT NULL, -- first occurrence, in UTC
+    time_zone TEXT NOT NULL, -- IANA zone the rule is evaluated in
+    exdates TEXT NOT NULL DEFAULT '[]', -- JSON array of skipped occurrences
+    ended_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Comments on tasks, in markdown
+CREATE TABLE IF NOT EXISTS task_comments (
+    id TEXT PRIMARY KEY,
+    task_id TEXT NOT NULL,
+    user_id TEXT NOT NULL, -- author
+    body TEXT NOT NULL,
+    edited_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS idx_task_comments_task ON task_comments(task_id, created_at);
+
+-- Time spent on tasks; a running timer is an entry without ended_at
+CREATE TABLE IF NOT EXISTS time_entries (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    task_id TEXT NOT NULL,
+    started_at DATETIME NOT NULL,
+    ended_at DATETIME,
+    note TEXT,
+    billable BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS idx_time_entries_user ON time_entries(user_id, started_at);
+CREATE INDEX IF NOT EXISTS idx_time_entries_task ON time_entries(task_id);
+-- One running timer per user
+CREATE UNIQUE INDEX IF NOT EXISTS idx_time_entries_running ON time_entries(user_id) WHERE ended_at IS NULL;
+
+-- Tags, shared by a user's tasks
+CREATE TABLE IF NOT EXISTS tags (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL COLLATE NOCASE,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (user_id, name),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS task_tags (
+    task_id TEXT NOT NULL,
+    tag_id TEXT NOT NULL,
+    PRIMARY KEY (task_id, tag_id),
+    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS idx_task_tags_tag ON task_tags(tag_id);
+
+-- "task_id is blocked by blocked_by_id" links between a user's tasks
+CREATE TABLE IF NOT EXISTS task_dependencies (
+    task_id TEXT NOT NULL,
+    blocked_by_id TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (task_id, blocked_by_id),
+    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE,
+    FOREIGN KEY (blocked_by_id) REFERENCES tasks(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS idx_task_dependencies_blocker ON task_dependencies(blocked_by_id);
+
+-- Reminders, on a task or free-standing, sent out by the reminder scheduler
+CREATE TABLE IF NOT EXISTS reminders (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    task_id TEXT REFERENCES tasks(id) ON DELETE CASCADE,
+    message TEXT, -- NULL uses the task's title
+    remind_at DATETIME NOT NULL,
+    channel TEXT NOT NULL DEFAULT 'in_app', -- 'in_app', 'webhook', 'email'
+    webhook_url TEXT,
+    status TEXT NOT NULL DEFAULT 'pending', -- 'pending', 'sent', 'skipped', 'failed'
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at DATETIME NOT NULL, -- remind_at, pushed back after failed deliveries
+    last_error TEXT,
+    sent_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS idx_reminders_due ON reminders(status, next_attempt_at);
+CREATE INDEX IF NOT EXISTS idx_reminders_task ON reminders(task_id);
+
+-- In-app notifications, e.g. from reminders on the in_app channel
+CREATE TABLE IF NOT EXISTS notifications (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    reminder_id TEXT REFERENCES reminders(id) ON DELETE SET NULL,
+    title TEXT NOT NULL,
+    body TEXT NOT NULL,
+    read_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS idx_notifications_user ON notifications(user_id, created_at);
+
 -- Expenses table
 CREATE TABLE IF NOT EXISTS expenses (
     id TEXT PRIMARY KEY,
     user_id TEXT NOT NULL,
     category TEXT NOT NULL,
     amount REAL NOT NULL,
+    notes TEXT,
+    deleted_at DATETIME, -- in the trash until purged
     created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-    FOREIGN KEY (user_id) REFERENCES users(id)
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
 );
 
+-- Undoable changes to tasks and expenses, newest last
+CREATE TABLE IF NOT EXISTS operations (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    payload TEXT NOT NULL, -- JSON: the action and the rows it touched
+    undone_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS idx_operations_user ON operations(user_id, created_at);
+
 -- AI Chat history
 CREATE TABLE IF NOT EXISTS chat_messages (
     id TEXT PRIMARY KEY,
@@ -33,5 +207,122 @@ CREATE TABLE IF NOT EXISTS chat_messages (
     role TEXT NOT NULL, -- 'system', 'user', 'assistant'
     content TEXT NOT NULL,
     created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-    FOREIGN KEY (user_id) REFERENCES users(id)
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Full-text index over task titles and notes, expense categories and notes
+-- and chat messages. `kind` is 'task', 'expense' or 'chat'; the handlers
+-- that write those rows keep their entries in sync.
+CREATE VIRTUAL TABLE IF NOT EXISTS search_index USING fts5(
+    title,
+    body,
+    kind UNINDEXED,
+    item_id UNINDEXED,
+    user_id UNINDEXED,
+    created_at UNINDEXED,
+    tokenize = 'porter unicode61 remove_diacritics 2'
+);
+
+-- Login sessions backing refresh tokens
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    refresh_token_hash TEXT UNIQUE NOT NULL,
+    user_agent TEXT,
+    ip TEXT, -- where the session was started
+    last_seen_at DATETIME,
+    expires_at DATETIME NOT NULL,
+    revoked_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Personal access tokens for scripts and integrations
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    token_hash TEXT UNIQUE NOT NULL,
+    scopes TEXT NOT NULL, -- JSON array, e.g. ["tasks:read"]
+    last_used_at DATETIME,
+    expires_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- One-time recovery codes for two-factor authentication
+CREATE TABLE IF NOT EXISTS recovery_codes (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    code_hash TEXT NOT NULL,
+    used_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Password-verified logins waiting for their second factor
+CREATE TABLE IF NOT EXISTS two_factor_challenges (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    token_hash TEXT UNIQUE NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at DATETIME NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Single-use tokens sent by email (verify-email, reset-password)
+CREATE TABLE IF NOT EXISTS email_tokens (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    purpose TEXT NOT NULL,
+    payload TEXT, -- e.g. the new address for 'change_email'
+    expires_at DATETIME NOT NULL,
+    used_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Login attempts, used for backoff and lockout (purged after 24h)
+CREATE TABLE IF NOT EXISTS login_attempts (
+    id TEXT PRIMARY KEY,
+    email TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    succeeded BOOLEAN NOT NULL,
+    created_at DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_login_attempts_email ON login_attempts(email, created_at);
+CREATE INDEX IF NOT EXISTS idx_login_attempts_ip ON login_attempts(ip, created_at);
+
+-- Security-relevant events shown to the account owner (e.g. lockouts)
+CREATE TABLE IF NOT EXISTS security_events (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    ip TEXT,
+    detail TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- OpenID Connect identities linked to a user (one per provider subject)
+CREATE TABLE IF NOT EXISTS user_identities (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    issuer TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    email TEXT NOT NULL,
+    last_login_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (issuer, subject),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Pending OpenID Connect logins, keyed by the hashed `state` parameter
+CREATE TABLE IF NOT EXISTS oidc_logins (
+    state_hash TEXT PRIMARY KEY,
+    code_verifier TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    expires_at DATETIME NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
 );
@@ -0,0 +1,462 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use sqlx::Sqlite;
+use std::env;
+use std::net::SocketAddr;
+
+use super::AppState;
+use crate::auth::{
+    account_deletion_grace, confirm_password, consume_email_token, delete_user, hash_password, issue_email_token,
+    expire_api_tokens, record_security_event, revoke_all_sessions, AuthSession, EmailTokenPurpose,
+};
+use crate::throttle::client_ip;
+use crate::mailer::Email;
+use crate::models::*;
+
+const VERIFY_EMAIL_TTL_HOURS: i64 = 24;
+const RESET_PASSWORD_TTL_MINUTES: i64 = 60;
+const CHANGE_EMAIL_TTL_HOURS: i64 = 24;
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Rejects passwords shorter than `MIN_PASSWORD_LENGTH` characters.
+pub fn check_password(password: &str) -> Result<(), (StatusCode, String)> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err((StatusCode::BAD_REQUEST, format!("Password must be at least {} characters", MIN_PASSWORD_LENGTH)));
+    }
+    Ok(())
+}
+
+pub fn app_url() -> String {
+    env::var("APP_URL").unwrap_or_else(|_| "http://localhost:3000".to_string())
+}
+
+/// Emails `user` a link to confirm their address.
+pub async fn send_verification_email(state: &AppState, user: &User) -> Result<(), String> {
+    let token = issue_email_token(
+        &state.db,
+        &user.id,
+        EmailTokenPurpose::VerifyEmail,
+        None,
+        chrono::Duration::hours(VERIFY_EMAIL_TTL_HOURS),
+    )
+    .await?;
+
+    state
+        .mailer
+        .send(Email {
+            to: user.email.clone(),
+            subject: "Confirm your email address".to_string(),
+            body: format!(
+                "Welcome! Confirm your email address by opening this link:\n\n{}/?verify_email={}\n\nThe link expires in {} hours.",
+                app_url(),
+                token,
+                VERIFY_EMAIL_TTL_HOURS
+            ),
+        })
+        .await
+}
+
+// --- Email Verification & Password Reset Handlers ---
+
+pub async fn verify_email(
+    State(state): State<AppState>,
+    Json(payload): Json<VerifyEmailRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let (user_id, _) = consume_email_token(&state.db, &payload.token, EmailTokenPurpose::VerifyEmail)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid or expired verification link".to_string()))?;
+
+    sqlx::query("UPDATE users SET email_verified_at = COALESCE(email_verified_at, ?) WHERE id = ?")
+        .bind(chrono::Utc::now())
+        .bind(&user_id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn resend_verification(
+    State(state): State<AppState>,
+    session: AuthSession,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    if session.user.email_verified_at.is_some() {
+        return Err((StatusCode::CONFLICT, "Email address is already verified".to_string()));
+    }
+
+    send_verification_email(&state, &session.user)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Always answers 202 so the endpoint cannot be used to probe which emails have accounts.
+pub async fn forgot_password(
+    State(state): State<AppState>,
+    Json(payload): Json<ForgotPasswordRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let user = sqlx::query_as::<Sqlite, User>("SELECT * FROM users WHERE email = ?")
+        .bind(&payload.email)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if let Some(user) = user {
+        let token = issue_email_token(
+            &state.db,
+            &user.id,
+            EmailTokenPurpose::ResetPassword,
+            None,
+            chrono::Duration::minutes(RESET_PASSWORD_TTL_MINUTES),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+        let sent = state
+            .mailer
+            .send(Email {
+                to: user.email.clone(),
+                subject: "Reset your password".to_string(),
+                body: format!(
+                    "Someone asked to reset the password for this account. If it was you, open:\n\n{}/?reset_password={}\n\nThe link expires in {} minutes. If it wasn't you, ignore this email.",
+                    app_url(),
+                    token,
+                    RESET_PASSWORD_TTL_MINUTES
+                ),
+            })
+            .await;
+
+        if let Err(e) = sent {
+            tracing::error!("Failed to send password reset email: {}", e);
+        }
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub async fn reset_password(
+    State(state): State<AppState>,
+    Json(payload): Json<ResetPasswordRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    check_password(&payload.password)?;
+
+    let (user_id, _) = consume_email_token(&state.db, &payload.token, EmailTokenPurpose::ResetPassword)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid or expired reset link".to_string()))?;
+
+    // Receiving the link also proves ownership of the address
+    sqlx::query("UPDATE users SET password_hash = ?, email_verified_at = COALESCE(email_verified_at, ?) WHERE id = ?")
+        .bind(hash_password(&payload.password))
+        .bind(chrono::Utc::now())
+        .bind(&user_id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Whoever knew the old password must not stay logged in
+    revoke_all_sessions(&state.db, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    expire_api_tokens(&state.db, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// --- Account Self-Service Handlers ---
+
+/// Changes the password after re-checking the current one. Every other
+/// session is logged out and API tokens expire; the one making the change
+/// stays signed in.
+pub async fn change_password(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    session: AuthSession,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    confirm_password(&session.user, &payload.current_password)?;
+    check_password(&payload.new_password)?;
+
+    sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+        .bind(hash_password(&payload.new_password))
+        .bind(&session.user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query("UPDATE sessions SET revoked_at = ? WHERE user_id = ? AND id != ? AND revoked_at IS NULL")
+        .bind(chrono::Utc::now())
+        .bind(&session.user.id)
+        .bind(&session.session_id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    expire_api_tokens(&state.db, &session.user.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    record_security_event(
+        &state.db,
+        &session.user.id,
+        "password_changed",
+        Some(&client_ip(&addr, &headers)),
+        "Password changed; other sessions were logged out",
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Starts an email change. The address only changes once the link sent to
+/// the new address is opened; the current address gets a heads-up.
+pub async fn request_email_change(
+    State(state): State<AppState>,
+    session: AuthSession,
+    Json(payload): Json<ChangeEmailRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let new_email = payload.new_email.trim();
+    confirm_password(&session.user, &payload.password)?;
+    if !new_email.contains('@') {
+        return Err((StatusCode::BAD_REQUEST, "Invalid email address".to_string()));
+    }
+    if new_email.eq_ignore_ascii_case(&session.user.email) {
+        return Err((StatusCode::BAD_REQUEST, "That is already your email address".to_string()));
+    }
+    ensure_email_available(&state, new_email).await?;
+
+    let token = issue_email_token(
+        &state.db,
+        &session.user.id,
+        EmailTokenPurpose::ChangeEmail,
+        Some(new_email),
+        chrono::Duration::hours(CHANGE_EMAIL_TTL_HOURS),
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    state
+        .mailer
+        .send(Email {
+            to: new_email.to_string(),
+            subject: "Confirm your new email address".to_string(),
+            body: format!(
+                "Confirm that this should become the email address of your account:\n\n{}/?confirm_email={}\n\nThe link expires in {} hours.",
+                app_url(),
+                token,
+                CHANGE_EMAIL_TTL_HOURS
+            ),
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let notice = state
+        .mailer
+        .send(Email {
+            to: session.user.email.clone(),
+            subject: "Email change requested".to_string(),
+            body: format!(
+                "Someone asked to change your account's email address to {}. If this wasn't you, change your password now.",
+                new_email
+            ),
+        })
+        .await;
+    if let Err(e) = notice {
+        tracing::error!("Failed to notify old address of email change: {}", e);
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Switches to the new address once its link is opened, logging out every
+/// session and expiring API tokens.
+pub async fn confirm_email_change(
+    State(state): State<AppState>,
+    Json(payload): Json<VerifyEmailRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let (user_id, new_email) = consume_email_token(&state.db, &payload.token, EmailTokenPurpose::ChangeEmail)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid or expired confirmation link".to_string()))?;
+    let new_email = new_email.ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid confirmation link".to_string()))?;
+
+    // The address may have been taken since the link was sent
+    ensure_email_available(&state, &new_email).await?;
+
+    sqlx::query("UPDATE users SET email = ?, email_verified_at = ? WHERE id = ?")
+        .bind(&new_email)
+        .bind(chrono::Utc::now())
+        .bind(&user_id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Sessions and tokens were handed out to the old address
+    revoke_all_sessions(&state.db, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    expire_api_tokens(&state.db, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    record_security_event(&state.db, &user_id, "email_changed", None, &format!("Email changed to {}", new_email))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn ensure_email_available(state: &AppState, email: &str) -> Result<(), (StatusCode, String)> {
+    let taken: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM users WHERE lower(email) = lower(?)")
+        .bind(email)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if taken {
+        return Err((StatusCode::CONFLICT, "That email address is already in use".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Deletes the caller's account. With a grace period configured the account
+/// is only scheduled for deletion and logging in again cancels it; without
+/// one, the account and all of its data are removed right away.
+pub async fn delete_account(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    session: AuthSession,
+    Json(payload): Json<DeleteAccountRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    confirm_password(&session.user, &payload.password)?;
+
+    let grace = account_deletion_grace();
+    if grace <= chrono::Duration::zero() {
+        delete_user(&state.db, &session.user.id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        return Ok(Json(DeleteAccountResponse { deletion_scheduled_at: None }));
+    }
+
+    let deletion_scheduled_at = chrono::Utc::now() + grace;
+    sqlx::query("UPDATE users SET deletion_scheduled_at = ? WHERE id = ?")
+        .bind(deletion_scheduled_at)
+        .bind(&session.user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    revoke_all_sessions(&state.db, &session.user.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    record_security_event(
+        &state.db,
+        &session.user.id,
+        "account_deletion_scheduled",
+        Some(&client_ip(&addr, &headers)),
+        &format!("Account will be deleted at {}; log in before then to cancel", deletion_scheduled_at),
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(DeleteAccountResponse {
+        deletion_scheduled_at: Some(deletion_scheduled_at),
+    }))
+}
+
+// --- Security Event Handlers ---
+
+pub async fn list_security_events(
+    State(state): State<AppState>,
+    session: AuthSession,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let events = sqlx::query_as::<Sqlite, SecurityEvent>(
+        "SELECT * FROM security_events WHERE user_id = ? ORDER BY created_at DESC LIMIT 100",
+    )
+    .bind(&session.user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(events))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{TestApp, PASSWORD};
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn registration_requires_a_long_enough_password() {
+        let app = TestApp::new().await;
+        let body = json!({ "email": "ann@example.com", "password": "short" });
+        let response = app.request(Method::POST, "/api/auth/register", None, Some(body)).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users").fetch_one(&app.state.db).await.unwrap();
+        assert_eq!(users, 0);
+    }
+
+    #[tokio::test]
+    async fn a_confirmed_email_change_revokes_sessions_and_tokens() {
+        let app = TestApp::new().await;
+        let session = app.sign_up("ann@example.com").await;
+        let created = app.post("/api/tokens", &session, json!({ "name": "script", "scopes": ["tasks:read"] })).await;
+        let api_token = created.body["token"].as_str().unwrap().to_string();
+
+        let body = json!({ "new_email": "ann@new.example", "password": PASSWORD });
+        let requested = app.request(Method::PUT, "/api/auth/email", Some(&session), Some(body)).await;
+        assert_eq!(requested.status, StatusCode::ACCEPTED, "{}", requested.body);
+        // Nothing changes until the link is opened
+        assert_eq!(app.get("/api/auth/me", &session).await.status, StatusCode::OK);
+
+        let sent = app.mailer.sent.lock().unwrap().iter().find(|email| email.to == "ann@new.example").cloned();
+        let link = sent.unwrap().body;
+        let token = link.split("confirm_email=").nth(1).unwrap().split_whitespace().next().unwrap();
+        let body = json!({ "token": token });
+        let confirmed = app.request(Method::POST, "/api/auth/email/confirm", None, Some(body)).await;
+        assert_eq!(confirmed.status, StatusCode::NO_CONTENT, "{}", confirmed.body);
+
+        assert_eq!(app.get("/api/auth/me", &session).await.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(app.get("/api/tasks", &api_token).await.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(app.login("ann@example.com").await.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(app.login("ann@new.example").await.status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn logging_in_cancels_a_scheduled_deletion() {
+        let app = TestApp::new().await;
+        let session = app.sign_up("ann@example.com").await;
+
+        let body = json!({ "password": PASSWORD });
+        let deleted = app.request(Method::DELETE, "/api/auth/account", Some(&session), Some(body)).await;
+        assert_eq!(deleted.status, StatusCode::OK, "{}", deleted.body);
+        assert!(deleted.body["deletion_scheduled_at"].is_string());
+        assert_eq!(app.get("/api/auth/me", &session).await.status, StatusCode::UNAUTHORIZED);
+
+        let login = app.login("ann@example.com").await;
+        assert_eq!(login.status, StatusCode::OK, "{}", login.body);
+        assert!(login.body["user"]["deletion_scheduled_at"].is_null());
+        let (scheduled, cancelled): (Option<String>, i64) = sqlx::query_as(
+            "SELECT deletion_scheduled_at,
+                    (SELECT COUNT(*) FROM security_events WHERE kind = 'account_deletion_cancelled')
+             FROM users",
+        )
+        .fetch_one(&app.state.db)
+        .await
+        .unwrap();
+        assert_eq!((scheduled, cancelled), (None, 1));
+    }
+}
@@ -0,0 +1,366 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use sqlx::{QueryBuilder, Sqlite, SqliteExecutor};
+
+use super::AppState;
+use crate::auth::{
+    delete_user, expire_api_tokens, hash_password, issue_email_token, revoke_all_sessions, AdminUser, EmailTokenPurpose, ROLES,
+    ROLE_ADMIN,
+};
+use crate::mailer::Email;
+use crate::models::*;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+async fn fetch_user<'e>(executor: impl SqliteExecutor<'e>, id: &str) -> Result<User, (StatusCode, String)> {
+    sqlx::query_as::<Sqlite, User>("SELECT * FROM users WHERE id = ?")
+        .bind(id)
+        .fetch_optional(executor)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "User not found".to_string()))
+}
+
+/// Refuses changes that would leave the instance without an active admin.
+async fn ensure_other_admin<'e>(executor: impl SqliteExecutor<'e>, user: &User) -> Result<(), (StatusCode, String)> {
+    if user.role != ROLE_ADMIN || user.disabled_at.is_some() {
+        return Ok(());
+    }
+
+    let others: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE role = ? AND disabled_at IS NULL AND id != ?")
+        .bind(ROLE_ADMIN)
+        .bind(&user.id)
+        .fetch_one(executor)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if others == 0 {
+        return Err((StatusCode::CONFLICT, "Cannot remove the last active admin".to_string()));
+    }
+
+    Ok(())
+}
+
+// --- Admin Handlers ---
+
+pub async fn list_users(
+    State(state): State<AppState>,
+    AdminUser(_admin): AdminUser,
+    Query(query): Query<AdminUserQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let filters = |builder: &mut QueryBuilder<Sqlite>| {
+        builder.push(" WHERE 1 = 1");
+        if let Some(q) = query.q.as_deref().filter(|q| !q.trim().is_empty()) {
+            let pattern = q.trim().replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            builder
+                .push(" AND email LIKE ")
+                .push_bind(format!("%{}%", pattern))
+                .push(" ESCAPE '\\'");
+        }
+        if let Some(role) = &query.role {
+            builder.push(" AND role = ").push_bind(role.clone());
+        }
+        match query.disabled {
+            Some(true) => {
+                builder.push(" AND disabled_at IS NOT NULL");
+            }
+            Some(false) => {
+                builder.push(" AND disabled_at IS NULL");
+            }
+            None => {}
+        }
+    };
+
+    let mut count = QueryBuilder::<Sqlite>::new("SELECT COUNT(*) FROM users");
+    filters(&mut count);
+    let total: i64 = count
+        .build_query_scalar()
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut select = QueryBuilder::<Sqlite>::new("SELECT * FROM users");
+    filters(&mut select);
+    select
+        .push(" ORDER BY created_at DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+    let users = select
+        .build_query_as::<User>()
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(AdminUserList { users, total }))
+}
+
+pub async fn get_user(
+    State(state): State<AppState>,
+    AdminUser(_admin): AdminUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    Ok(Json(fetch_user(&state.db, &id).await?))
+}
+
+/// Changes a user's role and/or disables (or re-enables) their account.
+/// Disabling also ends every session the user has.
+pub async fn update_user(
+    State(state): State<AppState>,
+    AdminUser(admin): AdminUser,
+    Path(id): Path<String>,
+    Json(payload): Json<AdminUpdateUserRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    // One transaction, so a refused disable doesn't leave the role change
+    // behind and the admin count can't change between check and update
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let user = fetch_user(&mut *tx, &id).await?;
+
+    if let Some(role) = &payload.role {
+        if !ROLES.contains(&role.as_str()) {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown role '{}'", role)));
+        }
+        if role != ROLE_ADMIN {
+            ensure_other_admin(&mut *tx, &user).await?;
+        }
+        sqlx::query("UPDATE users SET role = ? WHERE id = ?")
+            .bind(role)
+            .bind(&id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    match payload.disabled {
+        Some(true) => {
+            if user.id == admin.id {
+                return Err((StatusCode::CONFLICT, "You cannot disable your own account".to_string()));
+            }
+            ensure_other_admin(&mut *tx, &user).await?;
+            sqlx::query("UPDATE users SET disabled_at = COALESCE(disabled_at, ?) WHERE id = ?")
+                .bind(chrono::Utc::now())
+                .bind(&id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+        Some(false) => {
+            sqlx::query("UPDATE users SET disabled_at = NULL WHERE id = ?")
+                .bind(&id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+        None => {}
+    }
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if payload.disabled == Some(true) {
+        revoke_all_sessions(&state.db, &id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    }
+
+    Ok(Json(fetch_user(&state.db, &id).await?))
+}
+
+pub async fn delete_user_handler(
+    State(state): State<AppState>,
+    AdminUser(admin): AdminUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    if id == admin.id {
+        return Err((StatusCode::CONFLICT, "You cannot delete your own account here".to_string()));
+    }
+    let user = fetch_user(&state.db, &id).await?;
+    ensure_other_admin(&state.db, &user).await?;
+
+    delete_user(&state.db, &id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sets a new password directly, or emails the user a reset link when no
+/// password is given. Either way the user's sessions are revoked and their
+/// API tokens expire.
+pub async fn reset_user_password(
+    State(state): State<AppState>,
+    AdminUser(_admin): AdminUser,
+    Path(id): Path<String>,
+    Json(payload): Json<AdminResetPasswordRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let user = fetch_user(&state.db, &id).await?;
+
+    match payload.password {
+        Some(password) => {
+            super::account::check_password(&password)?;
+            sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+                .bind(hash_password(&password))
+                .bind(&id)
+                .execute(&state.db)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+        None => {
+            let token = issue_email_token(
+                &state.db,
+                &user.id,
+                EmailTokenPurpose::ResetPassword,
+                None,
+                chrono::Duration::hours(24),
+            )
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+            state
+                .mailer
+                .send(Email {
+                    to: user.email.clone(),
+                    subject: "Your password was reset by an administrator".to_string(),
+                    body: format!(
+                        "An administrator reset the password for this account. Choose a new one here:\n\n{}/?reset_password={}\n\nThe link expires in 24 hours.",
+                        super::account::app_url(),
+                        token
+                    ),
+                })
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        }
+    }
+
+    revoke_all_sessions(&state.db, &user.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    expire_api_tokens(&state.db, &user.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn instance_stats(
+    State(state): State<AppState>,
+    AdminUser(_admin): AdminUser,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let now = chrono::Utc::now();
+
+    let (users, admins, disabled_users, verified_users, signups_last_7_days) =
+        sqlx::query_as::<Sqlite, (i64, i64, i64, i64, i64)>(
+            "SELECT COUNT(*),
+                    COALESCE(SUM(role = ?), 0),
+                    COALESCE(SUM(disabled_at IS NOT NULL), 0),
+                    COALESCE(SUM(email_verified_at IS NOT NULL), 0),
+                    COALESCE(SUM(created_at >= datetime('now', '-7 days')), 0)
+             FROM users",
+        )
+        .bind(ROLE_ADMIN)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let active_sessions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sessions WHERE revoked_at IS NULL AND expires_at > ?")
+        .bind(now)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let (tasks, completed_tasks) =
+        sqlx::query_as::<Sqlite, (i64, i64)>("SELECT COUNT(*), COALESCE(SUM(completed), 0) FROM tasks WHERE deleted_at IS NULL")
+            .fetch_one(&state.db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let (expenses, total_spending) =
+        sqlx::query_as::<Sqlite, (i64, f64)>("SELECT COUNT(*), COALESCE(SUM(amount), 0.0) FROM expenses WHERE deleted_at IS NULL")
+            .fetch_one(&state.db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let chat_messages: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM chat_messages")
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(InstanceStats {
+        users,
+        admins,
+        disabled_users,
+        verified_users,
+        signups_last_7_days,
+        active_sessions,
+        tasks,
+        completed_tasks,
+        expenses,
+        total_spending,
+        chat_messages,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestApp;
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    async fn roles(app: &TestApp) -> Vec<(String, String, bool)> {
+        sqlx::query_as("SELECT email, role, disabled_at IS NOT NULL FROM users ORDER BY email")
+            .fetch_all(&app.state.db)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_refused_update_changes_nothing() {
+        let app = TestApp::new().await;
+        let ann = app.sign_up("ann@example.com").await;
+        app.sign_up("bob@example.com").await;
+        sqlx::query("UPDATE users SET role = 'admin'").execute(&app.state.db).await.unwrap();
+        let ann_id: String = sqlx::query_scalar("SELECT id FROM users WHERE email = 'ann@example.com'")
+            .fetch_one(&app.state.db)
+            .await
+            .unwrap();
+
+        // The role change alone would be fine, but admins can't disable themselves
+        let path = format!("/api/admin/users/{}", ann_id);
+        let response = app.patch(&path, &ann, json!({ "role": "user", "disabled": true })).await;
+        assert_eq!(response.status, StatusCode::CONFLICT);
+        let expected = [
+            ("ann@example.com".to_string(), "admin".to_string(), false),
+            ("bob@example.com".to_string(), "admin".to_string(), false),
+        ];
+        assert_eq!(roles(&app).await, expected);
+
+        let response = app.patch(&path, &ann, json!({ "role": "user" })).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(response.body["role"], "user");
+    }
+
+    #[tokio::test]
+    async fn keeps_one_active_admin() {
+        let app = TestApp::new().await;
+        let ann = app.sign_up("ann@example.com").await;
+        app.sign_up("bob@example.com").await;
+        sqlx::query("UPDATE users SET role = 'admin' WHERE email = 'ann@example.com'")
+            .execute(&app.state.db)
+            .await
+            .unwrap();
+        let ann_id: String = sqlx::query_scalar("SELECT id FROM users WHERE role = 'admin'")
+            .fetch_one(&app.state.db)
+            .await
+            .unwrap();
+
+        let response = app.patch(&format!("/api/admin/users/{}", ann_id), &ann, json!({ "role": "user" })).await;
+        assert_eq!(response.status, StatusCode::CONFLICT);
+        assert_eq!(roles(&app).await[0].1, "admin");
+    }
+}
@@ -0,0 +1,245 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use sqlx::{Connection, SqliteConnection};
+use std::collections::HashSet;
+
+use super::{apply_task_update, fetch_task, projects, tags, trash, workflow, AppState};
+use crate::auth::AuthenticatedUser;
+use crate::dates;
+use crate::models::*;
+
+const MAX_BATCH_SIZE: usize = 200;
+
+/// Rejects actions that would fail for every task alike, before any of
+/// them is touched.
+async fn check_action(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    action: &mut BatchAction,
+) -> Result<(), (StatusCode, String)> {
+    match action {
+        BatchAction::Complete { .. } | BatchAction::Delete => {}
+        BatchAction::Retag { add, remove } => {
+            if add.is_empty() && remove.is_empty() {
+                return Err((StatusCode::BAD_REQUEST, "Give tags to add, remove or both".to_string()));
+            }
+            *add = tags::normalize_tags(add)?;
+            *remove = tags::normalize_tags(remove)?;
+        }
+        BatchAction::Move { project_id, state_id } => {
+            if project_id.is_none() && state_id.is_none() {
+                return Err((StatusCode::BAD_REQUEST, "Give project_id, state_id or both".to_string()));
+            }
+            if let Some(Some(project_id)) = project_id {
+                projects::check_project(&mut *conn, user_id, project_id).await?;
+            }
+            if let Some(state_id) = state_id {
+                workflow::ensure_workflow(conn, user_id).await?;
+                workflow::fetch_state(conn, user_id, state_id).await?;
+            }
+        }
+        BatchAction::Reschedule { start_at, due_at, shift_days } => match shift_days {
+            Some(_) if start_at.is_some() || due_at.is_some() => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "shift_days cannot be combined with start_at or due_at".to_string(),
+                ));
+            }
+            None if start_at.is_none() && due_at.is_none() => {
+                return Err((StatusCode::BAD_REQUEST, "Give start_at, due_at or shift_days".to_string()));
+            }
+            _ => {}
+        },
+    }
+    Ok(())
+}
+
+/// The same wall-clock time `days` later in the task's zone, as a local
+/// time `apply_task_update` reads back in that zone.
+fn shift_date(instant: Option<DateTime<Utc>>, tz: Tz, days: i64) -> Option<String> {
+    instant.map(|instant| {
+        let local = instant.with_timezone(&tz).naive_local() + chrono::Duration::days(days);
+        local.format("%Y-%m-%dT%H:%M:%S").to_string()
+    })
+}
+
+/// Applies the action to one task. Returns the tasks moved to the trash,
+/// if it deleted any.
+async fn apply_action(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    id: &str,
+    action: &BatchAction,
+    trashed: &[String],
+) -> Result<Vec<String>, (StatusCode, String)> {
+    let update = match action {
+        BatchAction::Delete => {
+            // Already in the trash as a subtask of an earlier task of the batch
+            if trashed.iter().any(|trashed_id| trashed_id == id) {
+                return Ok(Vec::new());
+            }
+            let task_ids = trash::trash_task(conn, user_id, id).await?;
+            if task_ids.is_empty() {
+                return Err((StatusCode::NOT_FOUND, "Task not found".to_string()));
+            }
+            return Ok(task_ids);
+        }
+        BatchAction::Complete { completed, complete_subtasks } => UpdateTaskRequest {
+            completed: Some(*completed),
+            complete_subtasks: *complete_subtasks,
+            ..Default::default()
+        },
+        BatchAction::Retag { add, remove } => {
+            let task = fetch_task(&mut *conn, user_id, id).await?;
+            let mut names: Vec<String> = task
+                .tags
+                .0
+                .into_iter()
+                .filter(|name| !remove.iter().any(|removed| removed.eq_ignore_ascii_case(name)))
+                .collect();
+            names.extend(add.iter().cloned());
+            UpdateTaskRequest {
+                tags: Some(names),
+                ..Default::default()
+            }
+        }
+        BatchAction::Move { project_id, state_id } => UpdateTaskRequest {
+            project_id: project_id.clone(),
+            state_id: state_id.clone(),
+            ..Default::default()
+        },
+        BatchAction::Reschedule { start_at, due_at, shift_days: None } => UpdateTaskRequest {
+            start_at: start_at.clone(),
+            due_at: due_at.clone(),
+            ..Default::default()
+        },
+        BatchAction::Reschedule { shift_days: Some(days), .. } => {
+            let task = fetch_task(&mut *conn, user_id, id).await?;
+            if task.start_at.is_none() && task.due_at.is_none() {
+                return Err((StatusCode::BAD_REQUEST, "The task has no dates to shift".to_string()));
+            }
+            let tz = match task.time_zone.as_deref() {
+                Some(name) => dates::parse_time_zone(name).map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+                None => Tz::UTC,
+            };
+            UpdateTaskRequest {
+                start_at: Some(shift_date(task.start_at, tz, *days)),
+                due_at: Some(shift_date(task.due_at, tz, *days)),
+                ..Default::default()
+            }
+        }
+    };
+    apply_task_update(conn, user_id, id, update).await?;
+    Ok(Vec::new())
+}
+
+/// Applies one action to many tasks in a single transaction. Each task gets
+/// its own savepoint, so one that fails is reported and left as it was while
+/// the others are saved.
+pub async fn batch_tasks(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(mut payload): Json<BatchTaskRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    if payload.ids.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Give at least one task id".to_string()));
+    }
+    if payload.ids.len() > MAX_BATCH_SIZE {
+        return Err((StatusCode::BAD_REQUEST, format!("Batches are limited to {} tasks", MAX_BATCH_SIZE)));
+    }
+    let mut seen = HashSet::new();
+    if let Some(id) = payload.ids.iter().find(|id| !seen.insert(id.as_str())) {
+        return Err((StatusCode::BAD_REQUEST, format!("Task '{}' is listed twice", id)));
+    }
+
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    check_action(&mut tx, &user.id, &mut payload.action).await?;
+
+    let mut trashed = Vec::new();
+    let mut results = Vec::with_capacity(payload.ids.len());
+    for id in payload.ids {
+        let mut item = Connection::begin(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let outcome = match apply_action(&mut item, &user.id, &id, &payload.action, &trashed).await {
+            Ok(task_ids) if matches!(payload.action, BatchAction::Delete) => Ok((task_ids, None)),
+            Ok(task_ids) => fetch_task(&mut *item, &user.id, &id).await.map(|task| (task_ids, Some(task))),
+            Err(error) => Err(error),
+        };
+        match outcome {
+            Ok((task_ids, task)) => {
+                item.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                trashed.extend(task_ids);
+                results.push(BatchItemResult {
+                    id,
+                    status: StatusCode::OK.as_u16(),
+                    error: None,
+                    task,
+                });
+            }
+            Err((status, error)) => {
+                item.rollback().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                results.push(BatchItemResult {
+                    id,
+                    status: status.as_u16(),
+                    error: Some(error),
+                    task: None,
+                });
+            }
+        }
+    }
+    if !trashed.is_empty() {
+        trash::log_operation(&mut tx, &user.id, OperationKind::DeleteTasks { task_ids: trashed }).await?;
+    }
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let succeeded = results.iter().filter(|result| result.error.is_none()).count();
+    Ok(Json(BatchTaskResponse {
+        succeeded,
+        failed: results.len() - succeeded,
+        results,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestApp;
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn reports_each_task_of_a_mixed_batch() {
+        let app = TestApp::new().await;
+        let ann = app.sign_up("ann@example.com").await;
+        let bob = app.sign_up("bob@example.com").await;
+        let report = app.create_task(&ann, json!({ "title": "Report" })).await;
+        let slides = app.create_task(&ann, json!({ "title": "Slides" })).await;
+        let foreign = app.create_task(&bob, json!({ "title": "Bob's task" })).await;
+
+        let ids = json!([report, foreign, "missing", slides]);
+        let batch = app.post("/api/tasks/batch", &ann, json!({ "ids": ids, "action": "complete" })).await;
+        assert_eq!(batch.status, StatusCode::OK, "{}", batch.body);
+        assert_eq!(batch.body["succeeded"], 2);
+        assert_eq!(batch.body["failed"], 2);
+        let results = batch.body["results"].as_array().unwrap();
+        let statuses: Vec<_> = results.iter().map(|result| (result["id"].clone(), result["status"].clone())).collect();
+        assert_eq!(
+            statuses,
+            [
+                (json!(report), json!(200)),
+                (json!(foreign), json!(404)),
+                (json!("missing"), json!(404)),
+                (json!(slides), json!(200)),
+            ]
+        );
+        assert_eq!(results[0]["task"]["completed"], true);
+        assert!(results[1]["task"].is_null() && results[1]["error"].is_string());
+
+        let completed: Vec<(String, bool)> = sqlx::query_as("SELECT id, completed FROM tasks ORDER BY title")
+            .fetch_all(&app.state.db)
+            .await
+            .unwrap();
+        assert_eq!(completed, [(foreign, false), (report, true), (slides, true)]);
+    }
+}
@@ -0,0 +1,177 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use sqlx::Sqlite;
+use uuid::Uuid;
+
+use super::{fetch_task, AppState};
+use crate::auth::AuthenticatedUser;
+use crate::markdown;
+use crate::models::*;
+
+const MAX_COMMENT_LENGTH: usize = 5_000;
+/// Also the most a preview renders.
+const MAX_DESCRIPTION_LENGTH: usize = 20_000;
+
+const COMMENT_COLUMNS: &str = "task_comments.id, task_comments.task_id, task_comments.user_id AS author_id,
+    users.email AS author_email, task_comments.body, task_comments.edited_at, task_comments.created_at";
+
+pub fn check_description(description: Option<&str>) -> Result<(), (StatusCode, String)> {
+    match description {
+        Some(text) if text.chars().count() > MAX_DESCRIPTION_LENGTH => Err((
+            StatusCode::BAD_REQUEST,
+            format!("Descriptions are limited to {} characters", MAX_DESCRIPTION_LENGTH),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn normalize_body(body: &str) -> Result<String, (StatusCode, String)> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Comments must not be empty".to_string()));
+    }
+    if body.chars().count() > MAX_COMMENT_LENGTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Comments are limited to {} characters", MAX_COMMENT_LENGTH),
+        ));
+    }
+    Ok(body.to_string())
+}
+
+fn with_html(mut comment: TaskComment) -> TaskComment {
+    comment.html = markdown::render(&comment.body);
+    comment
+}
+
+// --- Comment Handlers ---
+
+/// Oldest first, as a conversation reads.
+pub async fn list_comments(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    fetch_task(&state.db, &user.id, &id).await?;
+
+    let comments = sqlx::query_as::<Sqlite, TaskComment>(&format!(
+        "SELECT {} FROM task_comments JOIN users ON users.id = task_comments.user_id
+         WHERE task_comments.task_id = ? ORDER BY task_comments.created_at, task_comments.rowid",
+        COMMENT_COLUMNS
+    ))
+    .bind(&id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(comments.into_iter().map(with_html).collect::<Vec<_>>()))
+}
+
+pub async fn add_comment(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+    Json(payload): Json<CommentRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    fetch_task(&state.db, &user.id, &id).await?;
+    let body = normalize_body(&payload.body)?;
+    let comment_id = Uuid::new_v4().to_string();
+
+    sqlx::query("INSERT INTO task_comments (id, task_id, user_id, body) VALUES (?, ?, ?, ?)")
+        .bind(&comment_id)
+        .bind(&id)
+        .bind(&user.id)
+        .bind(&body)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let comment = fetch_comment(&state, &user.id, &id, &comment_id).await?;
+    Ok((StatusCode::CREATED, Json(comment)))
+}
+
+/// Only the author can edit a comment.
+pub async fn update_comment(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path((id, comment_id)): Path<(String, String)>,
+    Json(payload): Json<CommentRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let body = normalize_body(&payload.body)?;
+
+    let result = sqlx::query(
+        "UPDATE task_comments SET body = ?, edited_at = ? WHERE id = ? AND task_id = ? AND user_id = ?",
+    )
+    .bind(&body)
+    .bind(chrono::Utc::now())
+    .bind(&comment_id)
+    .bind(&id)
+    .bind(&user.id)
+    .execute(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "Comment not found".to_string()));
+    }
+
+    Ok(Json(fetch_comment(&state, &user.id, &id, &comment_id).await?))
+}
+
+pub async fn delete_comment(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path((id, comment_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let result = sqlx::query("DELETE FROM task_comments WHERE id = ? AND task_id = ? AND user_id = ?")
+        .bind(&comment_id)
+        .bind(&id)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "Comment not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Renders markdown the way descriptions and comments are shown, so an
+/// editor can preview text before saving it.
+pub async fn preview_markdown(
+    AuthenticatedUser(_user): AuthenticatedUser,
+    Json(payload): Json<MarkdownPreviewRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    check_description(Some(&payload.markdown))?;
+    Ok(Json(MarkdownPreview {
+        html: markdown::render(&payload.markdown),
+    }))
+}
+
+async fn fetch_comment(
+    state: &AppState,
+    user_id: &str,
+    task_id: &str,
+    id: &str,
+) -> Result<TaskComment, (StatusCode, String)> {
+    sqlx::query_as::<Sqlite, TaskComment>(&format!(
+        "SELECT {} FROM task_comments JOIN users ON users.id = task_comments.user_id
+         JOIN tasks ON tasks.id = task_comments.task_id AND tasks.deleted_at IS NULL
+         WHERE task_comments.id = ? AND task_comments.task_id = ? AND tasks.user_id = ?",
+        COMMENT_COLUMNS
+    ))
+    .bind(id)
+    .bind(task_id)
+    .bind(user_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map(with_html)
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "Comment not found".to_string()))
+}
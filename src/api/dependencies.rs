@@ -0,0 +1,265 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use sqlx::Sqlite;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use super::{fetch_task, AppState, TASK_COLUMNS};
+use crate::auth::AuthenticatedUser;
+use crate::models::*;
+
+const DEFAULT_NEXT_TASKS: usize = 20;
+
+/// Order among tasks that are free to go next: most urgent, then soonest
+/// due, then oldest.
+type Urgency = (Priority, Reverse<DateTime<Utc>>, Reverse<DateTime<Utc>>, Reverse<usize>);
+
+fn urgency(task: &Task, index: usize) -> Urgency {
+    (
+        task.priority,
+        Reverse(task.due_at.unwrap_or(DateTime::<Utc>::MAX_UTC)),
+        Reverse(task.created_at),
+        Reverse(index),
+    )
+}
+
+// --- Dependency Handlers ---
+
+pub async fn list_dependencies(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    fetch_task(&state.db, &user.id, &id).await?;
+
+    let blocked_by = sqlx::query_as::<Sqlite, Task>(&format!(
+        "SELECT {} FROM tasks JOIN task_dependencies ON task_dependencies.blocked_by_id = tasks.id
+         WHERE task_dependencies.task_id = ? AND tasks.user_id = ? AND tasks.deleted_at IS NULL ORDER BY tasks.created_at",
+        TASK_COLUMNS
+    ))
+    .bind(&id)
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let blocking = sqlx::query_as::<Sqlite, Task>(&format!(
+        "SELECT {} FROM tasks JOIN task_dependencies ON task_dependencies.task_id = tasks.id
+         WHERE task_dependencies.blocked_by_id = ? AND tasks.user_id = ? AND tasks.deleted_at IS NULL
+         ORDER BY tasks.created_at",
+        TASK_COLUMNS
+    ))
+    .bind(&id)
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(TaskDependencies { blocked_by, blocking }))
+}
+
+/// Marks the task as blocked by another of the user's tasks. Links that
+/// would close a cycle are rejected, since no task in it could ever start.
+pub async fn add_dependency(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+    Json(payload): Json<DependencyRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    fetch_task(&state.db, &user.id, &id).await?;
+    fetch_task(&state.db, &user.id, &payload.blocked_by)
+        .await
+        .map_err(|(status, _)| (status, "Blocking task not found".to_string()))?;
+
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Does the blocker already wait on this task, directly or through others?
+    let creates_cycle: bool = sqlx::query_scalar(
+        "WITH RECURSIVE upstream(id) AS (
+             SELECT ?
+             UNION
+             SELECT task_dependencies.blocked_by_id FROM task_dependencies
+             JOIN upstream ON task_dependencies.task_id = upstream.id
+         )
+         SELECT COUNT(*) > 0 FROM upstream WHERE id = ?",
+    )
+    .bind(&payload.blocked_by)
+    .bind(&id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if creates_cycle {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "That link would make the tasks wait on each other in a cycle".to_string(),
+        ));
+    }
+
+    sqlx::query("INSERT OR IGNORE INTO task_dependencies (task_id, blocked_by_id) VALUES (?, ?)")
+        .bind(&id)
+        .bind(&payload.blocked_by)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(fetch_task(&state.db, &user.id, &id).await?)))
+}
+
+pub async fn remove_dependency(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path((id, blocker_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let result = sqlx::query(
+        "DELETE FROM task_dependencies WHERE task_id = ? AND blocked_by_id = ?
+         AND task_id IN (SELECT id FROM tasks WHERE user_id = ?)",
+    )
+    .bind(&id)
+    .bind(&blocker_id)
+    .bind(&user.id)
+    .execute(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "Dependency not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Open tasks in an order that puts every blocker before the tasks it
+/// blocks, most urgent first wherever the order is free. The first ones can
+/// be started now; `blocked` marks those still waiting.
+pub async fn next_tasks(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<NextTasksQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let tasks = sqlx::query_as::<Sqlite, Task>(&format!(
+        "SELECT {} FROM tasks WHERE user_id = ? AND completed = 0 AND deleted_at IS NULL",
+        TASK_COLUMNS
+    ))
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let edges: Vec<(String, String)> = sqlx::query_as(
+        "SELECT task_dependencies.task_id, task_dependencies.blocked_by_id FROM task_dependencies
+         JOIN tasks AS task ON task.id = task_dependencies.task_id
+         JOIN tasks AS blocker ON blocker.id = task_dependencies.blocked_by_id
+         WHERE task.user_id = ? AND task.completed = 0 AND blocker.completed = 0
+         AND task.deleted_at IS NULL AND blocker.deleted_at IS NULL",
+    )
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_NEXT_TASKS);
+    Ok(Json(topological_order(tasks, &edges, limit)))
+}
+
+/// Kahn's algorithm, taking the most urgent ready task at each step.
+fn topological_order(tasks: Vec<Task>, edges: &[(String, String)], limit: usize) -> Vec<Task> {
+    let index: HashMap<&str, usize> = tasks.iter().enumerate().map(|(i, task)| (task.id.as_str(), i)).collect();
+    let mut waiting_on = vec![0usize; tasks.len()];
+    let mut unblocks: Vec<Vec<usize>> = vec![Vec::new(); tasks.len()];
+    for (task_id, blocker_id) in edges {
+        if let (Some(&task), Some(&blocker)) = (index.get(task_id.as_str()), index.get(blocker_id.as_str())) {
+            waiting_on[task] += 1;
+            unblocks[blocker].push(task);
+        }
+    }
+
+    let mut ready: BinaryHeap<Urgency> = (0..tasks.len())
+        .filter(|&i| waiting_on[i] == 0)
+        .map(|i| urgency(&tasks[i], i))
+        .collect();
+
+    let mut order = Vec::with_capacity(limit.min(tasks.len()));
+    while order.len() < limit {
+        let Some((.., Reverse(i))) = ready.pop() else { break };
+        order.push(i);
+        for &next in &unblocks[i] {
+            waiting_on[next] -= 1;
+            if waiting_on[next] == 0 {
+                ready.push(urgency(&tasks[next], next));
+            }
+        }
+    }
+
+    let mut tasks: Vec<Option<Task>> = tasks.into_iter().map(Some).collect();
+    order.into_iter().filter_map(|i| tasks[i].take()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestApp;
+    use axum::http::StatusCode;
+    use serde_json::{json, Value};
+
+    fn titles(tasks: &Value) -> Vec<&str> {
+        tasks.as_array().unwrap().iter().map(|task| task["title"].as_str().unwrap()).collect()
+    }
+
+    async fn block(app: &TestApp, token: &str, id: &str, blocked_by: &str) -> StatusCode {
+        let path = format!("/api/tasks/{}/dependencies", id);
+        app.post(&path, token, json!({ "blocked_by": blocked_by })).await.status
+    }
+
+    #[tokio::test]
+    async fn rejects_cycles() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        let a = app.create_task(&token, json!({ "title": "A" })).await;
+        let b = app.create_task(&token, json!({ "title": "B" })).await;
+        let c = app.create_task(&token, json!({ "title": "C" })).await;
+
+        assert_eq!(block(&app, &token, &a, &b).await, StatusCode::CREATED);
+        assert_eq!(block(&app, &token, &b, &a).await, StatusCode::BAD_REQUEST);
+        assert_eq!(block(&app, &token, &a, &a).await, StatusCode::BAD_REQUEST);
+        // Through a longer chain too: A waits on B, which waits on C
+        assert_eq!(block(&app, &token, &b, &c).await, StatusCode::CREATED);
+        assert_eq!(block(&app, &token, &c, &a).await, StatusCode::BAD_REQUEST);
+
+        let edges: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM task_dependencies")
+            .fetch_one(&app.state.db)
+            .await
+            .unwrap();
+        assert_eq!(edges, 2);
+    }
+
+    #[tokio::test]
+    async fn puts_blockers_first_and_urgent_tasks_early() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        // A diamond: D waits on B and C, which both wait on A
+        let a = app.create_task(&token, json!({ "title": "A", "priority": "low" })).await;
+        let b = app.create_task(&token, json!({ "title": "B", "priority": "low" })).await;
+        let c = app.create_task(&token, json!({ "title": "C", "priority": "medium" })).await;
+        let d = app.create_task(&token, json!({ "title": "D", "priority": "urgent" })).await;
+        app.create_task(&token, json!({ "title": "E", "priority": "high" })).await;
+        for (task, blocker) in [(&b, &a), (&c, &a), (&d, &b), (&d, &c)] {
+            assert_eq!(block(&app, &token, task, blocker).await, StatusCode::CREATED);
+        }
+
+        let next = app.get("/api/tasks/next", &token).await;
+        assert_eq!(next.status, StatusCode::OK, "{}", next.body);
+        assert_eq!(titles(&next.body), ["E", "A", "C", "B", "D"]);
+
+        // Completing a blocker takes it out of the way
+        app.patch(&format!("/api/tasks/{}", a), &token, json!({ "completed": true })).await;
+        let next = app.get("/api/tasks/next?limit=2", &token).await;
+        assert_eq!(titles(&next.body), ["E", "C"]);
+    }
+}
@@ -1,21 +1,68 @@
+mod account;
+mod admin;
+mod batch;
+mod comments;
+mod dependencies;
+mod ordering;
+mod projects;
+mod quick_add;
+mod recurrence;
+mod reminders;
+mod search;
+mod sessions;
+mod sso;
+mod subtasks;
+mod tags;
+mod tokens;
+mod trash;
+mod two_factor;
+mod workflow;
+
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{delete, get, patch, post},
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
-use sqlx::{Sqlite, SqlitePool};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::{QueryBuilder, Sqlite};
+use std::net::SocketAddr;
+use std::sync::Arc;
 use uuid::Uuid;
 
-use crate::auth::{create_jwt, hash_password, verify_password, AuthenticatedUser};
+use crate::auth::{
+    access_token_ttl, create_jwt, create_session, hash_password, is_bootstrap_admin, record_security_event,
+    require_scope, revoke_all_sessions, revoke_session, rotate_refresh_token, verify_password, AuthSession,
+    AuthenticatedUser, ScopeGuard, SessionMetadata, ROLE_ADMIN, ROLE_USER,
+};
+use crate::dates;
 use crate::groq::GroqClient;
+use crate::keys;
+use crate::markdown;
+use crate::mailer::Mailer;
+use crate::oidc::OidcClient;
 use crate::models::*;
+use crate::search_index::{self, SearchKind};
+use crate::throttle;
+
+pub use account::app_url;
+pub use ordering::backfill_positions;
+pub use trash::spawn_trash_purge;
+pub use workflow::seed_workflows;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: sqlx::SqlitePool,
     pub groq: Option<GroqClient>,
+    pub oidc: Option<OidcClient>,
+    pub mailer: Arc<dyn Mailer>,
 }
 
 impl axum::extract::FromRef<AppState> for sqlx::SqlitePool {
@@ -25,24 +72,137 @@ impl axum::extract::FromRef<AppState> for sqlx::SqlitePool {
 }
 
 pub fn create_router(state: AppState) -> Router {
-    Router::new()
-        // Auth routes
-        .route("/api/auth/register", post(register))
-        .route("/api/auth/login", post(login))
-        .route("/api/auth/me", get(get_me))
-        
-        // Task routes
+    // Routes reachable with personal access tokens, guarded by per-route scopes
+    let task_routes = Router::new()
         .route("/api/tasks", get(list_tasks).post(create_task))
         .route("/api/tasks/:id", patch(update_task).delete(delete_task))
-        
-        // Expense routes
+        .route("/api/tasks/next", get(dependencies::next_tasks))
+        .route("/api/tasks/tree", get(subtasks::list_task_trees))
+        .route("/api/tasks/preview", post(comments::preview_markdown))
+        .route("/api/tasks/parse", post(quick_add::parse_task))
+        .route("/api/tasks/batch", post(batch::batch_tasks))
+        .route("/api/tasks/trash", get(trash::list_task_trash))
+        .route("/api/tasks/:id/restore", post(trash::restore_task))
+        .route("/api/tasks/:id/tree", get(subtasks::get_task_tree))
+        .route(
+            "/api/tasks/:id/recurrence",
+            get(recurrence::get_recurrence).put(recurrence::set_recurrence).delete(recurrence::end_recurrence),
+        )
+        .route("/api/tasks/:id/skip", post(recurrence::skip_occurrence))
+        .route("/api/tasks/:id/move", post(ordering::move_task))
+        .route("/api/tasks/:id/comments", get(comments::list_comments).post(comments::add_comment))
+        .route(
+            "/api/tasks/:id/comments/:comment_id",
+            patch(comments::update_comment).delete(comments::delete_comment),
+        )
+        .route(
+            "/api/tasks/:id/dependencies",
+            get(dependencies::list_dependencies).post(dependencies::add_dependency),
+        )
+        .route("/api/tasks/:id/dependencies/:blocker_id", delete(dependencies::remove_dependency))
+        .route("/api/projects", get(projects::list_projects).post(projects::create_project))
+        .route("/api/projects/order", put(projects::reorder_projects))
+        .route(
+            "/api/projects/:id",
+            get(projects::get_project).patch(projects::update_project).delete(projects::delete_project),
+        )
+        .route("/api/projects/:id/tasks", get(projects::list_project_tasks))
+        .route("/api/projects/:id/stats", get(projects::project_stats))
+        .route("/api/tasks/:id/timer", post(start_timer))
+        .route("/api/timer", get(get_timer))
+        .route("/api/timer/stop", post(stop_timer))
+        .route("/api/time-entries", get(list_time_entries).post(create_time_entry))
+        .route("/api/time-entries/report", get(time_report))
+        .route("/api/time-entries/:id", patch(update_time_entry).delete(delete_time_entry))
+        .route("/api/workflow", get(workflow::get_workflow))
+        .route("/api/workflow/states", post(workflow::create_state))
+        .route("/api/workflow/states/order", put(workflow::reorder_states))
+        .route("/api/workflow/states/:id", patch(workflow::update_state).delete(workflow::delete_state))
+        .route("/api/workflow/transitions", put(workflow::set_transitions))
+        .route("/api/board", get(workflow::get_board))
+        .route("/api/tags", get(tags::list_tags).post(tags::create_tag))
+        .route("/api/tags/:id", patch(tags::rename_tag).delete(tags::delete_tag))
+        .route("/api/reminders", get(reminders::list_reminders).post(reminders::create_reminder))
+        .route(
+            "/api/reminders/:id",
+            get(reminders::get_reminder).patch(reminders::update_reminder).delete(reminders::delete_reminder),
+        )
+        .route("/api/notifications", get(reminders::list_notifications))
+        .route("/api/notifications/read-all", post(reminders::mark_all_notifications_read))
+        .route("/api/notifications/:id/read", post(reminders::mark_notification_read))
+        .route_layer(middleware::from_fn_with_state(
+            ScopeGuard::new(&state.db, "tasks:read", "tasks:write"),
+            require_scope,
+        ));
+
+    let expense_routes = Router::new()
         .route("/api/expenses", get(list_expenses).post(create_expense))
         .route("/api/expenses/:id", delete(delete_expense))
         .route("/api/expenses/summary", get(get_expense_summary))
-        
-        // AI routes
+        .route("/api/expenses/trash", get(trash::list_expense_trash))
+        .route("/api/expenses/:id/restore", post(trash::restore_expense))
+        .route_layer(middleware::from_fn_with_state(
+            ScopeGuard::new(&state.db, "expenses:read", "expenses:write"),
+            require_scope,
+        ));
+
+    let ai_routes = Router::new()
         .route("/api/ai/suggest", post(ai_suggest))
         .route("/api/ai/chat", post(ai_chat_handler))
+        .route_layer(middleware::from_fn_with_state(
+            ScopeGuard::new(&state.db, "ai:use", "ai:use"),
+            require_scope,
+        ));
+
+    Router::new()
+        // Auth routes
+        .route("/.well-known/jwks.json", get(jwks))
+        .route("/api/auth/register", post(register))
+        .route("/api/auth/login", post(login))
+        .route("/api/auth/refresh", post(refresh))
+        .route("/api/auth/sso", get(sso::sso_config))
+        .route("/api/auth/sso/start", post(sso::start_sso))
+        .route("/api/auth/sso/callback", post(sso::finish_sso))
+        .route("/api/auth/logout", post(logout))
+        .route("/api/auth/logout-all", post(logout_all))
+        .route("/api/auth/sessions", get(sessions::list_sessions))
+        .route("/api/auth/sessions/:id", delete(sessions::revoke_session_handler))
+        .route("/api/auth/me", get(get_me))
+        .route("/api/auth/security-events", get(account::list_security_events))
+        .route("/api/auth/verify-email", post(account::verify_email))
+        .route("/api/auth/verify-email/resend", post(account::resend_verification))
+        .route("/api/auth/password/forgot", post(account::forgot_password))
+        .route("/api/auth/password/reset", post(account::reset_password))
+        .route("/api/auth/password", put(account::change_password))
+        .route("/api/auth/email", put(account::request_email_change))
+        .route("/api/auth/email/confirm", post(account::confirm_email_change))
+        .route("/api/auth/account", delete(account::delete_account))
+        .route("/api/auth/2fa/setup", post(two_factor::setup))
+        .route("/api/auth/2fa/enable", post(two_factor::enable))
+        .route("/api/auth/2fa/disable", post(two_factor::disable))
+        .route("/api/auth/2fa/recovery-codes", post(two_factor::regenerate_recovery_codes))
+        .route("/api/auth/2fa/verify", post(two_factor::verify))
+
+        // Search and undo, across data guarded by different scopes
+        .route("/api/search", get(search::search))
+        .route("/api/undo/create-delete", post(trash::undo_create_delete))
+
+        // Personal access token routes
+        .route("/api/tokens", get(tokens::list_api_tokens).post(tokens::create_api_token))
+        .route("/api/tokens/:id", delete(tokens::revoke_api_token))
+
+        // Admin routes
+        .route("/api/admin/users", get(admin::list_users))
+        .route(
+            "/api/admin/users/:id",
+            get(admin::get_user).patch(admin::update_user).delete(admin::delete_user_handler),
+        )
+        .route("/api/admin/users/:id/reset-password", post(admin::reset_user_password))
+        .route("/api/admin/stats", get(admin::instance_stats))
+
+        .merge(task_routes)
+        .merge(expense_routes)
+        .merge(ai_routes)
         .with_state(state)
 }
 
@@ -52,13 +212,16 @@ async fn register(
     State(state): State<AppState>,
     Json(payload): Json<RegisterRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    account::check_password(&payload.password)?;
     let id = Uuid::new_v4().to_string();
     let hashed = hash_password(&payload.password);
+    let role = if is_bootstrap_admin(&payload.email) { ROLE_ADMIN } else { ROLE_USER };
 
-    sqlx::query("INSERT INTO users (id, email, password_hash) VALUES (?, ?, ?)")
+    sqlx::query("INSERT INTO users (id, email, password_hash, role) VALUES (?, ?, ?, ?)")
         .bind(&id)
         .bind(&payload.email)
         .bind(&hashed)
+        .bind(role)
         .execute(&state.db)
         .await
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("User registration failed: {}", e)))?;
@@ -66,49 +229,461 @@ async fn register(
     let user = User {
         id,
         email: payload.email,
-        password_hash: "".to_string(), // Don't return hash
+        password_hash: None, // Don't return hash
+        totp_secret: None,
+        totp_enabled: false,
+        totp_last_step: None,
+        email_verified_at: None,
+        role: role.to_string(),
+        disabled_at: None,
+        deletion_scheduled_at: None,
         created_at: chrono::Utc::now(),
     };
 
+    if let Err(e) = account::send_verification_email(&state, &user).await {
+        tracing::error!("Failed to send verification email: {}", e);
+    }
+
     Ok((StatusCode::CREATED, Json(user)))
 }
 
 async fn login(
     State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<LoginRequest>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
+    let ip = throttle::client_ip(&addr, &headers);
+
+    if let Some(retry_after) = throttle::check(&state.db, &payload.email, &ip)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+    {
+        return Ok(too_many_attempts(retry_after));
+    }
+
     let user = sqlx::query_as::<Sqlite, User>("SELECT * FROM users WHERE email = ?")
         .bind(&payload.email)
-        .fetch_one(&state.db)
+        .fetch_optional(&state.db)
         .await
-        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid email or password".to_string()))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let user = match user {
+        Some(user) if user.password_hash.as_deref().is_some_and(|hash| verify_password(&payload.password, hash)) => user,
+        _ => {
+            throttle::record_attempt(&state.db, &payload.email, &ip, false)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            return Err((StatusCode::UNAUTHORIZED, "Invalid email or password".to_string()));
+        }
+    };
+
+    if user.disabled_at.is_some() {
+        return Err((StatusCode::FORBIDDEN, "This account has been disabled".to_string()));
+    }
 
-    if !verify_password(&payload.password, &user.password_hash) {
-        return Err((StatusCode::UNAUTHORIZED, "Invalid email or password".to_string()));
+    // The password alone is not enough; finish the login at /api/auth/2fa/verify
+    if user.totp_enabled {
+        let challenge = two_factor::start_challenge(&state.db, &user.id).await?;
+        return Ok(Json(LoginResponse::TwoFactorRequired(challenge)).into_response());
     }
 
-    let token = create_jwt(&user.id).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    throttle::record_attempt(&state.db, &payload.email, &ip, true)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let user = cancel_scheduled_deletion(&state.db, user, &ip).await?;
+
+    let (session_id, refresh_token) = create_session(&state.db, &user.id, &SessionMetadata::new(&ip, &headers))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(LoginResponse::Authenticated(Box::new(auth_response(user, &session_id, refresh_token)?))).into_response())
+}
+
+/// Logging in during the deletion grace period keeps the account.
+async fn cancel_scheduled_deletion(
+    db: &sqlx::SqlitePool,
+    mut user: User,
+    ip: &str,
+) -> Result<User, (StatusCode, String)> {
+    if user.deletion_scheduled_at.is_none() {
+        return Ok(user);
+    }
+
+    sqlx::query("UPDATE users SET deletion_scheduled_at = NULL WHERE id = ?")
+        .bind(&user.id)
+        .execute(db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    record_security_event(db, &user.id, "account_deletion_cancelled", Some(ip), "Logged in during the grace period")
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    user.deletion_scheduled_at = None;
+    Ok(user)
+}
+
+/// 429 with a `Retry-After` header for throttled login attempts.
+fn too_many_attempts(retry_after: i64) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after.to_string())],
+        format!("Too many failed login attempts. Try again in {} seconds.", retry_after),
+    )
+        .into_response()
+}
+
+async fn refresh(
+    State(state): State<AppState>,
+    Json(payload): Json<RefreshRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let (user_id, session_id, refresh_token) = rotate_refresh_token(&state.db, &payload.refresh_token)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "Invalid or expired refresh token".to_string()))?;
+
+    let user = sqlx::query_as::<Sqlite, User>("SELECT * FROM users WHERE id = ? AND disabled_at IS NULL")
+        .bind(&user_id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "User not found".to_string()))?;
 
-    Ok(Json(AuthResponse { token, user }))
+    Ok(Json(auth_response(user, &session_id, refresh_token)?))
 }
 
-async fn get_me(AuthenticatedUser(user): AuthenticatedUser) -> impl IntoResponse {
-    Json(user)
+async fn logout(
+    State(state): State<AppState>,
+    session: AuthSession,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    revoke_session(&state.db, &session.user.id, &session.session_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn logout_all(
+    State(state): State<AppState>,
+    session: AuthSession,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let revoked = revoke_all_sessions(&state.db, &session.user.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(serde_json::json!({ "revoked_sessions": revoked })))
+}
+
+fn auth_response(user: User, session_id: &str, refresh_token: String) -> Result<AuthResponse, (StatusCode, String)> {
+    let token = create_jwt(&user.id, session_id).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(AuthResponse {
+        token,
+        refresh_token,
+        expires_in: access_token_ttl().num_seconds(),
+        user,
+    })
+}
+
+async fn get_me(session: AuthSession) -> impl IntoResponse {
+    Json(session.user)
+}
+
+/// Public keys other services can use to verify our access tokens.
+async fn jwks() -> impl IntoResponse {
+    (
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(keys::manager().jwks()),
+    )
 }
 
 // --- Task Handlers ---
 
+/// True while a task waits on an open task that blocks it.
+macro_rules! blocked_condition {
+    () => {
+        "EXISTS (SELECT 1 FROM task_dependencies JOIN tasks AS blocker ON blocker.id = task_dependencies.blocked_by_id
+         WHERE task_dependencies.task_id = tasks.id AND blocker.completed = 0 AND blocker.deleted_at IS NULL)"
+    };
+}
+
+/// Seconds a time entry covers, up to now while its timer runs.
+macro_rules! entry_seconds {
+    () => {
+        "CAST(ROUND((julianday(COALESCE(time_entries.ended_at, 'now')) - julianday(time_entries.started_at)) * 86400)
+             AS INTEGER)"
+    };
+}
+
+/// Task columns plus its tag names as a JSON array, for `Task::tags`, the
+/// counts behind `Task::progress`, the rule in `Task::recurrence`, the name in
+/// `Task::state`, `Task::tracked_seconds` and `Task::blocked`.
+const TASK_COLUMNS: &str = concat!(
+    "tasks.*, (SELECT json_group_array(tags.name ORDER BY tags.name) FROM task_tags
+     JOIN tags ON tags.id = task_tags.tag_id WHERE task_tags.task_id = tasks.id) AS tags,
+     (SELECT COUNT(*) FROM tasks AS sub WHERE sub.parent_id = tasks.id AND sub.deleted_at IS NULL) AS subtask_count,
+     (SELECT COUNT(*) FROM tasks AS sub WHERE sub.parent_id = tasks.id AND sub.deleted_at IS NULL AND sub.completed = 1)
+         AS completed_subtask_count,
+     (SELECT AVG(sub.completed = 1) FROM tasks AS sub WHERE sub.parent_id = tasks.id AND sub.deleted_at IS NULL)
+         AS progress,
+     (SELECT rrule FROM task_series WHERE task_series.id = tasks.series_id AND task_series.ended_at IS NULL) AS recurrence,
+     (SELECT name FROM workflow_states WHERE workflow_states.id = tasks.state_id) AS state,
+     (SELECT COALESCE(SUM(", entry_seconds!(), "), 0) FROM time_entries WHERE time_entries.task_id = tasks.id)
+         AS tracked_seconds, ",
+    blocked_condition!(),
+    " AS blocked"
+);
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+/// The SQL each sortable field orders by. Missing dates sort after all others.
+fn task_sort_key(field: &str) -> Option<&'static str> {
+    match field {
+        "created_at" => Some("tasks.created_at"),
+        "due_at" => Some("COALESCE(tasks.due_at, '9999')"),
+        "start_at" => Some("COALESCE(tasks.start_at, '9999')"),
+        "priority" => Some("tasks.priority"),
+        "title" => Some("lower(tasks.title)"),
+        "position" => Some("tasks.position"),
+        "completed" => Some("tasks.completed"),
+        _ => None,
+    }
+}
+
+/// Where the next page starts: the sort key (as JSON, so text and numbers
+/// keep their SQL type) and id of the last task on the previous page.
+#[derive(Serialize, Deserialize)]
+struct TaskCursor {
+    sort: String,
+    desc: bool,
+    key: String,
+    id: String,
+}
+
+/// Cursors are signed, so clients can only hand back ones the server made.
+impl TaskCursor {
+    fn encode(&self) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).expect("cursor serializes"));
+        let signature = URL_SAFE_NO_PAD.encode(Self::mac(&payload).finalize().into_bytes());
+        format!("{}.{}", payload, signature)
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        let (payload, signature) = cursor.split_once('.')?;
+        Self::mac(payload).verify_slice(&URL_SAFE_NO_PAD.decode(signature).ok()?).ok()?;
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()
+    }
+
+    fn mac(payload: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(keys::manager().link_secret()).expect("HMAC accepts any key length");
+        mac.update(format!("task-cursor:{}", payload).as_bytes());
+        mac
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct TaskPageRow {
+    #[sqlx(flatten)]
+    task: Task,
+    sort_key: String,
+}
+
+/// Lists tasks one page at a time; `X-Next-Cursor` is set while more follow.
 async fn list_tasks(
     State(state): State<AppState>,
     AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<TaskListQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let tasks = sqlx::query_as::<Sqlite, Task>("SELECT * FROM tasks WHERE user_id = ? ORDER BY created_at DESC")
-        .bind(user.id)
+    let tz = dates::parse_time_zone(query.tz.as_deref().unwrap_or("UTC")).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let now = chrono::Utc::now();
+
+    // Date views read best soonest-first, everything else newest-first
+    let date_view = matches!(query.due.as_deref(), Some("overdue" | "today" | "week"));
+    let sort = query.sort.as_deref().unwrap_or(if date_view { "due_at" } else { "created_at" });
+    let key = task_sort_key(sort).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Unknown sort field '{}', expected created_at, due_at, start_at, priority, title, position or completed", sort),
+        )
+    })?;
+    let desc = match query.order.as_deref() {
+        None => !date_view && matches!(sort, "created_at" | "priority"),
+        Some("asc") => false,
+        Some("desc") => true,
+        Some(other) => return Err((StatusCode::BAD_REQUEST, format!("Unknown order '{}', expected asc or desc", other))),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let mut builder = QueryBuilder::<Sqlite>::new(format!(
+        "SELECT {}, json_quote({}) AS sort_key FROM tasks WHERE deleted_at IS NULL AND user_id = ",
+        TASK_COLUMNS, key
+    ));
+    builder.push_bind(&user.id);
+
+    match query.due.as_deref() {
+        None => {}
+        Some("overdue") => {
+            builder.push(" AND completed = 0 AND due_at < ").push_bind(now);
+        }
+        Some(range @ ("today" | "week")) => {
+            let (start, end) = if range == "today" { dates::day_bounds(tz, now) } else { dates::week_bounds(tz, now) };
+            builder.push(" AND due_at >= ").push_bind(start);
+            builder.push(" AND due_at < ").push_bind(end);
+        }
+        Some("none") => {
+            builder.push(" AND due_at IS NULL AND start_at IS NULL");
+        }
+        Some(other) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unknown due filter '{}', expected overdue, today, week or none", other),
+            ));
+        }
+    }
+
+    if let Some(completed) = query.completed {
+        builder.push(" AND completed = ").push_bind(completed);
+    }
+
+    match query.project.as_deref() {
+        None => {}
+        Some("none") => {
+            builder.push(" AND project_id IS NULL");
+        }
+        Some(project_id) => {
+            builder.push(" AND project_id = ").push_bind(project_id.to_string());
+        }
+    }
+
+    if let Some(state_id) = query.state.as_deref() {
+        builder.push(" AND state_id = ").push_bind(state_id.to_string());
+    }
+
+    if let Some(blocked) = query.blocked {
+        builder.push(concat!(" AND ", blocked_condition!(), " = ")).push_bind(blocked);
+    }
+
+    match query.parent.as_deref() {
+        None => {}
+        Some("none") => {
+            builder.push(" AND parent_id IS NULL");
+        }
+        Some(parent_id) => {
+            builder.push(" AND parent_id = ").push_bind(parent_id.to_string());
+        }
+    }
+
+    if let Some(priorities) = query.priority.as_deref() {
+        let priorities = priorities
+            .split(',')
+            .map(|name| {
+                Priority::parse(name.trim()).ok_or_else(|| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("Unknown priority '{}', expected low, medium, high or urgent", name.trim()),
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        builder.push(" AND priority IN (");
+        let mut list = builder.separated(", ");
+        for priority in priorities {
+            list.push_bind(priority);
+        }
+        builder.push(")");
+    }
+
+    if let Some(tags) = query.tag.as_deref() {
+        let names: Vec<String> = tags.split(',').map(str::to_string).collect();
+        for name in tags::normalize_tags(&names)? {
+            builder.push(
+                " AND EXISTS (SELECT 1 FROM task_tags JOIN tags ON tags.id = task_tags.tag_id
+                 WHERE task_tags.task_id = tasks.id AND tags.name = ",
+            );
+            builder.push_bind(name).push(")");
+        }
+    }
+
+    if let Some(text) = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        let pattern = format!("%{}%", text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        builder.push(" AND title LIKE ").push_bind(pattern).push(" ESCAPE '\\'");
+    }
+
+    if let Some(cursor) = query.cursor.as_deref() {
+        let cursor = TaskCursor::decode(cursor)
+            .filter(|c| c.sort == sort && c.desc == desc)
+            .ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid cursor for this sort order".to_string()))?;
+        let op = if desc { "<" } else { ">" };
+        builder.push(format!(" AND ({} {} json_extract(", key, op)).push_bind(cursor.key.clone());
+        builder.push(format!(", '$') OR ({} = json_extract(", key)).push_bind(cursor.key);
+        builder.push(format!(", '$') AND tasks.id {} ", op)).push_bind(cursor.id).push("))");
+    }
+
+    let direction = if desc { "DESC" } else { "ASC" };
+    builder.push(format!(" ORDER BY {} {}, tasks.id {} LIMIT ", key, direction, direction));
+    builder.push_bind(limit + 1);
+
+    let mut rows = builder
+        .build_query_as::<TaskPageRow>()
         .fetch_all(&state.db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    Ok(Json(tasks))
+    let mut headers = HeaderMap::new();
+    if rows.len() as i64 > limit {
+        rows.truncate(limit as usize);
+        let last = rows.last().expect("page is not empty");
+        let cursor = TaskCursor {
+            sort: sort.to_string(),
+            desc,
+            key: last.sort_key.clone(),
+            id: last.task.id.clone(),
+        };
+        headers.insert(
+            "x-next-cursor",
+            header::HeaderValue::from_str(&cursor.encode()).expect("base64 is a valid header value"),
+        );
+    }
+
+    let tasks: Vec<Task> = rows.into_iter().map(|row| row.task).collect();
+    Ok((headers, Json(tasks)))
+}
+
+/// A task of the user that isn't in the trash.
+async fn fetch_task<'e>(
+    executor: impl sqlx::SqliteExecutor<'e>,
+    user_id: &str,
+    id: &str,
+) -> Result<Task, (StatusCode, String)> {
+    sqlx::query_as::<Sqlite, Task>(&format!(
+        "SELECT {} FROM tasks WHERE id = ? AND user_id = ? AND deleted_at IS NULL",
+        TASK_COLUMNS
+    ))
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "Task not found".to_string()))
+}
+
+/// Parses an optional task date, reading local times in the task's zone.
+fn parse_task_date(input: Option<&str>, tz: Tz) -> Result<Option<DateTime<Utc>>, (StatusCode, String)> {
+    input
+        .map(|value| dates::parse_datetime(value, tz))
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+fn check_task_dates(start_at: Option<DateTime<Utc>>, due_at: Option<DateTime<Utc>>) -> Result<(), (StatusCode, String)> {
+    match (start_at, due_at) {
+        (Some(start), Some(due)) if start > due => {
+            Err((StatusCode::BAD_REQUEST, "start_at must not be after due_at".to_string()))
+        }
+        _ => Ok(()),
+    }
 }
 
 async fn create_task(
@@ -117,21 +692,72 @@ async fn create_task(
     Json(payload): Json<CreateTaskRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     let id = Uuid::new_v4().to_string();
-    
-    sqlx::query("INSERT INTO tasks (id, user_id, title) VALUES (?, ?, ?)")
-        .bind(&id)
-        .bind(&user.id)
-        .bind(&payload.title)
-        .execute(&state.db)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let task = sqlx::query_as::<Sqlite, Task>("SELECT * FROM tasks WHERE id = ?")
-        .bind(&id)
-        .fetch_one(&state.db)
+    let tz = match payload.time_zone.as_deref() {
+        Some(name) => Some(dates::parse_time_zone(name).map_err(|e| (StatusCode::BAD_REQUEST, e))?),
+        None => None,
+    };
+    let zone = tz.unwrap_or(Tz::UTC);
+    let start_at = parse_task_date(payload.start_at.as_deref(), zone)?;
+    let due_at = parse_task_date(payload.due_at.as_deref(), zone)?;
+    check_task_dates(start_at, due_at)?;
+    comments::check_description(payload.description.as_deref())?;
+    let tag_names = tags::normalize_tags(payload.tags.as_deref().unwrap_or_default())?;
+
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if let Some(project_id) = payload.project_id.as_deref() {
+        projects::check_project(&mut *tx, &user.id, project_id).await?;
+    }
+    if let Some(parent_id) = payload.parent_id.as_deref() {
+        subtasks::check_parent(&mut tx, &user.id, None, parent_id).await?;
+    }
+    let initial_state = match payload.state_id.as_deref() {
+        Some(state_id) => {
+            workflow::ensure_workflow(&mut tx, &user.id).await?;
+            workflow::fetch_state(&mut tx, &user.id, state_id).await?
+        }
+        None => workflow::first_state(&mut tx, &user.id, StateCategory::Open).await?,
+    };
+    let position = ordering::next_position(&mut tx, &user.id).await?;
+
+    // Subtasks join their parent's project unless told otherwise
+    sqlx::query(
+        "INSERT INTO tasks (id, user_id, title, notes, description, state_id, completed, position, priority, start_at,
+                            due_at, time_zone, parent_id, project_id)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, COALESCE(?, (SELECT project_id FROM tasks WHERE id = ?)))",
+    )
+    .bind(&id)
+    .bind(&user.id)
+    .bind(&payload.title)
+    .bind(&payload.notes)
+    .bind(&payload.description)
+    .bind(&initial_state.id)
+    .bind(initial_state.category.is_closed())
+    .bind(&position)
+    .bind(payload.priority.unwrap_or_default())
+    .bind(start_at)
+    .bind(due_at)
+    .bind(tz.map(|tz| tz.name().to_string()))
+    .bind(&payload.parent_id)
+    .bind(&payload.project_id)
+    .bind(&payload.parent_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tags::set_task_tags(&mut tx, &user.id, &id, &tag_names).await?;
+    if let Some(rule) = payload.recurrence.as_deref() {
+        let time_zone = tz.map(|tz| tz.name());
+        recurrence::start_series(&mut tx, &user.id, &id, (start_at, due_at), time_zone, rule).await?;
+    }
+    search_index::sync(&mut tx, SearchKind::Task, &id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    trash::log_operation(&mut tx, &user.id, OperationKind::CreateTask { task_id: id.clone() }).await?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    let task = fetch_task(&state.db, &user.id, &id).await?;
     Ok((StatusCode::CREATED, Json(task)))
 }
 
@@ -141,57 +767,542 @@ async fn update_task(
     Path(id): Path<String>,
     Json(payload): Json<UpdateTaskRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    if let Some(completed) = payload.completed {
-        sqlx::query("UPDATE tasks SET completed = ? WHERE id = ? AND user_id = ?")
-            .bind(completed)
-            .bind(&id)
-            .bind(&user.id)
-            .execute(&state.db)
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    apply_task_update(&mut tx, &user.id, &id, payload).await?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let task = fetch_task(&state.db, &user.id, &id).await?;
+    Ok(Json(task))
+}
+
+/// Applies a `PATCH /api/tasks/:id` body to one of the user's tasks. Every
+/// change goes through `conn`, so the caller's transaction keeps them together.
+async fn apply_task_update(
+    conn: &mut sqlx::SqliteConnection,
+    user_id: &str,
+    id: &str,
+    payload: UpdateTaskRequest,
+) -> Result<(), (StatusCode, String)> {
+    let task = fetch_task(&mut *conn, user_id, id).await?;
+
+    if payload.start_at.is_some() || payload.due_at.is_some() || payload.time_zone.is_some() {
+        let time_zone = payload.time_zone.unwrap_or_else(|| task.time_zone.clone());
+        let zone = match time_zone.as_deref() {
+            Some(name) => dates::parse_time_zone(name).map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+            None => Tz::UTC,
+        };
+        let start_at = match payload.start_at {
+            Some(value) => parse_task_date(value.as_deref(), zone)?,
+            None => task.start_at,
+        };
+        let due_at = match payload.due_at {
+            Some(value) => parse_task_date(value.as_deref(), zone)?,
+            None => task.due_at,
+        };
+        check_task_dates(start_at, due_at)?;
+
+        sqlx::query("UPDATE tasks SET start_at = ?, due_at = ?, time_zone = ? WHERE id = ? AND user_id = ?")
+            .bind(start_at)
+            .bind(due_at)
+            .bind(time_zone.map(|_| zone.name().to_string()))
+            .bind(id)
+            .bind(user_id)
+            .execute(&mut *conn)
             .await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     }
 
-    if let Some(title) = payload.title {
-        sqlx::query("UPDATE tasks SET title = ? WHERE id = ? AND user_id = ?")
-            .bind(title)
-            .bind(&id)
-            .bind(&user.id)
-            .execute(&state.db)
+    if payload.title.is_some() || payload.notes.is_some() || payload.description.is_some() {
+        if let Some(description) = &payload.description {
+            comments::check_description(description.as_deref())?;
+        }
+        sqlx::query("UPDATE tasks SET title = ?, notes = ?, description = ? WHERE id = ? AND user_id = ?")
+            .bind(payload.title.unwrap_or_else(|| task.title.clone()))
+            .bind(payload.notes.unwrap_or_else(|| task.notes.clone()))
+            .bind(payload.description.unwrap_or_else(|| task.description.clone()))
+            .bind(id)
+            .bind(user_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        search_index::sync(conn, SearchKind::Task, id)
             .await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     }
 
-    let task = sqlx::query_as::<Sqlite, Task>("SELECT * FROM tasks WHERE id = ?")
-        .bind(&id)
-        .fetch_one(&state.db)
+    if let Some(priority) = payload.priority {
+        sqlx::query("UPDATE tasks SET priority = ? WHERE id = ? AND user_id = ?")
+            .bind(priority)
+            .bind(id)
+            .bind(user_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    if let Some(project_id) = payload.project_id {
+        if let Some(project_id) = project_id.as_deref() {
+            projects::check_project(&mut *conn, user_id, project_id).await?;
+        }
+        sqlx::query("UPDATE tasks SET project_id = ? WHERE id = ? AND user_id = ?")
+            .bind(project_id)
+            .bind(id)
+            .bind(user_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    if let Some(parent_id) = payload.parent_id {
+        if let Some(parent_id) = parent_id.as_deref() {
+            subtasks::check_parent(conn, user_id, Some(id), parent_id).await?;
+        }
+        sqlx::query("UPDATE tasks SET parent_id = ? WHERE id = ? AND user_id = ?")
+            .bind(parent_id)
+            .bind(id)
+            .bind(user_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    if let Some(names) = payload.tags {
+        let tag_names = tags::normalize_tags(&names)?;
+        tags::set_task_tags(conn, user_id, id, &tag_names).await?;
+    }
+
+    // Last, so that completing a recurring task copies the other changes along
+    if payload.state_id.is_some() || payload.completed.is_some() {
+        let updated = fetch_task(&mut *conn, user_id, id).await?;
+        let target = match (payload.state_id.as_deref(), payload.completed) {
+            (Some(state_id), completed) => {
+                let target = workflow::fetch_state(conn, user_id, state_id).await?;
+                if completed.is_some_and(|completed| completed != target.category.is_closed()) {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        format!("completed contradicts the state '{}'", target.name),
+                    ));
+                }
+                Some(target)
+            }
+            // Already open or closed tasks stay in their state
+            (None, Some(completed)) if completed != task.completed => {
+                let category = if completed { StateCategory::Done } else { StateCategory::Open };
+                Some(workflow::first_state(conn, user_id, category).await?)
+            }
+            (None, _) => None,
+        };
+        if let Some(target) = target {
+            workflow::move_task(conn, &updated, &target).await?;
+            let completed = target.category.is_closed();
+            if completed && payload.complete_subtasks {
+                subtasks::complete_descendants(conn, user_id, id).await?;
+            }
+            if completed && !task.completed {
+                recurrence::create_next_occurrence(conn, &updated).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn delete_task(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    // Subtasks go to the trash along with the task
+    let task_ids = trash::trash_task(&mut tx, &user.id, &id).await?;
+    if !task_ids.is_empty() {
+        trash::log_operation(&mut tx, &user.id, OperationKind::DeleteTasks { task_ids }).await?;
+    }
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// --- Time Entry Handlers ---
+
+const MAX_TIME_NOTE_LENGTH: usize = 500;
+const DEFAULT_TIME_ENTRIES: i64 = 100;
+const MAX_TIME_ENTRIES: i64 = 500;
+
+const TIME_ENTRY_COLUMNS: &str = concat!(
+    "time_entries.id, time_entries.task_id, tasks.title AS task_title, tasks.project_id, time_entries.started_at,
+     time_entries.ended_at, time_entries.note, time_entries.billable, time_entries.created_at, ",
+    entry_seconds!(),
+    " AS duration_seconds"
+);
+
+fn normalize_time_note(note: Option<String>) -> Result<Option<String>, (StatusCode, String)> {
+    let Some(note) = note.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()) else {
+        return Ok(None);
+    };
+    if note.chars().count() > MAX_TIME_NOTE_LENGTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Time entry notes are limited to {} characters", MAX_TIME_NOTE_LENGTH),
+        ));
+    }
+    Ok(Some(note))
+}
+
+/// Reads an entry's times in the given zone, else the task's, else UTC.
+fn time_entry_zone(time_zone: Option<&str>, task: &Task) -> Result<Tz, (StatusCode, String)> {
+    match time_zone.or(task.time_zone.as_deref()) {
+        Some(name) => dates::parse_time_zone(name).map_err(|e| (StatusCode::BAD_REQUEST, e)),
+        None => Ok(Tz::UTC),
+    }
+}
+
+fn check_entry_times(started_at: DateTime<Utc>, ended_at: DateTime<Utc>) -> Result<(), (StatusCode, String)> {
+    if ended_at <= started_at {
+        return Err((StatusCode::BAD_REQUEST, "ended_at must be after started_at".to_string()));
+    }
+    if ended_at > Utc::now() {
+        return Err((StatusCode::BAD_REQUEST, "Time entries cannot end in the future".to_string()));
+    }
+    Ok(())
+}
+
+async fn fetch_time_entry<'e>(
+    executor: impl sqlx::SqliteExecutor<'e>,
+    user_id: &str,
+    id: &str,
+) -> Result<TimeEntry, (StatusCode, String)> {
+    sqlx::query_as::<Sqlite, TimeEntry>(&format!(
+        "SELECT {} FROM time_entries JOIN tasks ON tasks.id = time_entries.task_id AND tasks.deleted_at IS NULL
+         WHERE time_entries.id = ? AND time_entries.user_id = ?",
+        TIME_ENTRY_COLUMNS
+    ))
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "Time entry not found".to_string()))
+}
+
+async fn running_timer<'e>(
+    executor: impl sqlx::SqliteExecutor<'e>,
+    user_id: &str,
+) -> Result<Option<TimeEntry>, (StatusCode, String)> {
+    sqlx::query_as::<Sqlite, TimeEntry>(&format!(
+        "SELECT {} FROM time_entries JOIN tasks ON tasks.id = time_entries.task_id AND tasks.deleted_at IS NULL
+         WHERE time_entries.user_id = ? AND time_entries.ended_at IS NULL",
+        TIME_ENTRY_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Newest first.
+async fn list_time_entries(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<TimeEntryListQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut builder = QueryBuilder::<Sqlite>::new(format!(
+        "SELECT {} FROM time_entries JOIN tasks ON tasks.id = time_entries.task_id AND tasks.deleted_at IS NULL
+         WHERE time_entries.user_id = ",
+        TIME_ENTRY_COLUMNS
+    ));
+    builder.push_bind(&user.id);
+    if let Some(task_id) = query.task_id {
+        builder.push(" AND time_entries.task_id = ").push_bind(task_id);
+    }
+    if let Some(from) = query.from {
+        builder.push(" AND time_entries.started_at >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        builder.push(" AND time_entries.started_at < ").push_bind(to);
+    }
+    builder
+        .push(" ORDER BY time_entries.started_at DESC LIMIT ")
+        .push_bind(query.limit.unwrap_or(DEFAULT_TIME_ENTRIES).clamp(1, MAX_TIME_ENTRIES));
+
+    let entries = builder
+        .build_query_as::<TimeEntry>()
+        .fetch_all(&state.db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    Ok(Json(task))
+    Ok(Json(entries))
 }
 
-async fn delete_task(
+/// Logs time already spent, without a timer.
+async fn create_time_entry(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(payload): Json<CreateTimeEntryRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let task = fetch_task(&state.db, &user.id, &payload.task_id).await?;
+    let zone = time_entry_zone(payload.time_zone.as_deref(), &task)?;
+    let started_at = dates::parse_datetime(&payload.started_at, zone).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let ended_at = dates::parse_datetime(&payload.ended_at, zone).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    check_entry_times(started_at, ended_at)?;
+    let note = normalize_time_note(payload.note)?;
+
+    let id = Uuid::new_v4().to_string();
+    sqlx::query(
+        "INSERT INTO time_entries (id, user_id, task_id, started_at, ended_at, note, billable) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(&user.id)
+    .bind(&task.id)
+    .bind(started_at)
+    .bind(ended_at)
+    .bind(&note)
+    .bind(payload.billable)
+    .execute(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(fetch_time_entry(&state.db, &user.id, &id).await?)))
+}
+
+async fn update_time_entry(
     State(state): State<AppState>,
     AuthenticatedUser(user): AuthenticatedUser,
     Path(id): Path<String>,
+    Json(payload): Json<UpdateTimeEntryRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    sqlx::query("DELETE FROM tasks WHERE id = ? AND user_id = ?")
-        .bind(id)
-        .bind(user.id)
+    let entry = fetch_time_entry(&state.db, &user.id, &id).await?;
+    let task = fetch_task(&state.db, &user.id, &entry.task_id).await?;
+    let zone = time_entry_zone(payload.time_zone.as_deref(), &task)?;
+
+    let started_at = match payload.started_at.as_deref() {
+        Some(input) => dates::parse_datetime(input, zone).map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+        None => entry.started_at,
+    };
+    let ended_at = match (payload.ended_at.as_deref(), entry.ended_at) {
+        (Some(_), None) => {
+            return Err((StatusCode::CONFLICT, "The timer is still running; stop it first".to_string()));
+        }
+        (Some(input), Some(_)) => Some(dates::parse_datetime(input, zone).map_err(|e| (StatusCode::BAD_REQUEST, e))?),
+        (None, ended_at) => ended_at,
+    };
+    match ended_at {
+        Some(ended_at) => check_entry_times(started_at, ended_at)?,
+        None if started_at > Utc::now() => {
+            return Err((StatusCode::BAD_REQUEST, "A running timer cannot start in the future".to_string()));
+        }
+        None => {}
+    }
+    let note = match payload.note {
+        Some(note) => normalize_time_note(note)?,
+        None => entry.note,
+    };
+
+    sqlx::query(
+        "UPDATE time_entries SET started_at = ?, ended_at = ?, note = ?, billable = ? WHERE id = ? AND user_id = ?",
+    )
+    .bind(started_at)
+    .bind(ended_at)
+    .bind(&note)
+    .bind(payload.billable.unwrap_or(entry.billable))
+    .bind(&id)
+    .bind(&user.id)
+    .execute(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(fetch_time_entry(&state.db, &user.id, &id).await?))
+}
+
+async fn delete_time_entry(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let result = sqlx::query("DELETE FROM time_entries WHERE id = ? AND user_id = ?")
+        .bind(&id)
+        .bind(&user.id)
         .execute(&state.db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "Time entry not found".to_string()));
+    }
+
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// The running timer, or `null`.
+async fn get_timer(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    Ok(Json(running_timer(&state.db, &user.id).await?))
+}
+
+/// Starts a timer on the task. Only one timer runs at a time, so another
+/// running one has to be stopped first.
+async fn start_timer(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+    payload: Option<Json<StartTimerRequest>>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let task = fetch_task(&state.db, &user.id, &id).await?;
+    let (note, billable) = match payload {
+        Some(Json(payload)) => (normalize_time_note(payload.note)?, payload.billable),
+        None => (None, true),
+    };
+
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if let Some(running) = running_timer(&mut *tx, &user.id).await? {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("A timer is already running on '{}'; stop it first", running.task_title),
+        ));
+    }
+    let entry_id = Uuid::new_v4().to_string();
+    // The unique index on running entries settles a race with another start
+    sqlx::query("INSERT INTO time_entries (id, user_id, task_id, started_at, note, billable) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(&entry_id)
+        .bind(&user.id)
+        .bind(&task.id)
+        .bind(Utc::now())
+        .bind(&note)
+        .bind(billable)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            if e.as_database_error().is_some_and(|d| d.is_unique_violation()) {
+                (StatusCode::CONFLICT, "A timer is already running; stop it first".to_string())
+            } else {
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            }
+        })?;
+    let entry = fetch_time_entry(&mut *tx, &user.id, &entry_id).await?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(entry)))
+}
+
+/// Stops the running timer, whichever task it is on.
+async fn stop_timer(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let running = running_timer(&state.db, &user.id)
+        .await?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "No timer is running".to_string()))?;
+
+    sqlx::query("UPDATE time_entries SET ended_at = ? WHERE id = ? AND user_id = ? AND ended_at IS NULL")
+        .bind(Utc::now())
+        .bind(&running.id)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(fetch_time_entry(&state.db, &user.id, &running.id).await?))
+}
+
+/// Time per task, project or day between two dates, both included. Each
+/// entry counts towards the day it started on in `tz`.
+async fn time_report(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<TimeReportQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let tz = dates::parse_time_zone(query.tz.as_deref().unwrap_or("UTC")).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let group = query.group.unwrap_or_else(|| "task".to_string());
+    if !matches!(group.as_str(), "task" | "project" | "day") {
+        return Err((StatusCode::BAD_REQUEST, format!("Unknown group '{}', expected task, project or day", group)));
+    }
+    let (week_start, week_end) = dates::week_bounds(tz, Utc::now());
+    let from = query.from.unwrap_or_else(|| week_start.with_timezone(&tz).date_naive());
+    let to = query.to.unwrap_or_else(|| (week_end.with_timezone(&tz) - chrono::Duration::days(1)).date_naive());
+    if to < from {
+        return Err((StatusCode::BAD_REQUEST, "to must not be before from".to_string()));
+    }
+    let end = to.succ_opt().ok_or_else(|| (StatusCode::BAD_REQUEST, "to is out of range".to_string()))?;
+
+    let mut builder = QueryBuilder::<Sqlite>::new(format!(
+        "SELECT {}, projects.name AS project_name FROM time_entries
+         JOIN tasks ON tasks.id = time_entries.task_id AND tasks.deleted_at IS NULL LEFT JOIN projects ON projects.id = tasks.project_id WHERE time_entries.user_id = ",
+        TIME_ENTRY_COLUMNS
+    ));
+    builder.push_bind(&user.id);
+    builder.push(" AND time_entries.started_at >= ").push_bind(dates::start_of_day(tz, from));
+    builder.push(" AND time_entries.started_at < ").push_bind(dates::start_of_day(tz, end));
+    match query.project.as_deref() {
+        None => {}
+        Some("none") => {
+            builder.push(" AND tasks.project_id IS NULL");
+        }
+        Some(project_id) => {
+            builder.push(" AND tasks.project_id = ").push_bind(project_id.to_string());
+        }
+    }
+    builder.push(" ORDER BY time_entries.started_at");
+
+    #[derive(sqlx::FromRow)]
+    struct ReportEntry {
+        #[sqlx(flatten)]
+        entry: TimeEntry,
+        project_name: Option<String>,
+    }
+    let entries = builder
+        .build_query_as::<ReportEntry>()
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut rows: Vec<TimeReportRow> = Vec::new();
+    for ReportEntry { entry, project_name } in entries {
+        let (key, label) = match group.as_str() {
+            "task" => (Some(entry.task_id), entry.task_title),
+            "project" => (entry.project_id, project_name.unwrap_or_else(|| "No project".to_string())),
+            _ => {
+                let day = entry.started_at.with_timezone(&tz).date_naive().to_string();
+                (Some(day.clone()), day)
+            }
+        };
+        let billable = if entry.billable { entry.duration_seconds } else { 0 };
+        match rows.iter_mut().find(|row| row.key == key) {
+            Some(row) => {
+                row.seconds += entry.duration_seconds;
+                row.billable_seconds += billable;
+            }
+            None => rows.push(TimeReportRow {
+                key,
+                label,
+                seconds: entry.duration_seconds,
+                billable_seconds: billable,
+            }),
+        }
+    }
+    // Days read in calendar order, everything else biggest first
+    if group != "day" {
+        rows.sort_by_key(|row| std::cmp::Reverse(row.seconds));
+    }
+
+    Ok(Json(TimeReport {
+        group,
+        from,
+        to,
+        total_seconds: rows.iter().map(|row| row.seconds).sum(),
+        billable_seconds: rows.iter().map(|row| row.billable_seconds).sum(),
+        rows,
+    }))
+}
+
 // --- Expense Handlers ---
 
 async fn list_expenses(
     State(state): State<AppState>,
     AuthenticatedUser(user): AuthenticatedUser,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let expenses = sqlx::query_as::<Sqlite, Expense>("SELECT * FROM expenses WHERE user_id = ? ORDER BY created_at DESC")
+    let expenses = sqlx::query_as::<Sqlite, Expense>("SELECT * FROM expenses WHERE user_id = ? AND deleted_at IS NULL ORDER BY created_at DESC")
         .bind(user.id)
         .fetch_all(&state.db)
         .await
@@ -207,14 +1318,21 @@ async fn create_expense(
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     let id = Uuid::new_v4().to_string();
 
-    sqlx::query("INSERT INTO expenses (id, user_id, category, amount) VALUES (?, ?, ?, ?)")
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    sqlx::query("INSERT INTO expenses (id, user_id, category, amount, notes) VALUES (?, ?, ?, ?, ?)")
         .bind(&id)
         .bind(&user.id)
         .bind(&payload.category)
         .bind(payload.amount)
-        .execute(&state.db)
+        .bind(&payload.notes)
+        .execute(&mut *tx)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    search_index::sync(&mut tx, SearchKind::Expense, &id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    trash::log_operation(&mut tx, &user.id, OperationKind::CreateExpense { expense_id: id.clone() }).await?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     let expense = sqlx::query_as::<Sqlite, Expense>("SELECT * FROM expenses WHERE id = ?")
         .bind(&id)
@@ -230,12 +1348,11 @@ async fn delete_expense(
     AuthenticatedUser(user): AuthenticatedUser,
     Path(id): Path<String>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    sqlx::query("DELETE FROM expenses WHERE id = ? AND user_id = ?")
-        .bind(id)
-        .bind(user.id)
-        .execute(&state.db)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if trash::set_expense_deleted(&mut tx, &user.id, &id, Some(Utc::now())).await? {
+        trash::log_operation(&mut tx, &user.id, OperationKind::DeleteExpense { expense_id: id }).await?;
+    }
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -244,13 +1361,13 @@ async fn get_expense_summary(
     State(state): State<AppState>,
     AuthenticatedUser(user): AuthenticatedUser,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let total: (f64,) = sqlx::query_as::<Sqlite, (f64,)>("SELECT COALESCE(SUM(amount), 0.0) FROM expenses WHERE user_id = ?")
+    let total: (f64,) = sqlx::query_as::<Sqlite, (f64,)>("SELECT COALESCE(SUM(amount), 0.0) FROM expenses WHERE user_id = ? AND deleted_at IS NULL")
         .bind(&user.id)
         .fetch_one(&state.db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let categories: Vec<(String, f64)> = sqlx::query_as::<Sqlite, (String, f64)>("SELECT category, SUM(amount) FROM expenses WHERE user_id = ? GROUP BY category ORDER BY SUM(amount) DESC")
+    let categories: Vec<(String, f64)> = sqlx::query_as::<Sqlite, (String, f64)>("SELECT category, SUM(amount) FROM expenses WHERE user_id = ? AND deleted_at IS NULL GROUP BY category ORDER BY SUM(amount) DESC")
         .bind(&user.id)
         .fetch_all(&state.db)
         .await
@@ -264,6 +1381,9 @@ async fn get_expense_summary(
 
 // --- AI Handlers ---
 
+/// Characters of a task's description included in AI prompts.
+const MAX_PROMPT_DESCRIPTION_CHARS: usize = 300;
+
 async fn ai_suggest(
     State(state): State<AppState>,
     AuthenticatedUser(user): AuthenticatedUser,
@@ -271,28 +1391,59 @@ async fn ai_suggest(
     let client = state.groq.ok_or_else(|| (StatusCode::SERVICE_UNAVAILABLE, "AI features disabled".to_string()))?;
     
     // Fetch data for context
-    let tasks = sqlx::query_as::<Sqlite, Task>("SELECT * FROM tasks WHERE user_id = ? AND completed = 0")
-        .bind(&user.id)
-        .fetch_all(&state.db)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let tasks = sqlx::query_as::<Sqlite, Task>(&format!(
+        "SELECT {} FROM tasks WHERE user_id = ? AND completed = 0 AND deleted_at IS NULL ORDER BY priority DESC",
+        TASK_COLUMNS
+    ))
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    // Only suggest what can be started now
+    let tasks: Vec<Task> = tasks.into_iter().filter(|task| !task.blocked).collect();
 
-    let total: (f64,) = sqlx::query_as::<Sqlite, (f64,)>("SELECT COALESCE(SUM(amount), 0.0) FROM expenses WHERE user_id = ?")
+    let total: (f64,) = sqlx::query_as::<Sqlite, (f64,)>("SELECT COALESCE(SUM(amount), 0.0) FROM expenses WHERE user_id = ? AND deleted_at IS NULL")
         .bind(&user.id)
         .fetch_one(&state.db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let task_list = tasks.iter().map(|t| t.title.clone()).collect::<Vec<_>>().join(", ");
+    let now = chrono::Utc::now();
+    let (week_start, _) = dates::week_bounds(Tz::UTC, now);
+    let (tracked, billable): (i64, i64) = sqlx::query_as(concat!(
+        "SELECT COALESCE(SUM(", entry_seconds!(), "), 0), COALESCE(SUM(CASE WHEN billable THEN ", entry_seconds!(),
+        " ELSE 0 END), 0) FROM time_entries JOIN tasks ON tasks.id = time_entries.task_id AND tasks.deleted_at IS NULL
+         WHERE time_entries.user_id = ? AND time_entries.started_at >= ?"
+    ))
+    .bind(&user.id)
+    .bind(week_start)
+    .fetch_one(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let timer = match running_timer(&state.db, &user.id).await? {
+        Some(entry) => format!("\nTimer running on: {} for {}", entry.task_title, describe_duration(entry.duration_seconds)),
+        None => String::new(),
+    };
+
+    let mut task_lines = Vec::new();
+    describe_task_tree(&subtasks::build_forest(tasks), 0, now, &mut task_lines);
+    let task_list = task_lines.join("\n");
     
     let prompt = format!(
         "User context:
-Tasks pending: {}
+Current time: {}
+Tasks pending:
+{}
 Total spending: ${:.2}
+Time tracked this week: {} ({} billable){}
 
-Provide a brief, motivating suggestion for what they should do next.",
+Provide a brief, motivating suggestion for what they should do next, taking due dates into account. Indented tasks are subtasks of the task above them; description lines belong to the task above.",
+        now.format("%a %-d %b %Y %H:%M UTC"),
         if task_list.is_empty() { "None" } else { &task_list },
-        total.0
+        total.0,
+        describe_duration(tracked),
+        describe_duration(billable),
+        timer
     );
 
     let response = client.quick_chat(&prompt).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
@@ -300,6 +1451,59 @@ Provide a brief, motivating suggestion for what they should do next.",
     Ok(Json(serde_json::json!({ "suggestion": response })))
 }
 
+/// Task lines for AI prompts, each subtask indented below its parent and
+/// descriptions shortened on the line after their task.
+fn describe_task_tree(nodes: &[TaskNode], depth: usize, now: DateTime<Utc>, lines: &mut Vec<String>) {
+    for node in nodes {
+        let indent = "  ".repeat(depth);
+        lines.push(format!("{}{}", indent, describe_task(&node.task, now)));
+        if let Some(description) = node.task.description.as_deref().filter(|d| !d.trim().is_empty()) {
+            lines.push(format!(
+                "{}  Description: {}",
+                indent,
+                markdown::excerpt(description, MAX_PROMPT_DESCRIPTION_CHARS)
+            ));
+        }
+        describe_task_tree(&node.subtasks, depth + 1, now, lines);
+    }
+}
+
+/// One line per task for AI prompts, with its dates in the task's own zone.
+fn describe_task(task: &Task, now: DateTime<Utc>) -> String {
+    let tz = task
+        .time_zone
+        .as_deref()
+        .and_then(|name| dates::parse_time_zone(name).ok())
+        .unwrap_or(Tz::UTC);
+
+    let mut line = format!("- {} [{:?} priority]", task.title, task.priority);
+    if !task.tags.is_empty() {
+        line.push_str(&format!(", tags: {}", task.tags.join(", ")));
+    }
+    if task.subtask_count > 0 {
+        line.push_str(&format!(", {} of {} subtasks done", task.completed_subtask_count, task.subtask_count));
+    }
+    if let Some(start_at) = task.start_at {
+        line.push_str(&format!(", starts {}", dates::describe(start_at, tz)));
+    }
+    if let Some(due_at) = task.due_at {
+        line.push_str(&format!(", due {}", dates::describe(due_at, tz)));
+        if due_at < now {
+            line.push_str(" (overdue)");
+        }
+    }
+    if task.tracked_seconds > 0 {
+        line.push_str(&format!(", {} tracked", describe_duration(task.tracked_seconds)));
+    }
+    line
+}
+
+/// Tracked time for prompts, e.g. `2h 05m`.
+fn describe_duration(seconds: i64) -> String {
+    let minutes = seconds / 60;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
 async fn ai_chat_handler(
     State(state): State<AppState>,
     AuthenticatedUser(user): AuthenticatedUser,
@@ -332,21 +1536,165 @@ async fn ai_chat_handler(
     let user_msg_id = Uuid::new_v4().to_string();
     let assistant_msg_id = Uuid::new_v4().to_string();
 
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
     sqlx::query("INSERT INTO chat_messages (id, user_id, role, content) VALUES (?, ?, 'user', ?)")
-        .bind(user_msg_id)
+        .bind(&user_msg_id)
         .bind(&user.id)
         .bind(&payload.message)
-        .execute(&state.db)
+        .execute(&mut *tx)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     sqlx::query("INSERT INTO chat_messages (id, user_id, role, content) VALUES (?, ?, 'assistant', ?)")
-        .bind(assistant_msg_id)
+        .bind(&assistant_msg_id)
         .bind(&user.id)
         .bind(&response)
-        .execute(&state.db)
+        .execute(&mut *tx)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    for id in [&user_msg_id, &assistant_msg_id] {
+        search_index::sync(&mut tx, SearchKind::Chat, id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
     Ok(Json(serde_json::json!({ "response": response })))
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestApp;
+    use axum::http::StatusCode;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use serde_json::json;
+    use std::collections::HashSet;
+
+    /// Every id of `path`, following `X-Next-Cursor` one page at a time.
+    async fn page_through(app: &TestApp, token: &str, path: &str) -> Vec<String> {
+        let mut ids = Vec::new();
+        let mut cursor = None;
+        loop {
+            let url = match &cursor {
+                Some(cursor) => format!("{}&cursor={}", path, cursor),
+                None => path.to_string(),
+            };
+            let page = app.get(&url, token).await;
+            assert_eq!(page.status, StatusCode::OK, "{}", page.body);
+            ids.extend(page.body.as_array().unwrap().iter().map(|task| task["id"].as_str().unwrap().to_string()));
+            match page.headers.get("x-next-cursor") {
+                Some(next) => cursor = Some(next.to_str().unwrap().to_string()),
+                None => return ids,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn pages_through_ties_without_duplicates_or_gaps() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        for i in 0..7 {
+            let priority = if i == 3 { "urgent" } else { "high" };
+            app.create_task(&token, json!({ "title": "Same title", "priority": priority })).await;
+        }
+
+        for sort in ["priority", "title", "created_at", "due_at"] {
+            for order in ["asc", "desc"] {
+                let path = format!("/api/tasks?sort={}&order={}", sort, order);
+                let everything = page_through(&app, &token, &format!("{}&limit=200", path)).await;
+                let paged = page_through(&app, &token, &format!("{}&limit=2", path)).await;
+                assert_eq!(everything.len(), 7);
+                assert_eq!(paged.iter().collect::<HashSet<_>>().len(), 7, "{} {}", sort, order);
+                assert_eq!(paged, everything, "{} {}", sort, order);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_a_tampered_cursor() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        for _ in 0..3 {
+            app.create_task(&token, json!({ "title": "Task" })).await;
+        }
+        let first = app.get("/api/tasks?sort=title&limit=1", &token).await;
+        let cursor = first.headers["x-next-cursor"].to_str().unwrap().to_string();
+        let next = app.get(&format!("/api/tasks?sort=title&limit=1&cursor={}", cursor), &token).await;
+        assert_eq!(next.status, StatusCode::OK);
+
+        // A valid cursor for another sort order
+        let other = app.get(&format!("/api/tasks?sort=title&order=desc&limit=1&cursor={}", cursor), &token).await;
+        assert_eq!(other.status, StatusCode::BAD_REQUEST);
+
+        // A rewritten position, with or without the old signature
+        let (payload, signature) = cursor.split_once('.').unwrap();
+        let mut decoded: serde_json::Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap();
+        decoded["key"] = json!("{");
+        let forged = URL_SAFE_NO_PAD.encode(decoded.to_string());
+        for tampered in [format!("{}.{}", forged, signature), forged, "garbage".to_string()] {
+            let response = app.get(&format!("/api/tasks?sort=title&limit=1&cursor={}", tampered), &token).await;
+            assert_eq!(response.status, StatusCode::BAD_REQUEST, "{}", tampered);
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_one_timer_at_a_time() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        let report = app.create_task(&token, json!({ "title": "Report" })).await;
+        let slides = app.create_task(&token, json!({ "title": "Slides" })).await;
+
+        let started = app.post(&format!("/api/tasks/{}/timer", report), &token, json!({})).await;
+        assert_eq!(started.status, StatusCode::CREATED, "{}", started.body);
+        let second = app.post(&format!("/api/tasks/{}/timer", slides), &token, json!({})).await;
+        assert_eq!(second.status, StatusCode::CONFLICT);
+        let running = app.get("/api/timer", &token).await;
+        assert_eq!(running.body["id"], started.body["id"]);
+        assert_eq!(running.body["task_id"], report.as_str());
+
+        let stopped = app.post("/api/timer/stop", &token, json!({})).await;
+        assert_eq!(stopped.status, StatusCode::OK);
+        assert!(stopped.body["ended_at"].is_string());
+        let next = app.post(&format!("/api/tasks/{}/timer", slides), &token, json!({})).await;
+        assert_eq!(next.status, StatusCode::CREATED, "{}", next.body);
+        let entries = app.get("/api/time-entries", &token).await;
+        assert_eq!(entries.body.as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn reports_days_in_the_given_zone() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        let task = app.create_task(&token, json!({ "title": "Report" })).await;
+        // Tokyo is UTC+9: the first entry falls on 2 March there, the last on 3 March
+        for (started_at, ended_at, billable) in [
+            ("2026-03-01T15:30:00Z", "2026-03-01T15:45:00Z", true),
+            ("2026-03-02T10:00:00Z", "2026-03-02T11:00:00Z", true),
+            ("2026-03-02T20:00:00Z", "2026-03-02T20:30:00Z", false),
+        ] {
+            let body = json!({ "task_id": task, "started_at": started_at, "ended_at": ended_at, "billable": billable });
+            let created = app.post("/api/time-entries", &token, body).await;
+            assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+        }
+
+        let path = "/api/time-entries/report?group=day&from=2026-03-02&to=2026-03-03";
+        let tokyo = app.get(&format!("{}&tz=Asia/Tokyo", path), &token).await;
+        assert_eq!(tokyo.status, StatusCode::OK, "{}", tokyo.body);
+        assert_eq!(
+            tokyo.body["rows"],
+            json!([
+                { "key": "2026-03-02", "label": "2026-03-02", "seconds": 4500, "billable_seconds": 4500 },
+                { "key": "2026-03-03", "label": "2026-03-03", "seconds": 1800, "billable_seconds": 0 },
+            ])
+        );
+        assert_eq!(tokyo.body["total_seconds"], 6300);
+
+        let utc = app.get(path, &token).await;
+        assert_eq!(
+            utc.body["rows"],
+            json!([{ "key": "2026-03-02", "label": "2026-03-02", "seconds": 5400, "billable_seconds": 3600 }])
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use sqlx::{SqliteConnection, SqlitePool};
+
+use super::{fetch_task, recurrence, workflow, AppState};
+use crate::auth::AuthenticatedUser;
+use crate::fractional_index::key_between;
+use crate::models::*;
+
+/// A position after every task of the user, for a new task.
+pub async fn next_position(conn: &mut SqliteConnection, user_id: &str) -> Result<String, (StatusCode, String)> {
+    let last: Option<String> = sqlx::query_scalar("SELECT MAX(position) FROM tasks WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    key_between(last.as_deref(), None).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+/// Gives tasks that predate manual ordering a position on startup, in the
+/// order they were created.
+pub async fn backfill_positions(pool: &SqlitePool) -> Result<(), String> {
+    let user_ids: Vec<String> = sqlx::query_scalar("SELECT DISTINCT user_id FROM tasks WHERE position IS NULL")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to find tasks without a position: {}", e))?;
+
+    for user_id in user_ids {
+        let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+        let ids: Vec<String> =
+            sqlx::query_scalar("SELECT id FROM tasks WHERE user_id = ? AND position IS NULL ORDER BY created_at, rowid")
+                .bind(&user_id)
+                .fetch_all(&mut *tx)
+                .await
+                .map_err(|e| e.to_string())?;
+        for id in ids {
+            let position = next_position(&mut tx, &user_id).await.map_err(|(_, e)| e)?;
+            sqlx::query("UPDATE tasks SET position = ? WHERE id = ?")
+                .bind(&position)
+                .bind(&id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        tx.commit().await.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+async fn position_of(conn: &mut SqliteConnection, user_id: &str, id: &str) -> Result<String, (StatusCode, String)> {
+    sqlx::query_scalar::<_, String>("SELECT position FROM tasks WHERE id = ? AND user_id = ? AND deleted_at IS NULL")
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Neighbor task not found".to_string()))
+}
+
+/// The position closest to `position` on one side among the user's other
+/// tasks, so a key picked in between collides with no other task.
+async fn closest_position(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    task_id: &str,
+    position: &str,
+    above: bool,
+) -> Result<Option<String>, (StatusCode, String)> {
+    let query = if above {
+        "SELECT MIN(position) FROM tasks WHERE user_id = ? AND id != ? AND position > ?"
+    } else {
+        "SELECT MAX(position) FROM tasks WHERE user_id = ? AND id != ? AND position < ?"
+    };
+    sqlx::query_scalar(query)
+        .bind(user_id)
+        .bind(task_id)
+        .bind(position)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Places a task right after `after_id` and/or right before `before_id`,
+/// its neighbors in whatever list it was dropped into: a project, a board
+/// column or the subtasks of a task. Only the moved task changes. A
+/// `state_id` moves the task to another board column at the same time,
+/// completing it like `PATCH /api/tasks/:id` when that column is closed.
+pub async fn move_task(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+    Json(payload): Json<MoveTaskRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let task = fetch_task(&state.db, &user.id, &id).await?;
+    if payload.after_id.as_deref() == Some(id.as_str()) || payload.before_id.as_deref() == Some(id.as_str()) {
+        return Err((StatusCode::BAD_REQUEST, "A task cannot be its own neighbor".to_string()));
+    }
+
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let after = match payload.after_id.as_deref() {
+        Some(after_id) => Some(position_of(&mut tx, &user.id, after_id).await?),
+        None => None,
+    };
+    let before = match payload.before_id.as_deref() {
+        Some(before_id) => Some(position_of(&mut tx, &user.id, before_id).await?),
+        None => None,
+    };
+    // Tasks of other lists may sit between the two neighbors, so the new
+    // key goes right next to one of them
+    let (low, high) = match (after, before) {
+        (Some(after), before) => {
+            let next = closest_position(&mut tx, &user.id, &id, &after, true).await?;
+            if let Some(before) = &before {
+                if *before <= after {
+                    return Err((StatusCode::BAD_REQUEST, "after_id must come before before_id".to_string()));
+                }
+            }
+            (Some(after), next)
+        }
+        (None, Some(before)) => {
+            let previous = closest_position(&mut tx, &user.id, &id, &before, false).await?;
+            (previous, Some(before))
+        }
+        (None, None) => {
+            return Err((StatusCode::BAD_REQUEST, "Give after_id, before_id or both".to_string()));
+        }
+    };
+    let position = key_between(low.as_deref(), high.as_deref()).map_err(|e| (StatusCode::CONFLICT, e))?;
+
+    sqlx::query("UPDATE tasks SET position = ? WHERE id = ? AND user_id = ?")
+        .bind(&position)
+        .bind(&id)
+        .bind(&user.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if let Some(state_id) = payload.state_id.as_deref() {
+        let target = workflow::fetch_state(&mut tx, &user.id, state_id).await?;
+        workflow::move_task(&mut tx, &task, &target).await?;
+        if target.category.is_closed() && !task.completed {
+            recurrence::create_next_occurrence(&mut tx, &task).await?;
+        }
+    }
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(fetch_task(&state.db, &user.id, &id).await?))
+}
@@ -0,0 +1,290 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use sqlx::{Sqlite, SqliteExecutor};
+use uuid::Uuid;
+
+use super::AppState;
+use crate::auth::AuthenticatedUser;
+use crate::models::*;
+
+const MAX_PROJECT_NAME_LENGTH: usize = 100;
+const DEFAULT_COLOR: &str = "#6366f1";
+
+const PROJECT_COLUMNS: &str = "projects.id, projects.name, projects.color, projects.position,
+    projects.archived_at, projects.created_at,
+    (SELECT COUNT(*) FROM tasks WHERE tasks.project_id = projects.id AND tasks.deleted_at IS NULL) AS task_count,
+    (SELECT COUNT(*) FROM tasks WHERE tasks.project_id = projects.id AND tasks.deleted_at IS NULL
+        AND tasks.completed = 1) AS completed_count";
+
+fn normalize_name(name: &str) -> Result<String, (StatusCode, String)> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Project names must not be empty".to_string()));
+    }
+    if name.chars().count() > MAX_PROJECT_NAME_LENGTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Project names are limited to {} characters", MAX_PROJECT_NAME_LENGTH),
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// Accepts `#rrggbb` in either case and stores it lowercase.
+fn normalize_color(color: &str) -> Result<String, (StatusCode, String)> {
+    let color = color.trim();
+    match color.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => Ok(color.to_ascii_lowercase()),
+        _ => Err((StatusCode::BAD_REQUEST, format!("Invalid color '{}', expected #rrggbb", color))),
+    }
+}
+
+/// Checks that a task may be put into the project: it must belong to the
+/// same user and not be archived. Someone else's project reads as missing.
+pub async fn check_project<'e>(
+    executor: impl SqliteExecutor<'e>,
+    user_id: &str,
+    project_id: &str,
+) -> Result<(), (StatusCode, String)> {
+    let archived: Option<bool> =
+        sqlx::query_scalar("SELECT archived_at IS NOT NULL FROM projects WHERE id = ? AND user_id = ?")
+            .bind(project_id)
+            .bind(user_id)
+            .fetch_optional(executor)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    match archived {
+        None => Err((StatusCode::NOT_FOUND, "Project not found".to_string())),
+        Some(true) => Err((StatusCode::CONFLICT, "Project is archived".to_string())),
+        Some(false) => Ok(()),
+    }
+}
+
+// --- Project Handlers ---
+
+pub async fn list_projects(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<ProjectListQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let archived_filter = if query.include_archived { "" } else { " AND archived_at IS NULL" };
+    let projects = sqlx::query_as::<Sqlite, Project>(&format!(
+        "SELECT {} FROM projects WHERE user_id = ?{} ORDER BY position, created_at",
+        PROJECT_COLUMNS, archived_filter
+    ))
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(projects))
+}
+
+/// New projects are listed last.
+pub async fn create_project(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(payload): Json<CreateProjectRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let name = normalize_name(&payload.name)?;
+    let color = normalize_color(payload.color.as_deref().unwrap_or(DEFAULT_COLOR))?;
+    let id = Uuid::new_v4().to_string();
+
+    sqlx::query(
+        "INSERT INTO projects (id, user_id, name, color, position)
+         SELECT ?, ?, ?, ?, COALESCE(MAX(position) + 1, 0) FROM projects WHERE user_id = ?",
+    )
+    .bind(&id)
+    .bind(&user.id)
+    .bind(&name)
+    .bind(&color)
+    .bind(&user.id)
+    .execute(&state.db)
+    .await
+    .map_err(|_| (StatusCode::CONFLICT, format!("Project '{}' already exists", name)))?;
+
+    let project = fetch_project(&state, &user.id, &id).await?;
+    Ok((StatusCode::CREATED, Json(project)))
+}
+
+pub async fn get_project(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    Ok(Json(fetch_project(&state, &user.id, &id).await?))
+}
+
+/// Renames, recolors, archives or unarchives a project. Archived projects
+/// keep their tasks but accept no new ones.
+pub async fn update_project(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateProjectRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    fetch_project(&state, &user.id, &id).await?;
+
+    if let Some(name) = payload.name {
+        let name = normalize_name(&name)?;
+        sqlx::query("UPDATE projects SET name = ? WHERE id = ? AND user_id = ?")
+            .bind(&name)
+            .bind(&id)
+            .bind(&user.id)
+            .execute(&state.db)
+            .await
+            .map_err(|_| (StatusCode::CONFLICT, format!("Project '{}' already exists", name)))?;
+    }
+
+    if let Some(color) = payload.color {
+        sqlx::query("UPDATE projects SET color = ? WHERE id = ? AND user_id = ?")
+            .bind(normalize_color(&color)?)
+            .bind(&id)
+            .bind(&user.id)
+            .execute(&state.db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    if let Some(archived) = payload.archived {
+        // Archiving again keeps the original date
+        sqlx::query(
+            "UPDATE projects SET archived_at = CASE WHEN ? THEN COALESCE(archived_at, ?) END
+             WHERE id = ? AND user_id = ?",
+        )
+        .bind(archived)
+        .bind(chrono::Utc::now())
+        .bind(&id)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    Ok(Json(fetch_project(&state, &user.id, &id).await?))
+}
+
+/// Deletes a project; its tasks stay, outside any project.
+pub async fn delete_project(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query("UPDATE tasks SET project_id = NULL WHERE project_id = ? AND user_id = ?")
+        .bind(&id)
+        .bind(&user.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let result = sqlx::query("DELETE FROM projects WHERE id = ? AND user_id = ?")
+        .bind(&id)
+        .bind(&user.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "Project not found".to_string()));
+    }
+
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sets the listing order. `ids` must name every project, archived ones included.
+pub async fn reorder_projects(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(payload): Json<ReorderProjectsRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut existing: Vec<String> = sqlx::query_scalar("SELECT id FROM projects WHERE user_id = ?")
+        .bind(&user.id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut requested = payload.ids.clone();
+    existing.sort();
+    requested.sort();
+    if existing != requested {
+        return Err((StatusCode::BAD_REQUEST, "ids must list each of your projects exactly once".to_string()));
+    }
+
+    for (position, id) in payload.ids.iter().enumerate() {
+        sqlx::query("UPDATE projects SET position = ? WHERE id = ? AND user_id = ?")
+            .bind(position as i64)
+            .bind(id)
+            .bind(&user.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    list_projects(State(state), AuthenticatedUser(user), Query(ProjectListQuery { include_archived: true })).await
+}
+
+/// The project's tasks, with the same filters, sorting and paging as `/api/tasks`.
+pub async fn list_project_tasks(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+    Query(mut query): Query<TaskListQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    fetch_project(&state, &user.id, &id).await?;
+    query.project = Some(id);
+    super::list_tasks(State(state), AuthenticatedUser(user), Query(query)).await
+}
+
+pub async fn project_stats(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    fetch_project(&state, &user.id, &id).await?;
+
+    let (total, completed, overdue): (i64, i64, i64) = sqlx::query_as(
+        "SELECT COUNT(*),
+                COALESCE(SUM(completed = 1), 0),
+                COALESCE(SUM(completed = 0 AND due_at < ?), 0)
+         FROM tasks WHERE project_id = ? AND user_id = ? AND deleted_at IS NULL",
+    )
+    .bind(chrono::Utc::now())
+    .bind(&id)
+    .bind(&user.id)
+    .fetch_one(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(ProjectStats {
+        project_id: id,
+        total,
+        completed,
+        open: total - completed,
+        overdue,
+        completion_rate: if total == 0 { 0.0 } else { completed as f64 / total as f64 },
+    }))
+}
+
+async fn fetch_project(state: &AppState, user_id: &str, id: &str) -> Result<Project, (StatusCode, String)> {
+    sqlx::query_as::<Sqlite, Project>(&format!(
+        "SELECT {} FROM projects WHERE id = ? AND user_id = ?",
+        PROJECT_COLUMNS
+    ))
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "Project not found".to_string()))
+}
@@ -0,0 +1,122 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use super::{tags, AppState};
+use crate::auth::AuthenticatedUser;
+use crate::dates;
+use crate::groq::GroqClient;
+use crate::models::*;
+use crate::quick_add::{parse, ParsedTask};
+use crate::recurrence::normalize_rule;
+
+const MAX_QUICK_ADD_LENGTH: usize = 500;
+
+/// What the AI is asked to reply with.
+#[derive(Debug, Deserialize)]
+struct AiTask {
+    title: String,
+    due_at: Option<String>,
+    recurrence: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    priority: Option<Priority>,
+}
+
+/// Has the AI read the text the same way the parser does. Its dates are
+/// read in `tz` like any local time the API is given.
+async fn ai_parse(client: &GroqClient, text: &str, now: DateTime<Utc>, tz: Tz) -> Result<ParsedTask, String> {
+    let system = format!(
+        "You turn a short note into a task. Reply with one JSON object and nothing else:
+{{\"title\": string, \"due_at\": local time as \"YYYY-MM-DDTHH:MM\" or null, \"recurrence\": an RFC 5545 RRULE \
+without the \"RRULE:\" prefix, at most daily, or null, \"tags\": [string], \"priority\": \"low\", \"medium\", \
+\"high\", \"urgent\" or null}}.
+Leave dates, recurrence, tags and priority out of the title. A date without a time is due at 23:59. It is now {}.",
+        dates::describe(now, tz)
+    );
+    let reply = client.chat_with_system(&system, text).await?;
+    let json = reply
+        .find('{')
+        .zip(reply.rfind('}'))
+        .filter(|(start, end)| start < end)
+        .map(|(start, end)| &reply[start..=end])
+        .ok_or_else(|| "The AI did not reply with JSON".to_string())?;
+    let task: AiTask = serde_json::from_str(json).map_err(|e| format!("Unreadable AI reply: {}", e))?;
+
+    Ok(ParsedTask {
+        title: task.title,
+        due_at: task.due_at.as_deref().map(|due_at| dates::parse_datetime(due_at, tz)).transpose()?,
+        recurrence: task.recurrence.filter(|rule| !rule.trim().is_empty()),
+        tags: task.tags,
+        priority: task.priority,
+        unparsed: Vec::new(),
+    })
+}
+
+/// Checks a parsed task the way `POST /api/tasks` would.
+fn preview(parsed: ParsedTask, tz: Tz, time_zone: Option<String>, source: ParseSource) -> Result<TaskPreview, String> {
+    let title = parsed.title.trim().to_string();
+    if title.is_empty() {
+        return Err("Nothing is left for the title".to_string());
+    }
+    let tags = tags::normalize_tags(&parsed.tags).map_err(|(_, e)| e)?;
+    let recurrence = match parsed.recurrence.as_deref() {
+        Some(rule) => {
+            let dtstart = parsed.due_at.ok_or_else(|| "Recurring tasks need a due date".to_string())?;
+            Some(normalize_rule(rule, dtstart, tz)?)
+        }
+        None => None,
+    };
+    Ok(TaskPreview {
+        title,
+        due_at: parsed.due_at,
+        time_zone,
+        recurrence,
+        tags,
+        priority: parsed.priority,
+        source,
+        unparsed: parsed.unparsed,
+    })
+}
+
+/// Reads a task from one line of text without saving it. The rule-based
+/// parser goes first; when it leaves date-like words in the title and AI
+/// features are enabled, the AI gets a try, falling back to the parser's
+/// reading if its answer doesn't hold up.
+pub async fn parse_task(
+    State(state): State<AppState>,
+    AuthenticatedUser(_user): AuthenticatedUser,
+    Json(payload): Json<ParseTaskRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let text = payload.text.trim();
+    if text.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Text must not be empty".to_string()));
+    }
+    if text.chars().count() > MAX_QUICK_ADD_LENGTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Quick add text is limited to {} characters", MAX_QUICK_ADD_LENGTH),
+        ));
+    }
+    let tz = match payload.time_zone.as_deref() {
+        Some(name) => dates::parse_time_zone(name).map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+        None => Tz::UTC,
+    };
+    let time_zone = payload.time_zone.as_ref().map(|_| tz.name().to_string());
+
+    let now = Utc::now();
+    let parsed = parse(text, now, tz);
+    if let Some(client) = state.groq.as_ref().filter(|_| payload.ai && !parsed.unparsed.is_empty()) {
+        let ai_preview = ai_parse(client, text, now, tz)
+            .await
+            .and_then(|ai| preview(ai, tz, time_zone.clone(), ParseSource::Ai));
+        match ai_preview {
+            Ok(ai_preview) => return Ok(Json(ai_preview)),
+            Err(e) => tracing::warn!("AI quick add failed, using the parser's reading: {}", e),
+        }
+    }
+
+    let preview = preview(parsed, tz, time_zone, ParseSource::Parser).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    Ok(Json(preview))
+}
@@ -0,0 +1,419 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use sqlx::{types::Json as SqlJson, Sqlite, SqliteConnection};
+use uuid::Uuid;
+
+use super::{fetch_task, ordering, workflow, AppState};
+use crate::auth::AuthenticatedUser;
+use crate::dates;
+use crate::models::*;
+use crate::recurrence::{normalize_rule, Series, MAX_UPCOMING};
+use crate::search_index::{self, SearchKind};
+
+const DEFAULT_UPCOMING: u16 = 10;
+
+/// The instant a task's occurrence falls on: its due date, else its start.
+fn occurrence_of(task: &Task) -> Option<DateTime<Utc>> {
+    task.due_at.or(task.start_at)
+}
+
+fn series_zone(series: &TaskSeries) -> Tz {
+    dates::parse_time_zone(&series.time_zone).unwrap_or(Tz::UTC)
+}
+
+fn series_of(series: &TaskSeries) -> Series<'_> {
+    Series {
+        rule: &series.rrule,
+        dtstart: series.dtstart,
+        tz: series_zone(series),
+        exdates: &series.exdates,
+    }
+}
+
+/// Starts a new series at the task's occurrence, its due date or else its
+/// start date, and links the task to it.
+pub async fn start_series(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    task_id: &str,
+    dates: (Option<DateTime<Utc>>, Option<DateTime<Utc>>),
+    time_zone: Option<&str>,
+    rule: &str,
+) -> Result<(), (StatusCode, String)> {
+    let (start_at, due_at) = dates;
+    let dtstart = due_at.or(start_at).ok_or_else(|| {
+        (StatusCode::BAD_REQUEST, "Recurring tasks need a due date or a start date".to_string())
+    })?;
+    let time_zone = time_zone.unwrap_or("UTC");
+    let tz = dates::parse_time_zone(time_zone).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let rule = normalize_rule(rule, dtstart, tz).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let series_id = Uuid::new_v4().to_string();
+
+    sqlx::query("INSERT INTO task_series (id, user_id, rrule, dtstart, time_zone) VALUES (?, ?, ?, ?, ?)")
+        .bind(&series_id)
+        .bind(user_id)
+        .bind(&rule)
+        .bind(dtstart)
+        .bind(time_zone)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query("UPDATE tasks SET series_id = ? WHERE id = ? AND user_id = ?")
+        .bind(&series_id)
+        .bind(task_id)
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(())
+}
+
+/// Called when an occurrence is completed: creates the task for the next
+/// occurrence, unless the series has ended, run out, or already has it.
+pub async fn create_next_occurrence(conn: &mut SqliteConnection, task: &Task) -> Result<(), (StatusCode, String)> {
+    let (Some(series_id), Some(current)) = (task.series_id.as_deref(), occurrence_of(task)) else {
+        return Ok(());
+    };
+    let Some(series) = fetch_series(conn, &task.user_id, series_id).await? else {
+        return Ok(());
+    };
+    if series.ended_at.is_some() {
+        return Ok(());
+    }
+
+    // Completing, reopening and completing again must not create a second copy
+    let already_created: bool = sqlx::query_scalar(
+        "SELECT COUNT(*) > 0 FROM tasks WHERE series_id = ? AND user_id = ? AND COALESCE(due_at, start_at) > ?",
+    )
+    .bind(series_id)
+    .bind(&task.user_id)
+    .bind(current)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if already_created {
+        return Ok(());
+    }
+
+    let Some(next) = series_of(&series).next_after(current).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))? else {
+        return Ok(());
+    };
+    let (start_at, due_at) = shift_dates(task, next);
+    let id = Uuid::new_v4().to_string();
+    let initial_state = workflow::first_state(conn, &task.user_id, StateCategory::Open).await?;
+    let position = ordering::next_position(conn, &task.user_id).await?;
+
+    sqlx::query(
+        "INSERT INTO tasks (id, user_id, title, notes, description, state_id, position, priority, start_at, due_at,
+                            time_zone, project_id, parent_id, series_id)
+         SELECT ?, user_id, title, notes, description, ?, ?, priority, ?, ?, time_zone, project_id, parent_id, series_id
+         FROM tasks WHERE id = ?",
+    )
+    .bind(&id)
+    .bind(&initial_state.id)
+    .bind(&position)
+    .bind(start_at)
+    .bind(due_at)
+    .bind(&task.id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query("INSERT INTO task_tags (task_id, tag_id) SELECT ?, tag_id FROM task_tags WHERE task_id = ?")
+        .bind(&id)
+        .bind(&task.id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    search_index::sync(conn, SearchKind::Task, &id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(())
+}
+
+/// The task's dates moved to another occurrence, keeping the gap between
+/// its start and due dates.
+fn shift_dates(task: &Task, to: DateTime<Utc>) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+    match task.due_at {
+        Some(due_at) => (task.start_at.map(|start_at| to - (due_at - start_at)), Some(to)),
+        None => (Some(to), None),
+    }
+}
+
+async fn fetch_series(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    series_id: &str,
+) -> Result<Option<TaskSeries>, (StatusCode, String)> {
+    sqlx::query_as::<Sqlite, TaskSeries>("SELECT * FROM task_series WHERE id = ? AND user_id = ?")
+        .bind(series_id)
+        .bind(user_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+async fn recurring_task(state: &AppState, user_id: &str, id: &str) -> Result<(Task, TaskSeries), (StatusCode, String)> {
+    let task = fetch_task(&state.db, user_id, id).await?;
+    let mut conn = state.db.acquire().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let series = match task.series_id.as_deref() {
+        Some(series_id) => fetch_series(&mut conn, user_id, series_id).await?,
+        None => None,
+    };
+    let series = series.ok_or_else(|| (StatusCode::NOT_FOUND, "Task does not recur".to_string()))?;
+    Ok((task, series))
+}
+
+fn recurrence_info(task: &Task, series: TaskSeries, limit: u16) -> Result<RecurrenceInfo, (StatusCode, String)> {
+    let upcoming = match (series.ended_at, occurrence_of(task)) {
+        (None, Some(current)) => series_of(&series)
+            .upcoming(current, limit.min(MAX_UPCOMING))
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?,
+        _ => Vec::new(),
+    };
+
+    Ok(RecurrenceInfo {
+        series_id: series.id,
+        rule: series.rrule,
+        time_zone: series.time_zone,
+        starts_at: series.dtstart,
+        ended_at: series.ended_at,
+        skipped: series.exdates.0,
+        upcoming,
+    })
+}
+
+// --- Recurrence Handlers ---
+
+pub async fn get_recurrence(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+    Query(query): Query<RecurrenceQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let (task, series) = recurring_task(&state, &user.id, &id).await?;
+    Ok(Json(recurrence_info(&task, series, query.limit.unwrap_or(DEFAULT_UPCOMING))?))
+}
+
+/// Makes the task recur, or changes its rule. Either way a new series starts
+/// at this task's occurrence; the old one ends.
+pub async fn set_recurrence(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+    Json(payload): Json<RecurrenceRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let task = fetch_task(&state.db, &user.id, &id).await?;
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if let Some(series_id) = task.series_id.as_deref() {
+        end_series(&mut tx, &user.id, series_id).await?;
+    }
+    start_series(
+        &mut tx,
+        &user.id,
+        &task.id,
+        (task.start_at, task.due_at),
+        task.time_zone.as_deref(),
+        &payload.rule,
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let (task, series) = recurring_task(&state, &user.id, &id).await?;
+    Ok(Json(recurrence_info(&task, series, DEFAULT_UPCOMING)?))
+}
+
+/// Ends the series: this task stays, but no further occurrences follow it.
+pub async fn end_recurrence(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let (_, series) = recurring_task(&state, &user.id, &id).await?;
+    let mut conn = state.db.acquire().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    end_series(&mut conn, &user.id, &series.id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Skips an upcoming occurrence given as `at`. Without `at`, skips the
+/// task's own occurrence and moves the task on to the next one.
+pub async fn skip_occurrence(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+    payload: Option<Json<SkipOccurrenceRequest>>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let (task, series) = recurring_task(&state, &user.id, &id).await?;
+    if series.ended_at.is_some() {
+        return Err((StatusCode::CONFLICT, "The series has ended".to_string()));
+    }
+    let current = occurrence_of(&task)
+        .ok_or_else(|| (StatusCode::CONFLICT, "Task has no date to recur from".to_string()))?;
+
+    let Json(payload) = payload.unwrap_or_default();
+    let skipped = match payload.at.as_deref() {
+        Some(at) => dates::parse_datetime(at, series_zone(&series)).map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+        None => current,
+    };
+
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if skipped == current {
+        if task.completed {
+            return Err((StatusCode::CONFLICT, "This occurrence is already completed".to_string()));
+        }
+        let next = series_of(&series)
+            .next_after(current)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+            .ok_or_else(|| {
+                (StatusCode::CONFLICT, "This is the last occurrence; end the series instead".to_string())
+            })?;
+        let (start_at, due_at) = shift_dates(&task, next);
+        sqlx::query("UPDATE tasks SET start_at = ?, due_at = ? WHERE id = ? AND user_id = ?")
+            .bind(start_at)
+            .bind(due_at)
+            .bind(&task.id)
+            .bind(&user.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    } else {
+        let upcoming = series_of(&series)
+            .next_after(skipped - chrono::Duration::seconds(1))
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        if skipped < current || upcoming != Some(skipped) {
+            return Err((StatusCode::BAD_REQUEST, "That is not an upcoming occurrence of this task".to_string()));
+        }
+    }
+
+    let mut exdates = series.exdates.0.clone();
+    exdates.push(skipped);
+    sqlx::query("UPDATE task_series SET exdates = ? WHERE id = ? AND user_id = ?")
+        .bind(SqlJson(exdates))
+        .bind(&series.id)
+        .bind(&user.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(fetch_task(&state.db, &user.id, &id).await?))
+}
+
+async fn end_series(conn: &mut SqliteConnection, user_id: &str, series_id: &str) -> Result<(), (StatusCode, String)> {
+    sqlx::query("UPDATE task_series SET ended_at = COALESCE(ended_at, ?) WHERE id = ? AND user_id = ?")
+        .bind(Utc::now())
+        .bind(series_id)
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestApp;
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    /// The user's tasks in the series of `id`, by date.
+    async fn occurrences(app: &TestApp, id: &str) -> Vec<(String, bool)> {
+        sqlx::query_as(
+            "SELECT due_at, completed FROM tasks
+             WHERE series_id = (SELECT series_id FROM tasks WHERE id = ?) ORDER BY due_at",
+        )
+        .bind(id)
+        .fetch_all(&app.state.db)
+        .await
+        .unwrap()
+    }
+
+    async fn weekly_task(app: &TestApp, token: &str) -> String {
+        let body = json!({
+            "title": "Water plants",
+            "due_at": "2030-01-07T09:00",
+            "time_zone": "Europe/Berlin",
+            "recurrence": "FREQ=WEEKLY;BYDAY=MO",
+            "tags": ["home"],
+        });
+        app.create_task(token, body).await
+    }
+
+    async fn complete(app: &TestApp, token: &str, id: &str, completed: bool) {
+        let response = app.patch(&format!("/api/tasks/{}", id), token, json!({ "completed": completed })).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    }
+
+    #[tokio::test]
+    async fn completing_an_occurrence_creates_the_next_once() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        let id = weekly_task(&app, &token).await;
+
+        complete(&app, &token, &id, true).await;
+        complete(&app, &token, &id, false).await;
+        complete(&app, &token, &id, true).await;
+
+        let expected = [
+            ("2030-01-07T08:00:00+00:00".to_string(), true),
+            ("2030-01-14T08:00:00+00:00".to_string(), false),
+        ];
+        assert_eq!(occurrences(&app, &id).await, expected);
+        let next = app.get("/api/tasks?completed=false", &token).await;
+        assert_eq!(next.body[0]["title"], "Water plants");
+        assert_eq!(next.body[0]["tags"], json!(["home"]));
+    }
+
+    #[tokio::test]
+    async fn skipping_moves_the_task_or_drops_an_upcoming_date() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        let id = weekly_task(&app, &token).await;
+
+        // Skip a later date, then the task's own
+        let skipped = app.post(&format!("/api/tasks/{}/skip", id), &token, json!({ "at": "2030-01-14T09:00" })).await;
+        assert_eq!(skipped.status, StatusCode::OK, "{}", skipped.body);
+        let moved = app.post(&format!("/api/tasks/{}/skip", id), &token, json!({})).await;
+        assert_eq!(moved.status, StatusCode::OK, "{}", moved.body);
+        assert_eq!(moved.body["due_at"], "2030-01-21T08:00:00Z");
+
+        let info = app.get(&format!("/api/tasks/{}/recurrence?limit=2", id), &token).await;
+        assert_eq!(info.body["skipped"], json!(["2030-01-14T08:00:00Z", "2030-01-07T08:00:00Z"]));
+        assert_eq!(info.body["upcoming"], json!(["2030-01-28T08:00:00Z", "2030-02-04T08:00:00Z"]));
+
+        // Only dates of the series can be skipped
+        let wrong = app.post(&format!("/api/tasks/{}/skip", id), &token, json!({ "at": "2030-01-29T09:00" })).await;
+        assert_eq!(wrong.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn an_ended_series_creates_nothing_more() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        let id = weekly_task(&app, &token).await;
+
+        let ended = app.delete(&format!("/api/tasks/{}/recurrence", id), &token).await;
+        assert_eq!(ended.status, StatusCode::NO_CONTENT);
+        let info = app.get(&format!("/api/tasks/{}/recurrence", id), &token).await;
+        assert!(info.body["ended_at"].is_string());
+        assert_eq!(info.body["upcoming"], json!([]));
+
+        complete(&app, &token, &id, true).await;
+        assert_eq!(occurrences(&app, &id).await.len(), 1);
+        let skipped = app.post(&format!("/api/tasks/{}/skip", id), &token, json!({})).await;
+        assert_eq!(skipped.status, StatusCode::CONFLICT);
+    }
+}
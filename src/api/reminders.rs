@@ -0,0 +1,274 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use sqlx::{QueryBuilder, Sqlite};
+use uuid::Uuid;
+
+use super::{fetch_task, AppState};
+use crate::auth::AuthenticatedUser;
+use crate::dates;
+use crate::models::*;
+use crate::reminders::check_webhook_url;
+
+const MAX_MESSAGE_LENGTH: usize = 500;
+const DEFAULT_NOTIFICATIONS: i64 = 50;
+const MAX_NOTIFICATIONS: i64 = 200;
+
+const REMINDER_COLUMNS: &str = "id, task_id, message, remind_at, channel, webhook_url, status, attempts,
+    last_error, sent_at, created_at";
+
+fn normalize_message(message: Option<String>) -> Result<Option<String>, (StatusCode, String)> {
+    let Some(message) = message.map(|m| m.trim().to_string()).filter(|m| !m.is_empty()) else {
+        return Ok(None);
+    };
+    if message.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Reminder messages are limited to {} characters", MAX_MESSAGE_LENGTH),
+        ));
+    }
+    Ok(Some(message))
+}
+
+/// Webhook reminders need a URL to call; the other channels take none.
+async fn check_channel(channel: ReminderChannel, webhook_url: Option<&str>) -> Result<(), (StatusCode, String)> {
+    match (channel, webhook_url) {
+        (ReminderChannel::Webhook, Some(url)) => check_webhook_url(url).await.map_err(|e| (StatusCode::BAD_REQUEST, e)),
+        (ReminderChannel::Webhook, None) => {
+            Err((StatusCode::BAD_REQUEST, "webhook_url is required for the webhook channel".to_string()))
+        }
+        (_, Some(_)) => Err((StatusCode::BAD_REQUEST, "webhook_url only applies to the webhook channel".to_string())),
+        (_, None) => Ok(()),
+    }
+}
+
+/// Reads `remind_at` in the given zone, else the task's, else UTC.
+fn parse_remind_at(
+    input: &str,
+    time_zone: Option<&str>,
+    task: Option<&Task>,
+) -> Result<DateTime<Utc>, (StatusCode, String)> {
+    let zone = match time_zone.or(task.and_then(|task| task.time_zone.as_deref())) {
+        Some(name) => dates::parse_time_zone(name).map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+        None => Tz::UTC,
+    };
+    dates::parse_datetime(input, zone).map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+// --- Reminder Handlers ---
+
+pub async fn list_reminders(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<ReminderListQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut builder = QueryBuilder::<Sqlite>::new(format!("SELECT {} FROM reminders WHERE user_id = ", REMINDER_COLUMNS));
+    builder.push_bind(&user.id);
+    if let Some(status) = query.status {
+        builder.push(" AND status = ").push_bind(status);
+    }
+    if let Some(task_id) = query.task_id {
+        builder.push(" AND task_id = ").push_bind(task_id);
+    }
+    builder.push(" ORDER BY remind_at, created_at");
+
+    let reminders = builder
+        .build_query_as::<Reminder>()
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(reminders))
+}
+
+/// Reminders set in the past go out on the scheduler's next run.
+pub async fn create_reminder(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(payload): Json<CreateReminderRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let task = match payload.task_id.as_deref() {
+        Some(task_id) => Some(fetch_task(&state.db, &user.id, task_id).await?),
+        None => None,
+    };
+    let message = normalize_message(payload.message)?;
+    if task.is_none() && message.is_none() {
+        return Err((StatusCode::BAD_REQUEST, "Reminders without a task need a message".to_string()));
+    }
+    check_channel(payload.channel, payload.webhook_url.as_deref()).await?;
+    let remind_at = parse_remind_at(&payload.remind_at, payload.time_zone.as_deref(), task.as_ref())?;
+
+    let id = Uuid::new_v4().to_string();
+    sqlx::query(
+        "INSERT INTO reminders (id, user_id, task_id, message, remind_at, channel, webhook_url, next_attempt_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(&user.id)
+    .bind(&payload.task_id)
+    .bind(&message)
+    .bind(remind_at)
+    .bind(payload.channel)
+    .bind(&payload.webhook_url)
+    .bind(remind_at)
+    .execute(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(fetch_reminder(&state, &user.id, &id).await?)))
+}
+
+pub async fn get_reminder(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    Ok(Json(fetch_reminder(&state, &user.id, &id).await?))
+}
+
+/// Any change schedules the reminder afresh, so a sent or failed reminder
+/// can be reused by giving it a new time.
+pub async fn update_reminder(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateReminderRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let reminder = fetch_reminder(&state, &user.id, &id).await?;
+    let task = match reminder.task_id.as_deref() {
+        Some(task_id) => Some(fetch_task(&state.db, &user.id, task_id).await?),
+        None => None,
+    };
+
+    let message = match payload.message {
+        Some(message) => normalize_message(message)?,
+        None => reminder.message,
+    };
+    if task.is_none() && message.is_none() {
+        return Err((StatusCode::BAD_REQUEST, "Reminders without a task need a message".to_string()));
+    }
+    let channel = payload.channel.unwrap_or(reminder.channel);
+    // Switching away from webhooks drops the URL unless one is given
+    let webhook_url = match payload.webhook_url {
+        Some(url) => url,
+        None if channel == ReminderChannel::Webhook => reminder.webhook_url,
+        None => None,
+    };
+    check_channel(channel, webhook_url.as_deref()).await?;
+    let remind_at = match payload.remind_at.as_deref() {
+        Some(input) => parse_remind_at(input, payload.time_zone.as_deref(), task.as_ref())?,
+        None => reminder.remind_at,
+    };
+
+    sqlx::query(
+        "UPDATE reminders SET message = ?, remind_at = ?, channel = ?, webhook_url = ?, status = 'pending',
+             attempts = 0, next_attempt_at = ?, last_error = NULL, sent_at = NULL
+         WHERE id = ? AND user_id = ?",
+    )
+    .bind(&message)
+    .bind(remind_at)
+    .bind(channel)
+    .bind(&webhook_url)
+    .bind(remind_at)
+    .bind(&id)
+    .bind(&user.id)
+    .execute(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(fetch_reminder(&state, &user.id, &id).await?))
+}
+
+pub async fn delete_reminder(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let result = sqlx::query("DELETE FROM reminders WHERE id = ? AND user_id = ?")
+        .bind(&id)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "Reminder not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn fetch_reminder(state: &AppState, user_id: &str, id: &str) -> Result<Reminder, (StatusCode, String)> {
+    sqlx::query_as::<Sqlite, Reminder>(&format!(
+        "SELECT {} FROM reminders WHERE id = ? AND user_id = ?",
+        REMINDER_COLUMNS
+    ))
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "Reminder not found".to_string()))
+}
+
+// --- Notification Handlers ---
+
+/// Newest first.
+pub async fn list_notifications(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<NotificationListQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let unread_filter = if query.unread { " AND read_at IS NULL" } else { "" };
+    let notifications = sqlx::query_as::<Sqlite, Notification>(&format!(
+        "SELECT id, reminder_id, title, body, read_at, created_at FROM notifications
+         WHERE user_id = ?{} ORDER BY created_at DESC LIMIT ?",
+        unread_filter
+    ))
+    .bind(&user.id)
+    .bind(query.limit.unwrap_or(DEFAULT_NOTIFICATIONS).clamp(1, MAX_NOTIFICATIONS))
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(notifications))
+}
+
+pub async fn mark_notification_read(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let result = sqlx::query("UPDATE notifications SET read_at = COALESCE(read_at, ?) WHERE id = ? AND user_id = ?")
+        .bind(Utc::now())
+        .bind(&id)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "Notification not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn mark_all_notifications_read(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    sqlx::query("UPDATE notifications SET read_at = ? WHERE user_id = ? AND read_at IS NULL")
+        .bind(Utc::now())
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,76 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use sqlx::{QueryBuilder, Sqlite};
+
+use super::AppState;
+use crate::auth::AuthenticatedUser;
+use crate::models::*;
+use crate::search_index::{self, SearchKind, MATCH_END, MATCH_START};
+
+const DEFAULT_RESULTS: i64 = 20;
+const MAX_RESULTS: i64 = 100;
+/// Words around a match in a snippet.
+const SNIPPET_WORDS: i64 = 16;
+
+/// Ranks the user's tasks, expenses and chat messages against `q`, with
+/// matches in titles weighing more than matches in notes.
+pub async fn search(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<SearchQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let fts_query = search_index::match_query(&query.q)
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "q must not be empty".to_string()))?;
+    let kinds = match query.kind.as_deref() {
+        Some(names) => names
+            .split(',')
+            .map(|name| {
+                SearchKind::parse(name.trim()).ok_or_else(|| {
+                    (StatusCode::BAD_REQUEST, format!("Unknown type '{}', expected task, expense or chat", name))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => SearchKind::ALL.to_vec(),
+    };
+
+    let mut builder = QueryBuilder::<Sqlite>::new("SELECT kind, item_id AS id, highlight(search_index, 0, ");
+    builder
+        .push_bind(MATCH_START)
+        .push(", ")
+        .push_bind(MATCH_END)
+        .push(") AS title, snippet(search_index, 1, ")
+        .push_bind(MATCH_START)
+        .push(", ")
+        .push_bind(MATCH_END)
+        .push(", '…', ")
+        .push_bind(SNIPPET_WORDS)
+        .push(") AS snippet, -bm25(search_index, 5.0, 1.0) AS score, created_at FROM search_index WHERE search_index MATCH ")
+        .push_bind(fts_query)
+        .push(" AND user_id = ")
+        .push_bind(&user.id)
+        .push(" AND kind IN (");
+    let mut separated = builder.separated(", ");
+    for kind in kinds {
+        separated.push_bind(kind.as_str());
+    }
+    builder
+        .push(") ORDER BY score DESC LIMIT ")
+        .push_bind(query.limit.unwrap_or(DEFAULT_RESULTS).clamp(1, MAX_RESULTS));
+
+    let mut results = builder
+        .build_query_as::<SearchResult>()
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    for result in &mut results {
+        result.title = search_index::render_highlights(&result.title);
+        result.snippet = search_index::render_highlights(&result.snippet);
+    }
+
+    Ok(Json(results))
+}
@@ -0,0 +1,66 @@
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use sqlx::Sqlite;
+use std::net::SocketAddr;
+
+use super::AppState;
+use crate::auth::{record_security_event, revoke_session, AuthSession};
+use crate::models::*;
+use crate::throttle::client_ip;
+
+// --- Session Handlers ---
+// Like token management, this needs a login session rather than an API token.
+
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    session: AuthSession,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let sessions = sqlx::query_as::<Sqlite, SessionInfo>(
+        "SELECT id, user_agent, ip, created_at, last_seen_at, expires_at, id = ? AS current FROM sessions
+         WHERE user_id = ? AND revoked_at IS NULL AND expires_at > ?
+         ORDER BY COALESCE(last_seen_at, created_at) DESC",
+    )
+    .bind(&session.session_id)
+    .bind(&session.user.id)
+    .bind(chrono::Utc::now())
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(sessions))
+}
+
+/// Logs out one device. Revoking the current session works like `/api/auth/logout`.
+pub async fn revoke_session_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    session: AuthSession,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let revoked = revoke_session(&state.db, &session.user.id, &id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    if !revoked {
+        return Err((StatusCode::NOT_FOUND, "Session not found".to_string()));
+    }
+
+    if id != session.session_id {
+        record_security_event(
+            &state.db,
+            &session.user.id,
+            "session_revoked",
+            Some(&client_ip(&addr, &headers)),
+            &format!("Signed out session {}", id),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,344 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use sqlx::{Sqlite, SqlitePool};
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+use super::{auth_response, cancel_scheduled_deletion, two_factor, AppState};
+use crate::auth::{
+    create_session, generate_token, hash_token, is_bootstrap_admin, record_security_event, SessionMetadata, ROLE_ADMIN,
+    ROLE_USER,
+};
+use crate::models::*;
+use crate::oidc::IdTokenClaims;
+use crate::throttle::client_ip;
+
+/// Time the user has to finish signing in at the identity provider.
+const LOGIN_TTL_MINUTES: i64 = 10;
+
+pub async fn sso_config(State(state): State<AppState>) -> impl IntoResponse {
+    Json(SsoConfig {
+        enabled: state.oidc.is_some(),
+        provider_name: state.oidc.map(|client| client.provider_name),
+    })
+}
+
+/// Starts a login at the identity provider. The frontend sends the browser
+/// to the returned URL; the provider redirects back with `code` and `state`.
+pub async fn start_sso(State(state): State<AppState>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let client = state.oidc.ok_or_else(|| (StatusCode::NOT_FOUND, "Single sign-on is not configured".to_string()))?;
+
+    let login_state = generate_token();
+    let nonce = generate_token();
+    let code_verifier = generate_token();
+    let now = chrono::Utc::now();
+
+    let authorization_url = client
+        .authorization_url(&login_state, &nonce, &code_verifier)
+        .await
+        .map_err(|e| {
+            tracing::error!("OIDC discovery failed: {}", e);
+            (StatusCode::BAD_GATEWAY, "The identity provider is unavailable".to_string())
+        })?;
+
+    sqlx::query("DELETE FROM oidc_logins WHERE expires_at <= ?")
+        .bind(now)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query("INSERT INTO oidc_logins (state_hash, code_verifier, nonce, expires_at) VALUES (?, ?, ?, ?)")
+        .bind(hash_token(&login_state))
+        .bind(&code_verifier)
+        .bind(&nonce)
+        .bind(now + chrono::Duration::minutes(LOGIN_TTL_MINUTES))
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(SsoStartResponse { authorization_url }))
+}
+
+/// Finishes a login: redeems the code, then signs in the user linked to the
+/// provider identity, linking or creating one by verified email if needed.
+/// Accounts whose own address was never confirmed are not linked.
+pub async fn finish_sso(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<SsoCallbackRequest>,
+) -> Result<Response, (StatusCode, String)> {
+    let client = state.oidc.ok_or_else(|| (StatusCode::NOT_FOUND, "Single sign-on is not configured".to_string()))?;
+    let ip = client_ip(&addr, &headers);
+
+    // Each state is good for exactly one attempt
+    let pending: Option<(String, String)> = sqlx::query_as(
+        "DELETE FROM oidc_logins WHERE state_hash = ? AND expires_at > ? RETURNING code_verifier, nonce",
+    )
+    .bind(hash_token(&payload.state))
+    .bind(chrono::Utc::now())
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let (code_verifier, nonce) =
+        pending.ok_or_else(|| (StatusCode::BAD_REQUEST, "Unknown or expired login, please start again".to_string()))?;
+
+    let claims = client.exchange_code(&payload.code, &code_verifier, &nonce).await.map_err(|e| {
+        tracing::warn!("OIDC login failed: {}", e);
+        (StatusCode::UNAUTHORIZED, "The identity provider did not confirm the login".to_string())
+    })?;
+
+    let user = find_or_create_user(&state.db, &claims, &ip).await?;
+
+    if user.disabled_at.is_some() {
+        return Err((StatusCode::FORBIDDEN, "This account has been disabled".to_string()));
+    }
+
+    // Single sign-on replaces the password, not the second factor
+    if user.totp_enabled {
+        let challenge = two_factor::start_challenge(&state.db, &user.id).await?;
+        return Ok(Json(LoginResponse::TwoFactorRequired(challenge)).into_response());
+    }
+
+    let user = cancel_scheduled_deletion(&state.db, user, &ip).await?;
+
+    let (session_id, refresh_token) = create_session(&state.db, &user.id, &SessionMetadata::new(&ip, &headers))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(LoginResponse::Authenticated(Box::new(auth_response(user, &session_id, refresh_token)?))).into_response())
+}
+
+async fn find_or_create_user(pool: &SqlitePool, claims: &IdTokenClaims, ip: &str) -> Result<User, (StatusCode, String)> {
+    let now = chrono::Utc::now();
+
+    let linked = sqlx::query_as::<Sqlite, User>(
+        "SELECT users.* FROM users JOIN user_identities ON user_identities.user_id = users.id
+         WHERE user_identities.issuer = ? AND user_identities.subject = ?",
+    )
+    .bind(&claims.iss)
+    .bind(&claims.sub)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if let Some(user) = linked {
+        sqlx::query("UPDATE user_identities SET last_login_at = ? WHERE issuer = ? AND subject = ?")
+            .bind(now)
+            .bind(&claims.iss)
+            .bind(&claims.sub)
+            .execute(pool)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        return Ok(user);
+    }
+
+    // Linking by email is only safe when the provider vouches for the address
+    let email = match (&claims.email, claims.email_verified) {
+        (Some(email), true) => email.trim().to_string(),
+        _ => {
+            return Err((
+                StatusCode::FORBIDDEN,
+                "The identity provider did not supply a verified email address".to_string(),
+            ))
+        }
+    };
+
+    let existing = sqlx::query_as::<Sqlite, User>("SELECT * FROM users WHERE lower(email) = lower(?)")
+        .bind(&email)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Anyone can register an address they don't own. Linking such an account
+    // would hand its owner's SSO login to whoever set its password.
+    if existing.as_ref().is_some_and(|user| user.email_verified_at.is_none()) {
+        return Err((
+            StatusCode::CONFLICT,
+            "An account with this email exists but its address was never confirmed. Confirm it or reset its \
+             password, then sign in with single sign-on again."
+                .to_string(),
+        ));
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let user_id = match &existing {
+        Some(user) => user.id.clone(),
+        None => {
+            let id = Uuid::new_v4().to_string();
+            let role = if is_bootstrap_admin(&email) { ROLE_ADMIN } else { ROLE_USER };
+            sqlx::query("INSERT INTO users (id, email, password_hash, role, email_verified_at) VALUES (?, ?, NULL, ?, ?)")
+                .bind(&id)
+                .bind(&email)
+                .bind(role)
+                .bind(now)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            id
+        }
+    };
+
+    sqlx::query(
+        "INSERT INTO user_identities (id, user_id, issuer, subject, email, last_login_at) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&user_id)
+    .bind(&claims.iss)
+    .bind(&claims.sub)
+    .bind(&email)
+    .bind(now)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if existing.is_some() {
+        record_security_event(pool, &user_id, "sso_linked", Some(ip), &format!("Single sign-on linked via {}", claims.iss))
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    }
+
+    sqlx::query_as::<Sqlite, User>("SELECT * FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::hash_password;
+    use crate::models::User;
+    use crate::oidc::mock::{Login, MockIdp};
+    use crate::test_support::{TestApp, TestResponse};
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+    use sqlx::Sqlite;
+
+    async fn test_app(idp: &MockIdp) -> TestApp {
+        TestApp::with_oidc(Some(idp.client())).await
+    }
+
+    /// Signs in through both endpoints as the browser would.
+    async fn sign_in(app: &TestApp, idp: &MockIdp, login: Login) -> TestResponse {
+        let started = app.request(Method::POST, "/api/auth/sso/start", None, None).await;
+        assert_eq!(started.status, StatusCode::OK, "{}", started.body);
+        let (code, state) = idp.authorize(started.body["authorization_url"].as_str().unwrap(), login);
+        let body = json!({ "code": code, "state": state });
+        app.request(Method::POST, "/api/auth/sso/callback", None, Some(body)).await
+    }
+
+    async fn identities(app: &TestApp) -> Vec<(String, String, String)> {
+        sqlx::query_as("SELECT user_id, issuer, subject FROM user_identities").fetch_all(&app.state.db).await.unwrap()
+    }
+
+    async fn insert_user(app: &TestApp, email: &str, verified: bool) -> String {
+        let password_hash = hash_password("password123");
+        sqlx::query(
+            "INSERT INTO users (id, email, password_hash, email_verified_at)
+             VALUES ('existing', ?, ?, CASE WHEN ? THEN CURRENT_TIMESTAMP END)",
+        )
+        .bind(email)
+        .bind(&password_hash)
+        .bind(verified)
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+        password_hash
+    }
+
+    #[tokio::test]
+    async fn creates_a_user_without_a_password() {
+        let idp = MockIdp::start().await;
+        let app = test_app(&idp).await;
+
+        let response = sign_in(&app, &idp, Login::new("user-1", "ann@example.com")).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert!(response.body["token"].is_string());
+        assert_eq!(response.body["user"]["email"], "ann@example.com");
+
+        let user = sqlx::query_as::<Sqlite, User>("SELECT * FROM users").fetch_one(&app.state.db).await.unwrap();
+        assert!(user.password_hash.is_none());
+        assert!(user.email_verified_at.is_some());
+        assert_eq!(identities(&app).await, [(user.id, idp.issuer().to_string(), "user-1".to_string())]);
+
+        // The identity is found again on the next login, whatever the email says by then
+        let again = sign_in(&app, &idp, Login::new("user-1", "ann@new.example")).await;
+        assert_eq!(again.status, StatusCode::OK, "{}", again.body);
+        let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users").fetch_one(&app.state.db).await.unwrap();
+        assert_eq!(users, 1);
+    }
+
+    #[tokio::test]
+    async fn links_an_existing_user_by_verified_email() {
+        let idp = MockIdp::start().await;
+        let app = test_app(&idp).await;
+        let password_hash = insert_user(&app, "Ann@Example.com", true).await;
+
+        let response = sign_in(&app, &idp, Login::new("user-1", "ann@example.com")).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(response.body["user"]["id"], "existing");
+
+        // The account's owner proved the address before, so their password stays theirs
+        let user = sqlx::query_as::<Sqlite, User>("SELECT * FROM users").fetch_one(&app.state.db).await.unwrap();
+        assert_eq!(user.password_hash.as_deref(), Some(password_hash.as_str()));
+        let expected = ("existing".to_string(), idp.issuer().to_string(), "user-1".to_string());
+        assert_eq!(identities(&app).await, [expected]);
+        let linked: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM security_events WHERE kind = 'sso_linked'")
+            .fetch_one(&app.state.db)
+            .await
+            .unwrap();
+        assert_eq!(linked, 1);
+    }
+
+    #[tokio::test]
+    async fn does_not_link_an_account_whose_email_was_never_confirmed() {
+        let idp = MockIdp::start().await;
+        let app = test_app(&idp).await;
+        // Registered by someone who doesn't own the address
+        insert_user(&app, "ann@example.com", false).await;
+
+        let response = sign_in(&app, &idp, Login::new("user-1", "ann@example.com")).await;
+        assert_eq!(response.status, StatusCode::CONFLICT, "{}", response.body);
+        assert!(identities(&app).await.is_empty());
+        let user = sqlx::query_as::<Sqlite, User>("SELECT * FROM users").fetch_one(&app.state.db).await.unwrap();
+        assert!(user.email_verified_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn refuses_an_unverified_email() {
+        let idp = MockIdp::start().await;
+        let app = test_app(&idp).await;
+        insert_user(&app, "ann@example.com", true).await;
+
+        let login = Login {
+            email_verified: false,
+            ..Login::new("user-1", "ann@example.com")
+        };
+        let response = sign_in(&app, &idp, login).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+        assert!(identities(&app).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn refuses_a_login_the_provider_did_not_confirm() {
+        let idp = MockIdp::start().await;
+        let app = test_app(&idp).await;
+
+        let login = Login {
+            forged: true,
+            ..Login::new("user-1", "ann@example.com")
+        };
+        let response = sign_in(&app, &idp, login).await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+        let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users").fetch_one(&app.state.db).await.unwrap();
+        assert_eq!(users, 0);
+    }
+}
@@ -0,0 +1,202 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection};
+use std::collections::{HashMap, HashSet};
+
+use super::{AppState, TASK_COLUMNS};
+use crate::auth::AuthenticatedUser;
+use crate::models::*;
+
+/// Levels a task tree may have, counting top-level tasks as the first.
+pub const MAX_DEPTH: i64 = 4;
+
+/// Checks that `task_id` (or a new task, when `None`) may be placed under
+/// `parent_id`: the parent must be the user's, must not be the task or one
+/// of its subtasks, and the moved subtree must stay within `MAX_DEPTH`.
+pub async fn check_parent(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    task_id: Option<&str>,
+    parent_id: &str,
+) -> Result<(), (StatusCode, String)> {
+    // The parent first, then its parent, up to the top-level task
+    let ancestors: Vec<String> = sqlx::query_scalar(
+        "WITH RECURSIVE ancestors(id, parent_id, depth) AS (
+             SELECT id, parent_id, 0 FROM tasks WHERE id = ? AND user_id = ? AND deleted_at IS NULL
+             UNION ALL
+             SELECT tasks.id, tasks.parent_id, ancestors.depth + 1
+             FROM tasks JOIN ancestors ON tasks.id = ancestors.parent_id
+         )
+         SELECT id FROM ancestors ORDER BY depth",
+    )
+    .bind(parent_id)
+    .bind(user_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if ancestors.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "Parent task not found".to_string()));
+    }
+
+    let subtree_height = match task_id {
+        Some(task_id) => {
+            if ancestors.iter().any(|id| id == task_id) {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "A task cannot be nested under itself or one of its subtasks".to_string(),
+                ));
+            }
+            subtree_height(conn, task_id).await?
+        }
+        None => 0,
+    };
+
+    // `ancestors.len()` levels above the task, plus the task's own subtree
+    if ancestors.len() as i64 + 1 + subtree_height > MAX_DEPTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Subtasks can be nested at most {} levels deep", MAX_DEPTH),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Levels below a task: 0 for a task without subtasks.
+async fn subtree_height(conn: &mut SqliteConnection, task_id: &str) -> Result<i64, (StatusCode, String)> {
+    sqlx::query_scalar(
+        "WITH RECURSIVE subtree(id, depth) AS (
+             SELECT id, 0 FROM tasks WHERE id = ?
+             UNION ALL
+             SELECT tasks.id, subtree.depth + 1 FROM tasks JOIN subtree ON tasks.parent_id = subtree.id
+         )
+         SELECT MAX(depth) FROM subtree",
+    )
+    .bind(task_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map(|height: Option<i64>| height.unwrap_or(0))
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Moves every open task below `task_id` to the user's first done state,
+/// whatever transitions the workflow allows.
+pub async fn complete_descendants(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    task_id: &str,
+) -> Result<(), (StatusCode, String)> {
+    sqlx::query(
+        "WITH RECURSIVE subtree(id) AS (
+             SELECT id FROM tasks WHERE parent_id = ? AND user_id = ? AND deleted_at IS NULL
+             UNION ALL
+             SELECT tasks.id FROM tasks JOIN subtree ON tasks.parent_id = subtree.id WHERE tasks.deleted_at IS NULL
+         )
+         UPDATE tasks SET completed = 1, state_id = (
+             SELECT id FROM workflow_states WHERE user_id = ? AND category = 'done'
+             ORDER BY position, created_at LIMIT 1)
+         WHERE id IN (SELECT id FROM subtree) AND completed = 0",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(())
+}
+
+/// Nests tasks under their parents, keeping the given order among siblings.
+/// Tasks whose parent is not in `tasks` become roots.
+pub fn build_forest(tasks: Vec<Task>) -> Vec<TaskNode> {
+    let ids: HashSet<String> = tasks.iter().map(|task| task.id.clone()).collect();
+    let mut children: HashMap<String, Vec<Task>> = HashMap::new();
+    let mut roots = Vec::new();
+
+    for task in tasks {
+        match task.parent_id.clone().filter(|parent_id| ids.contains(parent_id)) {
+            Some(parent_id) => children.entry(parent_id).or_default().push(task),
+            None => roots.push(task),
+        }
+    }
+
+    roots.into_iter().map(|task| attach_subtasks(task, &mut children)).collect()
+}
+
+fn attach_subtasks(task: Task, children: &mut HashMap<String, Vec<Task>>) -> TaskNode {
+    let subtasks = children
+        .remove(&task.id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|child| attach_subtasks(child, children))
+        .collect();
+    TaskNode { task, subtasks }
+}
+
+// --- Tree Handlers ---
+
+/// All of the user's tasks as a forest of top-level tasks and their subtasks.
+pub async fn list_task_trees(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<TaskTreeQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut builder = QueryBuilder::<Sqlite>::new(format!("SELECT {} FROM tasks WHERE deleted_at IS NULL AND user_id = ", TASK_COLUMNS));
+    builder.push_bind(&user.id);
+
+    if let Some(completed) = query.completed {
+        builder.push(" AND completed = ").push_bind(completed);
+    }
+    match query.project.as_deref() {
+        None => {}
+        Some("none") => {
+            builder.push(" AND project_id IS NULL");
+        }
+        Some(project_id) => {
+            builder.push(" AND project_id = ").push_bind(project_id.to_string());
+        }
+    }
+    builder.push(" ORDER BY position, created_at");
+
+    let tasks = builder
+        .build_query_as::<Task>()
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(build_forest(tasks)))
+}
+
+/// A task with all of its subtasks.
+pub async fn get_task_tree(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let tasks = sqlx::query_as::<Sqlite, Task>(&format!(
+        "WITH RECURSIVE subtree(id) AS (
+             SELECT id FROM tasks WHERE id = ? AND user_id = ? AND deleted_at IS NULL
+             UNION ALL
+             SELECT tasks.id FROM tasks JOIN subtree ON tasks.parent_id = subtree.id WHERE tasks.deleted_at IS NULL
+         )
+         SELECT {} FROM tasks WHERE id IN (SELECT id FROM subtree) ORDER BY position, created_at",
+        TASK_COLUMNS
+    ))
+    .bind(&id)
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    build_forest(tasks)
+        .into_iter()
+        .find(|node| node.task.id == id)
+        .map(Json)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Task not found".to_string()))
+}
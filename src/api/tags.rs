@@ -0,0 +1,166 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use sqlx::{Sqlite, SqliteConnection};
+use uuid::Uuid;
+
+use super::AppState;
+use crate::auth::AuthenticatedUser;
+use crate::models::*;
+
+const MAX_TAG_LENGTH: usize = 50;
+
+/// Trims tag names, drops a leading `#` and removes case-insensitive duplicates.
+pub fn normalize_tags(names: &[String]) -> Result<Vec<String>, (StatusCode, String)> {
+    let mut tags: Vec<String> = Vec::new();
+    for name in names {
+        let name = name.trim().trim_start_matches('#').trim();
+        if name.is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "Tag names must not be empty".to_string()));
+        }
+        if name.chars().count() > MAX_TAG_LENGTH {
+            return Err((StatusCode::BAD_REQUEST, format!("Tag names are limited to {} characters", MAX_TAG_LENGTH)));
+        }
+        if !tags.iter().any(|t| t.eq_ignore_ascii_case(name)) {
+            tags.push(name.to_string());
+        }
+    }
+    Ok(tags)
+}
+
+/// Replaces the tags on a task, creating tags that don't exist yet. Runs on
+/// the caller's connection so it can share the task's transaction.
+pub async fn set_task_tags(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    task_id: &str,
+    names: &[String],
+) -> Result<(), (StatusCode, String)> {
+    sqlx::query("DELETE FROM task_tags WHERE task_id = ?")
+        .bind(task_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    for name in names {
+        sqlx::query("INSERT INTO tags (id, user_id, name) VALUES (?, ?, ?) ON CONFLICT (user_id, name) DO NOTHING")
+            .bind(Uuid::new_v4().to_string())
+            .bind(user_id)
+            .bind(name)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        sqlx::query(
+            "INSERT OR IGNORE INTO task_tags (task_id, tag_id)
+             SELECT ?, id FROM tags WHERE user_id = ? AND name = ?",
+        )
+        .bind(task_id)
+        .bind(user_id)
+        .bind(name)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+// --- Tag Handlers ---
+
+const TAG_COLUMNS: &str = "tags.id, tags.name, tags.created_at,
+    (SELECT COUNT(*) FROM task_tags JOIN tasks ON tasks.id = task_tags.task_id
+        WHERE task_tags.tag_id = tags.id AND tasks.deleted_at IS NULL) AS task_count";
+
+pub async fn list_tags(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let tags = sqlx::query_as::<Sqlite, Tag>(&format!(
+        "SELECT {} FROM tags WHERE user_id = ? ORDER BY tags.name",
+        TAG_COLUMNS
+    ))
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(tags))
+}
+
+pub async fn create_tag(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(payload): Json<TagRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let name = normalize_tags(&[payload.name])?.remove(0);
+    let id = Uuid::new_v4().to_string();
+
+    sqlx::query("INSERT INTO tags (id, user_id, name) VALUES (?, ?, ?)")
+        .bind(&id)
+        .bind(&user.id)
+        .bind(&name)
+        .execute(&state.db)
+        .await
+        .map_err(|_| (StatusCode::CONFLICT, format!("Tag '{}' already exists", name)))?;
+
+    let tag = fetch_tag(&state, &user.id, &id).await?;
+    Ok((StatusCode::CREATED, Json(tag)))
+}
+
+/// Renames a tag on every task that carries it.
+pub async fn rename_tag(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+    Json(payload): Json<TagRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let name = normalize_tags(&[payload.name])?.remove(0);
+
+    let result = sqlx::query("UPDATE tags SET name = ? WHERE id = ? AND user_id = ?")
+        .bind(&name)
+        .bind(&id)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| (StatusCode::CONFLICT, format!("Tag '{}' already exists", name)))?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "Tag not found".to_string()));
+    }
+
+    Ok(Json(fetch_tag(&state, &user.id, &id).await?))
+}
+
+/// Deletes a tag and removes it from its tasks; the tasks themselves stay.
+pub async fn delete_tag(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let result = sqlx::query("DELETE FROM tags WHERE id = ? AND user_id = ?")
+        .bind(&id)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "Tag not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn fetch_tag(state: &AppState, user_id: &str, id: &str) -> Result<Tag, (StatusCode, String)> {
+    sqlx::query_as::<Sqlite, Tag>(&format!("SELECT {} FROM tags WHERE id = ? AND user_id = ?", TAG_COLUMNS))
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Tag not found".to_string()))
+}
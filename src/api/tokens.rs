@@ -0,0 +1,159 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use sqlx::Sqlite;
+use uuid::Uuid;
+
+use super::AppState;
+use crate::auth::{generate_token, hash_token, AuthSession, API_TOKEN_PREFIX, API_TOKEN_SCOPES};
+use crate::models::*;
+
+// --- Personal Access Token Handlers ---
+// Token management needs a login session; an API token cannot mint or revoke tokens.
+
+pub async fn list_api_tokens(
+    State(state): State<AppState>,
+    session: AuthSession,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let tokens = sqlx::query_as::<Sqlite, ApiToken>("SELECT * FROM api_tokens WHERE user_id = ? ORDER BY created_at DESC")
+        .bind(&session.user.id)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(tokens))
+}
+
+pub async fn create_api_token(
+    State(state): State<AppState>,
+    session: AuthSession,
+    Json(payload): Json<CreateApiTokenRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Token name is required".to_string()));
+    }
+    if payload.scopes.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "At least one scope is required".to_string()));
+    }
+    if let Some(unknown) = payload.scopes.iter().find(|s| !API_TOKEN_SCOPES.contains(&s.as_str())) {
+        return Err((StatusCode::BAD_REQUEST, format!("Unknown scope '{}'", unknown)));
+    }
+
+    let mut scopes = payload.scopes.clone();
+    scopes.sort();
+    scopes.dedup();
+
+    let expires_at = match payload.expires_in_days {
+        Some(days) if days <= 0 => {
+            return Err((StatusCode::BAD_REQUEST, "expires_in_days must be positive".to_string()));
+        }
+        Some(days) => Some(chrono::Utc::now() + chrono::Duration::days(days)),
+        None => None,
+    };
+
+    let id = Uuid::new_v4().to_string();
+    let token = format!("{}{}", API_TOKEN_PREFIX, generate_token());
+
+    sqlx::query("INSERT INTO api_tokens (id, user_id, name, token_hash, scopes, expires_at) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(&id)
+        .bind(&session.user.id)
+        .bind(name)
+        .bind(hash_token(&token))
+        .bind(sqlx::types::Json(&scopes))
+        .bind(expires_at)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let api_token = sqlx::query_as::<Sqlite, ApiToken>("SELECT * FROM api_tokens WHERE id = ?")
+        .bind(&id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(CreatedApiToken { token, api_token })))
+}
+
+pub async fn revoke_api_token(
+    State(state): State<AppState>,
+    session: AuthSession,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let result = sqlx::query("DELETE FROM api_tokens WHERE id = ? AND user_id = ?")
+        .bind(id)
+        .bind(&session.user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "API token not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestApp;
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    /// Creates a personal access token and returns its id and plaintext.
+    async fn create_token(app: &TestApp, session: &str, scopes: &[&str]) -> (String, String) {
+        let created = app.post("/api/tokens", session, json!({ "name": "script", "scopes": scopes })).await;
+        assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+        let id = created.body["api_token"]["id"].as_str().unwrap().to_string();
+        (id, created.body["token"].as_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn tokens_only_reach_their_scopes() {
+        let app = TestApp::new().await;
+        let session = app.sign_up("ann@example.com").await;
+        let (_, token) = create_token(&app, &session, &["tasks:read"]).await;
+
+        assert_eq!(app.get("/api/tasks", &token).await.status, StatusCode::OK);
+        assert_eq!(app.post("/api/tasks", &token, json!({ "title": "Nope" })).await.status, StatusCode::FORBIDDEN);
+        assert_eq!(app.get("/api/expenses", &token).await.status, StatusCode::FORBIDDEN);
+        assert_eq!(app.post("/api/ai/suggest", &token, json!({})).await.status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn tokens_cannot_reach_unguarded_routes() {
+        let app = TestApp::new().await;
+        let session = app.sign_up("ann@example.com").await;
+        let (_, token) = create_token(&app, &session, &["tasks:read", "tasks:write"]).await;
+
+        assert_eq!(app.get("/api/auth/me", &token).await.status, StatusCode::FORBIDDEN);
+        assert_eq!(app.post("/api/undo/create-delete", &token, json!({})).await.status, StatusCode::FORBIDDEN);
+        // Nor mint a token with more scopes
+        let minted = app.post("/api/tokens", &token, json!({ "name": "more", "scopes": ["expenses:read"] })).await;
+        assert_eq!(minted.status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_and_revoked_tokens() {
+        let app = TestApp::new().await;
+        let session = app.sign_up("ann@example.com").await;
+        let (_, expired) = create_token(&app, &session, &["tasks:read"]).await;
+        let (revoked_id, revoked) = create_token(&app, &session, &["tasks:read"]).await;
+
+        sqlx::query("UPDATE api_tokens SET expires_at = ? WHERE id != ?")
+            .bind(chrono::Utc::now() - chrono::Duration::minutes(1))
+            .bind(&revoked_id)
+            .execute(&app.state.db)
+            .await
+            .unwrap();
+        assert_eq!(app.get("/api/tasks", &expired).await.status, StatusCode::UNAUTHORIZED);
+
+        assert_eq!(app.get("/api/tasks", &revoked).await.status, StatusCode::OK);
+        let revoke = app.delete(&format!("/api/tokens/{}", revoked_id), &session).await;
+        assert_eq!(revoke.status, StatusCode::NO_CONTENT);
+        assert_eq!(app.get("/api/tasks", &revoked).await.status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,474 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use sqlx::{types::Json as SqlJson, QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+use std::env;
+use uuid::Uuid;
+
+use super::{AppState, TASK_COLUMNS};
+use crate::auth::AuthenticatedUser;
+use crate::models::*;
+use crate::search_index::{self, SearchKind};
+
+/// Days deleted tasks and expenses stay in the trash, from `TRASH_RETENTION_DAYS`.
+/// The operation log is kept as long, so anything still in the trash can be undone.
+pub fn trash_retention() -> chrono::Duration {
+    let days = env::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|days| *days > 0)
+        .unwrap_or(30);
+    chrono::Duration::days(days)
+}
+
+/// Records a create, delete or restore for `POST /api/undo/create-delete`.
+pub async fn log_operation(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    kind: OperationKind,
+) -> Result<(), (StatusCode, String)> {
+    // Bound rather than left to the column default, which is formatted
+    // differently from the cutoff the purge job compares it with
+    sqlx::query("INSERT INTO operations (id, user_id, payload, created_at) VALUES (?, ?, ?, ?)")
+        .bind(Uuid::new_v4().to_string())
+        .bind(user_id)
+        .bind(SqlJson(kind))
+        .bind(Utc::now())
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(())
+}
+
+/// Moves a task and its subtasks to the trash and stops a timer running on
+/// any of them. Returns every id moved, the task's first; none when the task
+/// is missing or already in the trash.
+pub async fn trash_task(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    task_id: &str,
+) -> Result<Vec<String>, (StatusCode, String)> {
+    let ids: Vec<String> = sqlx::query_scalar(
+        "WITH RECURSIVE subtree(id, depth) AS (
+             SELECT id, 0 FROM tasks WHERE id = ? AND user_id = ? AND deleted_at IS NULL
+             UNION ALL
+             SELECT tasks.id, subtree.depth + 1 FROM tasks JOIN subtree ON tasks.parent_id = subtree.id
+             WHERE tasks.deleted_at IS NULL
+         )
+         SELECT id FROM subtree ORDER BY depth",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if ids.is_empty() {
+        return Ok(ids);
+    }
+
+    let now = Utc::now();
+    set_tasks_deleted(conn, user_id, &ids, Some(now)).await?;
+    let mut builder = QueryBuilder::<Sqlite>::new("UPDATE time_entries SET ended_at = ");
+    builder.push_bind(now).push(" WHERE ended_at IS NULL AND task_id IN (");
+    let mut separated = builder.separated(", ");
+    for id in &ids {
+        separated.push_bind(id);
+    }
+    builder
+        .push(")")
+        .build()
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(ids)
+}
+
+/// Sets or clears `deleted_at` on the user's tasks and brings their search
+/// entries in line. Returns how many tasks changed.
+async fn set_tasks_deleted(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    ids: &[String],
+    deleted_at: Option<DateTime<Utc>>,
+) -> Result<u64, (StatusCode, String)> {
+    let mut builder = QueryBuilder::<Sqlite>::new("UPDATE tasks SET deleted_at = ");
+    builder.push_bind(deleted_at).push(" WHERE user_id = ").push_bind(user_id);
+    builder.push(if deleted_at.is_some() { " AND deleted_at IS NULL" } else { " AND deleted_at IS NOT NULL" });
+    builder.push(" AND id IN (");
+    let mut separated = builder.separated(", ");
+    for id in ids {
+        separated.push_bind(id);
+    }
+    let result = builder
+        .push(")")
+        .build()
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    for id in ids {
+        search_index::sync(conn, SearchKind::Task, id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+    Ok(result.rows_affected())
+}
+
+/// Takes a task out of the trash together with the subtasks that went in
+/// with it. Its parent has to be restored first.
+pub async fn untrash_task(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    task_id: &str,
+) -> Result<Vec<String>, (StatusCode, String)> {
+    let parent_deleted: Option<bool> = sqlx::query_scalar(
+        "SELECT parent.deleted_at IS NOT NULL FROM tasks
+         LEFT JOIN tasks AS parent ON parent.id = tasks.parent_id
+         WHERE tasks.id = ? AND tasks.user_id = ? AND tasks.deleted_at IS NOT NULL",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "Task not found in the trash".to_string()))?;
+    if parent_deleted == Some(true) {
+        return Err((StatusCode::CONFLICT, "Its parent task is in the trash; restore that first".to_string()));
+    }
+
+    // Subtasks trashed on their own before the task stay in the trash
+    let ids: Vec<String> = sqlx::query_scalar(
+        "WITH RECURSIVE subtree(id, deleted_at, depth) AS (
+             SELECT id, deleted_at, 0 FROM tasks WHERE id = ? AND user_id = ?
+             UNION ALL
+             SELECT tasks.id, tasks.deleted_at, subtree.depth + 1 FROM tasks JOIN subtree ON tasks.parent_id = subtree.id
+             WHERE tasks.deleted_at = subtree.deleted_at
+         )
+         SELECT id FROM subtree ORDER BY depth",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    set_tasks_deleted(conn, user_id, &ids, None).await?;
+    Ok(ids)
+}
+
+/// Sets or clears `deleted_at` on one of the user's expenses. Returns
+/// whether it changed.
+pub async fn set_expense_deleted(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    id: &str,
+    deleted_at: Option<DateTime<Utc>>,
+) -> Result<bool, (StatusCode, String)> {
+    let condition = if deleted_at.is_some() { "deleted_at IS NULL" } else { "deleted_at IS NOT NULL" };
+    let result = sqlx::query(&format!(
+        "UPDATE expenses SET deleted_at = ? WHERE id = ? AND user_id = ? AND {}",
+        condition
+    ))
+    .bind(deleted_at)
+    .bind(id)
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Someone else's expense with this id must keep its entry
+    if result.rows_affected() > 0 {
+        search_index::sync(conn, SearchKind::Expense, id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+    Ok(result.rows_affected() > 0)
+}
+
+// --- Trash Handlers ---
+
+/// Deleted tasks, most recently deleted first. Subtasks that went in with
+/// their parent are listed under it rather than on their own.
+pub async fn list_task_trash(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let tasks = sqlx::query_as::<Sqlite, Task>(&format!(
+        "SELECT {} FROM tasks WHERE user_id = ? AND deleted_at IS NOT NULL
+         AND NOT EXISTS (SELECT 1 FROM tasks AS parent WHERE parent.id = tasks.parent_id AND parent.deleted_at = tasks.deleted_at)
+         ORDER BY deleted_at DESC, position",
+        TASK_COLUMNS
+    ))
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(tasks))
+}
+
+pub async fn restore_task(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let task_ids = untrash_task(&mut tx, &user.id, &id).await?;
+    log_operation(&mut tx, &user.id, OperationKind::RestoreTasks { task_ids }).await?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(super::fetch_task(&state.db, &user.id, &id).await?))
+}
+
+/// Deleted expenses, most recently deleted first.
+pub async fn list_expense_trash(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let expenses = sqlx::query_as::<Sqlite, Expense>(
+        "SELECT * FROM expenses WHERE user_id = ? AND deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+    )
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(expenses))
+}
+
+pub async fn restore_expense(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !set_expense_deleted(&mut tx, &user.id, &id, None).await? {
+        return Err((StatusCode::NOT_FOUND, "Expense not found in the trash".to_string()));
+    }
+    log_operation(&mut tx, &user.id, OperationKind::RestoreExpense { expense_id: id.clone() }).await?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let expense = sqlx::query_as::<Sqlite, Expense>("SELECT * FROM expenses WHERE id = ? AND user_id = ?")
+        .bind(&id)
+        .bind(&user.id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(expense))
+}
+
+// --- Undo Handler ---
+
+/// Whether any of the user's tasks was changed after `since` in a way the
+/// operation log doesn't record, such as an edit of its title or tags.
+async fn tasks_changed_since(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    since: DateTime<Utc>,
+) -> Result<bool, (StatusCode, String)> {
+    sqlx::query_scalar("SELECT COUNT(*) > 0 FROM tasks WHERE user_id = ? AND updated_at > ?")
+        .bind(user_id)
+        .bind(since)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Reverts the user's latest create, delete or restore of a task or expense
+/// that hasn't been undone yet; calling it again walks further back. Edits
+/// aren't recorded, so once any task was changed after the operation, undo
+/// answers 409 instead of reverting around a change it can't see. Returns
+/// the operation that was undone.
+pub async fn undo_create_delete(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let operation = sqlx::query_as::<Sqlite, Operation>(
+        "SELECT id, payload, undone_at, created_at FROM operations WHERE user_id = ? AND undone_at IS NULL
+         ORDER BY created_at DESC, rowid DESC LIMIT 1",
+    )
+    .bind(&user.id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "Nothing to undo".to_string()))?;
+
+    // Expenses can't be edited, so only tasks need checking. The operation
+    // stays in the log, as every older one was followed by the edit too.
+    if tasks_changed_since(&mut tx, &user.id, operation.created_at).await? {
+        return Err((
+            StatusCode::CONFLICT,
+            "Tasks were edited after the last create, delete or restore, which undo cannot revert".to_string(),
+        ));
+    }
+
+    let now = Utc::now();
+    let reverted = match &operation.payload.0 {
+        OperationKind::CreateTask { task_id } => !trash_task(&mut tx, &user.id, task_id).await?.is_empty(),
+        OperationKind::RestoreTasks { task_ids } => {
+            set_tasks_deleted(&mut tx, &user.id, task_ids, Some(now)).await? > 0
+        }
+        OperationKind::DeleteTasks { task_ids } => set_tasks_deleted(&mut tx, &user.id, task_ids, None).await? > 0,
+        OperationKind::CreateExpense { expense_id } | OperationKind::RestoreExpense { expense_id } => {
+            set_expense_deleted(&mut tx, &user.id, expense_id, Some(now)).await?
+        }
+        OperationKind::DeleteExpense { expense_id } => {
+            set_expense_deleted(&mut tx, &user.id, expense_id, None).await?
+        }
+    };
+
+    // Marked undone even when there was nothing left to revert, so the next
+    // undo moves past it
+    sqlx::query("UPDATE operations SET undone_at = ? WHERE id = ?")
+        .bind(now)
+        .bind(&operation.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !reverted {
+        return Err((StatusCode::GONE, "The last change can no longer be undone".to_string()));
+    }
+    Ok(Json(Operation {
+        undone_at: Some(now),
+        ..operation
+    }))
+}
+
+// --- Purge Job ---
+
+/// Hard-deletes tasks and expenses that have been in the trash longer than
+/// `trash_retention`, and log entries as old. Subtasks go with their parent.
+pub fn spawn_trash_purge(pool: SqlitePool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            let cutoff = Utc::now() - trash_retention();
+            for (what, query) in [
+                ("tasks", "DELETE FROM tasks WHERE deleted_at <= ?"),
+                ("expenses", "DELETE FROM expenses WHERE deleted_at <= ?"),
+                ("operations", "DELETE FROM operations WHERE created_at <= ?"),
+            ] {
+                match sqlx::query(query).bind(cutoff).execute(&pool).await {
+                    Ok(result) if result.rows_affected() > 0 => {
+                        tracing::info!("Purged {} {} from the trash", result.rows_affected(), what)
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::error!("Failed to purge {} from the trash: {}", what, e),
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestApp;
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    async fn in_trash(app: &TestApp, id: &str) -> bool {
+        sqlx::query_scalar("SELECT deleted_at IS NOT NULL FROM tasks WHERE id = ?")
+            .bind(id)
+            .fetch_one(&app.state.db)
+            .await
+            .unwrap()
+    }
+
+    async fn undo(app: &TestApp, token: &str) -> (StatusCode, serde_json::Value) {
+        let response = app.post("/api/undo/create-delete", token, json!({})).await;
+        (response.status, response.body)
+    }
+
+    #[tokio::test]
+    async fn undoes_a_create() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        let id = app.create_task(&token, json!({ "title": "Write report" })).await;
+
+        let (status, body) = undo(&app, &token).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["action"], "create_task");
+        assert!(in_trash(&app, &id).await);
+
+        let (status, _) = undo(&app, &token).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn undoes_a_delete_with_its_subtasks() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        let parent = app.create_task(&token, json!({ "title": "Move house" })).await;
+        let child = app.create_task(&token, json!({ "title": "Pack books", "parent_id": parent })).await;
+        assert_eq!(app.delete(&format!("/api/tasks/{}", parent), &token).await.status, StatusCode::NO_CONTENT);
+        assert!(in_trash(&app, &child).await);
+
+        let (status, body) = undo(&app, &token).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["action"], "delete_tasks");
+        assert!(!in_trash(&app, &parent).await);
+        assert!(!in_trash(&app, &child).await);
+        assert_eq!(app.get("/api/tasks/trash", &token).await.body, json!([]));
+    }
+
+    #[tokio::test]
+    async fn undoes_a_restore() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        let id = app.create_task(&token, json!({ "title": "Write report" })).await;
+        app.delete(&format!("/api/tasks/{}", id), &token).await;
+        let restored = app.post(&format!("/api/tasks/{}/restore", id), &token, json!({})).await;
+        assert_eq!(restored.status, StatusCode::OK, "{}", restored.body);
+
+        let (status, body) = undo(&app, &token).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["action"], "restore_tasks");
+        assert!(in_trash(&app, &id).await);
+
+        // Then the delete before it
+        let (status, body) = undo(&app, &token).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["action"], "delete_tasks");
+        assert!(!in_trash(&app, &id).await);
+    }
+
+    #[tokio::test]
+    async fn refuses_to_undo_past_an_edit_of_any_task() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        let edited = app.create_task(&token, json!({ "title": "Write report" })).await;
+        let created = app.create_task(&token, json!({ "title": "Book flights" })).await;
+        // The log and the triggers both keep milliseconds
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        let title = json!({ "title": "Write the report" });
+        let updated = app.patch(&format!("/api/tasks/{}", edited), &token, title).await;
+        assert_eq!(updated.status, StatusCode::OK, "{}", updated.body);
+
+        // Undoing the second create would revert around the edit of the first task
+        for _ in 0..2 {
+            let (status, body) = undo(&app, &token).await;
+            assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+        }
+        assert!(!in_trash(&app, &created).await);
+        let pending: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM operations WHERE undone_at IS NULL")
+            .fetch_one(&app.state.db)
+            .await
+            .unwrap();
+        assert_eq!(pending, 2);
+
+        // Changes logged after the edit can still be undone
+        app.delete(&format!("/api/tasks/{}", created), &token).await;
+        let (status, body) = undo(&app, &token).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(!in_trash(&app, &created).await);
+    }
+}
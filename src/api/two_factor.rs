@@ -0,0 +1,396 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use rand::Rng;
+use sqlx::{Sqlite, SqlitePool};
+use std::env;
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+use super::{auth_response, cancel_scheduled_deletion, too_many_attempts, AppState};
+use crate::auth::{confirm_password, create_session, generate_token, hash_token, AuthSession, SessionMetadata};
+use crate::models::*;
+use crate::{throttle, totp};
+
+const CHALLENGE_TTL_MINUTES: i64 = 5;
+const MAX_CHALLENGE_ATTEMPTS: i64 = 5;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+// --- Two-Factor Authentication Handlers ---
+
+/// Starts enrollment by storing a fresh, not yet enabled secret. Like every
+/// change to the second factor, it needs the current password, so a stolen
+/// session can't enroll an authenticator of its own.
+pub async fn setup(
+    State(state): State<AppState>,
+    session: AuthSession,
+    Json(payload): Json<TwoFactorSetupRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    if session.user.totp_enabled {
+        return Err((StatusCode::CONFLICT, "Two-factor authentication is already enabled".to_string()));
+    }
+    confirm_password(&session.user, &payload.password)?;
+
+    let secret = totp::generate_secret();
+    let issuer = env::var("TOTP_ISSUER").unwrap_or_else(|_| "Personal Assistant".to_string());
+
+    sqlx::query("UPDATE users SET totp_secret = ?, totp_last_step = NULL WHERE id = ?")
+        .bind(&secret)
+        .bind(&session.user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let otpauth_uri = totp::otpauth_uri(&secret, &session.user.email, &issuer);
+
+    Ok(Json(TwoFactorSetupResponse { secret, otpauth_uri }))
+}
+
+/// Confirms enrollment with a first code from the authenticator app.
+pub async fn enable(
+    State(state): State<AppState>,
+    session: AuthSession,
+    Json(payload): Json<EnableTwoFactorRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let user = session.user;
+    if user.totp_enabled {
+        return Err((StatusCode::CONFLICT, "Two-factor authentication is already enabled".to_string()));
+    }
+    confirm_password(&user, &payload.password)?;
+    let secret = user
+        .totp_secret
+        .as_deref()
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "Call /api/auth/2fa/setup first".to_string()))?;
+
+    let step = totp::verify(secret, &payload.code, chrono::Utc::now().timestamp(), None)
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid code".to_string()))?;
+
+    sqlx::query("UPDATE users SET totp_enabled = TRUE, totp_last_step = ? WHERE id = ?")
+        .bind(step)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let recovery_codes = replace_recovery_codes(&state.db, &user.id).await?;
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
+
+pub async fn disable(
+    State(state): State<AppState>,
+    session: AuthSession,
+    Json(payload): Json<DisableTwoFactorRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let user = session.user;
+    if !user.totp_enabled {
+        return Err((StatusCode::BAD_REQUEST, "Two-factor authentication is not enabled".to_string()));
+    }
+    confirm_password(&user, &payload.password)?;
+    check_totp(&state.db, &user, &payload.code).await?;
+
+    sqlx::query("UPDATE users SET totp_enabled = FALSE, totp_secret = NULL, totp_last_step = NULL WHERE id = ?")
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?")
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Issues a new set of recovery codes, invalidating the previous ones.
+pub async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    session: AuthSession,
+    Json(payload): Json<TwoFactorCodeRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let user = session.user;
+    if !user.totp_enabled {
+        return Err((StatusCode::BAD_REQUEST, "Two-factor authentication is not enabled".to_string()));
+    }
+    check_totp(&state.db, &user, &payload.code).await?;
+
+    let recovery_codes = replace_recovery_codes(&state.db, &user.id).await?;
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
+
+/// Second login step: trades a challenge plus a TOTP or recovery code for tokens.
+///
+/// Failed codes count as failed logins, so the account lockout also caps
+/// how often the second factor can be guessed. The account is checked again
+/// as `login` does, since it may have been disabled since the first step.
+pub async fn verify(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<TwoFactorVerifyRequest>,
+) -> Result<Response, (StatusCode, String)> {
+    let challenge = sqlx::query_as::<Sqlite, (String, String)>(
+        "SELECT id, user_id FROM two_factor_challenges WHERE token_hash = ? AND expires_at > ?",
+    )
+    .bind(hash_token(&payload.challenge_token))
+    .bind(chrono::Utc::now())
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let (challenge_id, user_id) =
+        challenge.ok_or_else(|| (StatusCode::UNAUTHORIZED, "Invalid or expired challenge".to_string()))?;
+
+    let user = sqlx::query_as::<Sqlite, User>("SELECT * FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "User not found".to_string()))?;
+
+    let ip = throttle::client_ip(&addr, &headers);
+    if let Some(retry_after) = throttle::check(&state.db, &user.email, &ip)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+    {
+        return Ok(too_many_attempts(retry_after));
+    }
+
+    let verified = match (&payload.code, &payload.recovery_code) {
+        (Some(code), _) => check_totp(&state.db, &user, code).await.is_ok(),
+        (None, Some(recovery_code)) => use_recovery_code(&state.db, &user.id, recovery_code).await?,
+        (None, None) => {
+            return Err((StatusCode::BAD_REQUEST, "Provide a code or a recovery_code".to_string()));
+        }
+    };
+
+    throttle::record_attempt(&state.db, &user.email, &ip, verified)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    if !verified {
+        // Count the failure and drop the challenge once it has been guessed at too often
+        sqlx::query("UPDATE two_factor_challenges SET attempts = attempts + 1 WHERE id = ?")
+            .bind(&challenge_id)
+            .execute(&state.db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        sqlx::query("DELETE FROM two_factor_challenges WHERE id = ? AND attempts >= ?")
+            .bind(&challenge_id)
+            .bind(MAX_CHALLENGE_ATTEMPTS)
+            .execute(&state.db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        return Err((StatusCode::UNAUTHORIZED, "Invalid code".to_string()));
+    }
+
+    sqlx::query("DELETE FROM two_factor_challenges WHERE id = ?")
+        .bind(&challenge_id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if user.disabled_at.is_some() {
+        return Err((StatusCode::FORBIDDEN, "This account has been disabled".to_string()));
+    }
+    let user = cancel_scheduled_deletion(&state.db, user, &ip).await?;
+
+    let (session_id, refresh_token) = create_session(&state.db, &user.id, &SessionMetadata::new(&ip, &headers))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(auth_response(user, &session_id, refresh_token)?).into_response())
+}
+
+/// Records a password-verified login that still needs its second factor.
+pub async fn start_challenge(pool: &SqlitePool, user_id: &str) -> Result<TwoFactorChallenge, (StatusCode, String)> {
+    let token = generate_token();
+    let now = chrono::Utc::now();
+
+    sqlx::query("DELETE FROM two_factor_challenges WHERE expires_at <= ?")
+        .bind(now)
+        .execute(pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query("INSERT INTO two_factor_challenges (id, user_id, token_hash, expires_at) VALUES (?, ?, ?, ?)")
+        .bind(Uuid::new_v4().to_string())
+        .bind(user_id)
+        .bind(hash_token(&token))
+        .bind(now + chrono::Duration::minutes(CHALLENGE_TTL_MINUTES))
+        .execute(pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(TwoFactorChallenge {
+        two_factor_required: true,
+        challenge_token: token,
+        expires_in: CHALLENGE_TTL_MINUTES * 60,
+    })
+}
+
+/// Verifies a TOTP code for an enrolled user and burns its time step.
+async fn check_totp(pool: &SqlitePool, user: &User, code: &str) -> Result<(), (StatusCode, String)> {
+    let secret = user
+        .totp_secret
+        .as_deref()
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "Two-factor authentication is not enabled".to_string()))?;
+
+    let step = totp::verify(secret, code, chrono::Utc::now().timestamp(), user.totp_last_step)
+        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "Invalid code".to_string()))?;
+
+    // Conditional, so two requests racing with the same code can't both win
+    let result = sqlx::query(
+        "UPDATE users SET totp_last_step = ? WHERE id = ? AND (totp_last_step IS NULL OR totp_last_step < ?)",
+    )
+    .bind(step)
+    .bind(&user.id)
+    .bind(step)
+    .execute(pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid code".to_string()));
+    }
+
+    Ok(())
+}
+
+async fn use_recovery_code(pool: &SqlitePool, user_id: &str, code: &str) -> Result<bool, (StatusCode, String)> {
+    let result = sqlx::query("UPDATE recovery_codes SET used_at = ? WHERE user_id = ? AND code_hash = ? AND used_at IS NULL")
+        .bind(chrono::Utc::now())
+        .bind(user_id)
+        .bind(hash_token(&normalize_recovery_code(code)))
+        .execute(pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+async fn replace_recovery_codes(pool: &SqlitePool, user_id: &str) -> Result<Vec<String>, (StatusCode, String)> {
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| generate_recovery_code()).collect();
+    for code in &codes {
+        sqlx::query("INSERT INTO recovery_codes (id, user_id, code_hash) VALUES (?, ?, ?)")
+            .bind(Uuid::new_v4().to_string())
+            .bind(user_id)
+            .bind(hash_token(&normalize_recovery_code(code)))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(codes)
+}
+
+/// Recovery codes look like `K7QWM-3XH2P`; ambiguous characters are left out.
+fn generate_recovery_code() -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    let mut rng = rand::thread_rng();
+    let chars: String = (0..10)
+        .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+        .collect();
+    format!("{}-{}", &chars[..5], &chars[5..])
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{TestApp, PASSWORD};
+    use crate::totp;
+    use axum::http::{Method, StatusCode};
+    use serde_json::{json, Value};
+
+    /// Turns on 2FA for a new account and returns its recovery codes.
+    async fn enroll(app: &TestApp, email: &str) -> Vec<Value> {
+        let token = app.sign_up(email).await;
+        let setup = app.post("/api/auth/2fa/setup", &token, json!({ "password": PASSWORD })).await;
+        assert_eq!(setup.status, StatusCode::OK, "{}", setup.body);
+        let code = totp::code_at(setup.body["secret"].as_str().unwrap(), chrono::Utc::now().timestamp());
+        let enabled = app.post("/api/auth/2fa/enable", &token, json!({ "password": PASSWORD, "code": code })).await;
+        assert_eq!(enabled.status, StatusCode::OK, "{}", enabled.body);
+        enabled.body["recovery_codes"].as_array().unwrap().clone()
+    }
+
+    /// Logs in with the password and finishes with a recovery code, as the
+    /// code used to enroll can't be replayed.
+    async fn log_in_with(app: &TestApp, email: &str, recovery_code: &Value) -> StatusCode {
+        let login = app.login(email).await;
+        assert_eq!(login.body["two_factor_required"], true, "{}", login.body);
+        let body = json!({ "challenge_token": login.body["challenge_token"], "recovery_code": recovery_code });
+        app.request(Method::POST, "/api/auth/2fa/verify", None, Some(body)).await.status
+    }
+
+    #[tokio::test]
+    async fn setup_and_enable_need_the_password() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+
+        let setup = app.post("/api/auth/2fa/setup", &token, json!({ "password": "wrong password" })).await;
+        assert_eq!(setup.status, StatusCode::UNAUTHORIZED);
+        let setup = app.post("/api/auth/2fa/setup", &token, json!({ "password": PASSWORD })).await;
+        assert_eq!(setup.status, StatusCode::OK, "{}", setup.body);
+
+        let code = totp::code_at(setup.body["secret"].as_str().unwrap(), chrono::Utc::now().timestamp());
+        let body = json!({ "password": "wrong password", "code": code });
+        let enable = app.post("/api/auth/2fa/enable", &token, body).await;
+        assert_eq!(enable.status, StatusCode::UNAUTHORIZED);
+        let enabled: bool =
+            sqlx::query_scalar("SELECT totp_enabled FROM users").fetch_one(&app.state.db).await.unwrap();
+        assert!(!enabled);
+    }
+
+    #[tokio::test]
+    async fn verify_refuses_an_account_disabled_after_the_password_step() {
+        let app = TestApp::new().await;
+        let recovery_codes = enroll(&app, "ann@example.com").await;
+        assert_eq!(log_in_with(&app, "ann@example.com", &recovery_codes[0]).await, StatusCode::OK);
+
+        let login = app.login("ann@example.com").await;
+        sqlx::query("UPDATE users SET disabled_at = CURRENT_TIMESTAMP").execute(&app.state.db).await.unwrap();
+        let body = json!({ "challenge_token": login.body["challenge_token"], "recovery_code": recovery_codes[1] });
+        let verify = app.request(Method::POST, "/api/auth/2fa/verify", None, Some(body)).await;
+        assert_eq!(verify.status, StatusCode::FORBIDDEN);
+        let sessions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sessions").fetch_one(&app.state.db).await.unwrap();
+        assert_eq!(sessions, 2, "only sign-up's and the first login's");
+    }
+
+    #[tokio::test]
+    async fn verify_cancels_a_scheduled_deletion() {
+        let app = TestApp::new().await;
+        let recovery_codes = enroll(&app, "ann@example.com").await;
+        sqlx::query("UPDATE users SET deletion_scheduled_at = CURRENT_TIMESTAMP").execute(&app.state.db).await.unwrap();
+
+        assert_eq!(log_in_with(&app, "ann@example.com", &recovery_codes[0]).await, StatusCode::OK);
+        let scheduled: Option<String> =
+            sqlx::query_scalar("SELECT deletion_scheduled_at FROM users").fetch_one(&app.state.db).await.unwrap();
+        assert_eq!(scheduled, None);
+    }
+}
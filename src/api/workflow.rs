@@ -0,0 +1,603 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use super::{AppState, TASK_COLUMNS};
+use crate::auth::AuthenticatedUser;
+use crate::models::*;
+
+const MAX_STATE_NAME_LENGTH: usize = 50;
+
+/// The workflow every user starts with.
+const DEFAULT_STATES: &[(&str, StateCategory)] = &[
+    ("To do", StateCategory::Open),
+    ("In progress", StateCategory::Open),
+    ("Waiting", StateCategory::Open),
+    ("Done", StateCategory::Done),
+    ("Cancelled", StateCategory::Cancelled),
+];
+
+const STATE_COLUMNS: &str = "workflow_states.id, workflow_states.name, workflow_states.category,
+    workflow_states.position, workflow_states.created_at,
+    (SELECT COUNT(*) FROM tasks WHERE tasks.state_id = workflow_states.id AND tasks.deleted_at IS NULL) AS task_count";
+
+fn normalize_name(name: &str) -> Result<String, (StatusCode, String)> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "State names must not be empty".to_string()));
+    }
+    if name.chars().count() > MAX_STATE_NAME_LENGTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("State names are limited to {} characters", MAX_STATE_NAME_LENGTH),
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// Seeds the default workflow for a user who has none, and puts tasks
+/// without a state into the first open or done state by their `completed`.
+/// In the defaults open states move freely, while done and cancelled tasks
+/// can only be reopened to the first state.
+pub async fn ensure_workflow(conn: &mut SqliteConnection, user_id: &str) -> Result<(), (StatusCode, String)> {
+    let seeded: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM workflow_states WHERE user_id = ?)")
+        .bind(user_id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !seeded {
+        for (position, (name, category)) in DEFAULT_STATES.iter().enumerate() {
+            // OR IGNORE and the name lookups below keep a concurrent seed harmless
+            sqlx::query(
+                "INSERT OR IGNORE INTO workflow_states (id, user_id, name, category, position) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(user_id)
+            .bind(name)
+            .bind(category)
+            .bind(position as i64)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+        for (from, from_category) in DEFAULT_STATES {
+            for (to, _) in DEFAULT_STATES {
+                let allowed = if from_category.is_closed() { *to == DEFAULT_STATES[0].0 } else { from != to };
+                if !allowed {
+                    continue;
+                }
+                sqlx::query(
+                    "INSERT OR IGNORE INTO workflow_transitions (from_state_id, to_state_id)
+                     SELECT f.id, t.id FROM workflow_states AS f, workflow_states AS t
+                     WHERE f.user_id = ? AND f.name = ? AND t.user_id = f.user_id AND t.name = ?",
+                )
+                .bind(user_id)
+                .bind(from)
+                .bind(to)
+                .execute(&mut *conn)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            }
+        }
+    }
+
+    sqlx::query(
+        "UPDATE tasks SET state_id = (
+             SELECT id FROM workflow_states WHERE user_id = tasks.user_id
+             AND category = CASE WHEN tasks.completed THEN 'done' ELSE 'open' END
+             ORDER BY position, created_at LIMIT 1)
+         WHERE user_id = ? AND state_id IS NULL",
+    )
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(())
+}
+
+/// Gives users whose tasks predate workflows their states on startup.
+pub async fn seed_workflows(pool: &SqlitePool) -> Result<(), String> {
+    let user_ids: Vec<String> = sqlx::query_scalar("SELECT DISTINCT user_id FROM tasks WHERE state_id IS NULL")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to find tasks without a state: {}", e))?;
+
+    for user_id in user_ids {
+        let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+        ensure_workflow(&mut tx, &user_id).await.map_err(|(_, e)| format!("Failed to seed workflow: {}", e))?;
+        tx.commit().await.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// The user's state, or 404 for someone else's.
+pub async fn fetch_state(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    id: &str,
+) -> Result<WorkflowState, (StatusCode, String)> {
+    sqlx::query_as::<Sqlite, WorkflowState>(&format!(
+        "SELECT {} FROM workflow_states WHERE id = ? AND user_id = ?",
+        STATE_COLUMNS
+    ))
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "Workflow state not found".to_string()))
+}
+
+/// The first state of the category on the board; new tasks start in the first open one.
+pub async fn first_state(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    category: StateCategory,
+) -> Result<WorkflowState, (StatusCode, String)> {
+    ensure_workflow(conn, user_id).await?;
+    sqlx::query_as::<Sqlite, WorkflowState>(&format!(
+        "SELECT {} FROM workflow_states WHERE user_id = ? AND category = ? ORDER BY position, created_at LIMIT 1",
+        STATE_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(category)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or_else(|| (StatusCode::CONFLICT, "Your workflow has no state for this".to_string()))
+}
+
+/// Moves a task to `state`, keeping `completed` in step with its category.
+/// Moves the workflow doesn't allow are rejected; a task without a state
+/// may go anywhere.
+pub async fn move_task(
+    conn: &mut SqliteConnection,
+    task: &Task,
+    state: &WorkflowState,
+) -> Result<(), (StatusCode, String)> {
+    if let Some(from) = task.state_id.as_deref().filter(|from| *from != state.id) {
+        let allowed: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM workflow_transitions WHERE from_state_id = ? AND to_state_id = ?)",
+        )
+        .bind(from)
+        .bind(&state.id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        if !allowed {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("Tasks cannot move from '{}' to '{}'", task.state.as_deref().unwrap_or(from), state.name),
+            ));
+        }
+    }
+
+    sqlx::query("UPDATE tasks SET state_id = ?, completed = ? WHERE id = ? AND user_id = ?")
+        .bind(&state.id)
+        .bind(state.category.is_closed())
+        .bind(&task.id)
+        .bind(&task.user_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(())
+}
+
+/// A workflow needs somewhere for new tasks to start and for `completed: true` to go.
+async fn check_categories(conn: &mut SqliteConnection, user_id: &str) -> Result<(), (StatusCode, String)> {
+    let complete: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM workflow_states WHERE user_id = ? AND category = 'open')
+            AND EXISTS (SELECT 1 FROM workflow_states WHERE user_id = ? AND category = 'done')",
+    )
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !complete {
+        return Err((StatusCode::CONFLICT, "A workflow needs at least one open and one done state".to_string()));
+    }
+    Ok(())
+}
+
+async fn fetch_workflow(conn: &mut SqliteConnection, user_id: &str) -> Result<Workflow, (StatusCode, String)> {
+    let states = sqlx::query_as::<Sqlite, WorkflowState>(&format!(
+        "SELECT {} FROM workflow_states WHERE user_id = ? ORDER BY position, created_at",
+        STATE_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let transitions = sqlx::query_as::<Sqlite, WorkflowTransition>(
+        "SELECT from_state_id, to_state_id FROM workflow_transitions
+         JOIN workflow_states AS f ON f.id = workflow_transitions.from_state_id
+         JOIN workflow_states AS t ON t.id = workflow_transitions.to_state_id
+         WHERE f.user_id = ? ORDER BY f.position, t.position",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Workflow { states, transitions })
+}
+
+// --- Workflow Handlers ---
+
+pub async fn get_workflow(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    ensure_workflow(&mut tx, &user.id).await?;
+    let workflow = fetch_workflow(&mut tx, &user.id).await?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(workflow))
+}
+
+/// New states come last, and tasks can move between them and every other
+/// state until the transitions are narrowed down.
+pub async fn create_state(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(payload): Json<CreateStateRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let name = normalize_name(&payload.name)?;
+    let id = Uuid::new_v4().to_string();
+
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    ensure_workflow(&mut tx, &user.id).await?;
+    sqlx::query(
+        "INSERT INTO workflow_states (id, user_id, name, category, position)
+         SELECT ?, ?, ?, ?, COALESCE(MAX(position) + 1, 0) FROM workflow_states WHERE user_id = ?",
+    )
+    .bind(&id)
+    .bind(&user.id)
+    .bind(&name)
+    .bind(payload.category)
+    .bind(&user.id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| (StatusCode::CONFLICT, format!("State '{}' already exists", name)))?;
+
+    sqlx::query(
+        "INSERT INTO workflow_transitions (from_state_id, to_state_id)
+         SELECT ?, id FROM workflow_states WHERE user_id = ? AND id != ?
+         UNION ALL
+         SELECT id, ? FROM workflow_states WHERE user_id = ? AND id != ?",
+    )
+    .bind(&id)
+    .bind(&user.id)
+    .bind(&id)
+    .bind(&id)
+    .bind(&user.id)
+    .bind(&id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let created = fetch_state(&mut tx, &user.id, &id).await?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+/// Renames a state or changes its category. Tasks in the state follow a new
+/// category's `completed` right away, but finishing them this way doesn't
+/// start the next occurrence of a recurring task.
+pub async fn update_state(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateStateRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    fetch_state(&mut tx, &user.id, &id).await?;
+
+    if let Some(name) = payload.name {
+        let name = normalize_name(&name)?;
+        sqlx::query("UPDATE workflow_states SET name = ? WHERE id = ? AND user_id = ?")
+            .bind(&name)
+            .bind(&id)
+            .bind(&user.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| (StatusCode::CONFLICT, format!("State '{}' already exists", name)))?;
+    }
+
+    if let Some(category) = payload.category {
+        sqlx::query("UPDATE workflow_states SET category = ? WHERE id = ? AND user_id = ?")
+            .bind(category)
+            .bind(&id)
+            .bind(&user.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        check_categories(&mut tx, &user.id).await?;
+        sqlx::query("UPDATE tasks SET completed = ? WHERE state_id = ? AND user_id = ?")
+            .bind(category.is_closed())
+            .bind(&id)
+            .bind(&user.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    let updated = fetch_state(&mut tx, &user.id, &id).await?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(updated))
+}
+
+/// A state with tasks can only be deleted by naming the state they move to,
+/// which doesn't need to be an allowed transition.
+pub async fn delete_state(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<String>,
+    Query(query): Query<DeleteStateQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let deleted = fetch_state(&mut tx, &user.id, &id).await?;
+
+    match query.move_to.as_deref() {
+        Some(target_id) if target_id == id => {
+            return Err((StatusCode::BAD_REQUEST, "move_to must be another state".to_string()));
+        }
+        Some(target_id) => {
+            let target = fetch_state(&mut tx, &user.id, target_id).await?;
+            sqlx::query("UPDATE tasks SET state_id = ?, completed = ? WHERE state_id = ? AND user_id = ?")
+                .bind(&target.id)
+                .bind(target.category.is_closed())
+                .bind(&id)
+                .bind(&user.id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+        None if deleted.task_count > 0 => {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("State '{}' has {} tasks; pass move_to to move them", deleted.name, deleted.task_count),
+            ));
+        }
+        None => {}
+    }
+
+    sqlx::query("DELETE FROM workflow_states WHERE id = ? AND user_id = ?")
+        .bind(&id)
+        .bind(&user.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    check_categories(&mut tx, &user.id).await?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn reorder_states(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(payload): Json<ReorderStatesRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    ensure_workflow(&mut tx, &user.id).await?;
+
+    let mut existing: Vec<String> = sqlx::query_scalar("SELECT id FROM workflow_states WHERE user_id = ?")
+        .bind(&user.id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut requested = payload.ids.clone();
+    existing.sort();
+    requested.sort();
+    if existing != requested {
+        return Err((StatusCode::BAD_REQUEST, "ids must list each of your states exactly once".to_string()));
+    }
+
+    for (position, id) in payload.ids.iter().enumerate() {
+        sqlx::query("UPDATE workflow_states SET position = ? WHERE id = ? AND user_id = ?")
+            .bind(position as i64)
+            .bind(id)
+            .bind(&user.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    let workflow = fetch_workflow(&mut tx, &user.id).await?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(workflow))
+}
+
+pub async fn set_transitions(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(payload): Json<TransitionsRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    ensure_workflow(&mut tx, &user.id).await?;
+
+    let ids: HashSet<String> = sqlx::query_scalar("SELECT id FROM workflow_states WHERE user_id = ?")
+        .bind(&user.id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .into_iter()
+        .collect();
+    for transition in &payload.transitions {
+        for state_id in [&transition.from_state_id, &transition.to_state_id] {
+            if !ids.contains(state_id) {
+                return Err((StatusCode::NOT_FOUND, format!("Workflow state '{}' not found", state_id)));
+            }
+        }
+        if transition.from_state_id == transition.to_state_id {
+            return Err((StatusCode::BAD_REQUEST, "Transitions must lead to another state".to_string()));
+        }
+    }
+
+    sqlx::query(
+        "DELETE FROM workflow_transitions
+         WHERE from_state_id IN (SELECT id FROM workflow_states WHERE user_id = ?)",
+    )
+    .bind(&user.id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !payload.transitions.is_empty() {
+        let mut builder = QueryBuilder::<Sqlite>::new(
+            "INSERT OR IGNORE INTO workflow_transitions (from_state_id, to_state_id) ",
+        );
+        builder.push_values(&payload.transitions, |mut row, transition| {
+            row.push_bind(&transition.from_state_id).push_bind(&transition.to_state_id);
+        });
+        builder
+            .build()
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    let workflow = fetch_workflow(&mut tx, &user.id).await?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(workflow))
+}
+
+/// The user's tasks in one column per workflow state, in board order.
+/// Within a column tasks keep the order they were moved into.
+pub async fn get_board(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<BoardQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    ensure_workflow(&mut tx, &user.id).await?;
+    let states = fetch_workflow(&mut tx, &user.id).await?.states;
+
+    let mut builder = QueryBuilder::<Sqlite>::new(format!("SELECT {} FROM tasks WHERE deleted_at IS NULL AND user_id = ", TASK_COLUMNS));
+    builder.push_bind(&user.id);
+    match query.project.as_deref() {
+        None => {}
+        Some("none") => {
+            builder.push(" AND project_id IS NULL");
+        }
+        Some(project_id) => {
+            builder.push(" AND project_id = ").push_bind(project_id.to_string());
+        }
+    }
+    builder.push(" ORDER BY position, id");
+
+    let mut tasks = builder
+        .build_query_as::<Task>()
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let columns: Vec<BoardColumn> = states
+        .into_iter()
+        .map(|state| {
+            let (column, rest) = tasks.drain(..).partition(|task| task.state_id.as_deref() == Some(&state.id));
+            tasks = rest;
+            BoardColumn { state, tasks: column }
+        })
+        .collect();
+
+    Ok(Json(columns))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestApp;
+    use axum::http::StatusCode;
+    use serde_json::{json, Value};
+
+    /// The id of the user's workflow state called `name`.
+    async fn state_id(app: &TestApp, token: &str, name: &str) -> String {
+        let workflow = app.get("/api/workflow", token).await;
+        let states = workflow.body["states"].as_array().unwrap().clone();
+        let state = states.into_iter().find(|state| state["name"] == name).unwrap();
+        state["id"].as_str().unwrap().to_string()
+    }
+
+    async fn task(app: &TestApp, token: &str, id: &str) -> Value {
+        let tasks = app.get("/api/tasks?limit=200", token).await.body;
+        tasks.as_array().unwrap().iter().find(|task| task["id"] == id).unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn move_task_refuses_a_disallowed_transition() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        let other = app.create_task(&token, json!({ "title": "Other" })).await;
+        let id = app.create_task(&token, json!({ "title": "Report" })).await;
+        let done = state_id(&app, &token, "Done").await;
+        let in_progress = state_id(&app, &token, "In progress").await;
+
+        let path = format!("/api/tasks/{}/move", id);
+        let moved = app.post(&path, &token, json!({ "after_id": other, "state_id": done })).await;
+        assert_eq!(moved.status, StatusCode::OK, "{}", moved.body);
+        assert_eq!(moved.body["completed"], true);
+        let position = moved.body["position"].clone();
+
+        // Done tasks can only be reopened to To do
+        let refused = app.post(&path, &token, json!({ "before_id": other, "state_id": in_progress })).await;
+        assert_eq!(refused.status, StatusCode::CONFLICT);
+        let unchanged = task(&app, &token, &id).await;
+        assert_eq!(unchanged["state_id"], done.as_str());
+        assert_eq!(unchanged["position"], position);
+    }
+
+    #[tokio::test]
+    async fn tasks_follow_a_category_change() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        let waiting = state_id(&app, &token, "Waiting").await;
+        let id = app.create_task(&token, json!({ "title": "Report", "state_id": waiting })).await;
+        assert_eq!(task(&app, &token, &id).await["completed"], false);
+
+        let path = format!("/api/workflow/states/{}", waiting);
+        let updated = app.patch(&path, &token, json!({ "category": "done" })).await;
+        assert_eq!(updated.status, StatusCode::OK, "{}", updated.body);
+        assert_eq!(task(&app, &token, &id).await["completed"], true);
+
+        app.patch(&path, &token, json!({ "category": "open" })).await;
+        assert_eq!(task(&app, &token, &id).await["completed"], false);
+    }
+
+    #[tokio::test]
+    async fn deleting_a_state_with_tasks_needs_move_to() {
+        let app = TestApp::new().await;
+        let token = app.sign_up("ann@example.com").await;
+        let waiting = state_id(&app, &token, "Waiting").await;
+        let done = state_id(&app, &token, "Done").await;
+        let id = app.create_task(&token, json!({ "title": "Report", "state_id": waiting })).await;
+
+        let refused = app.delete(&format!("/api/workflow/states/{}", waiting), &token).await;
+        assert_eq!(refused.status, StatusCode::CONFLICT);
+        assert_eq!(task(&app, &token, &id).await["state_id"], waiting.as_str());
+
+        let deleted = app.delete(&format!("/api/workflow/states/{}?move_to={}", waiting, done), &token).await;
+        assert_eq!(deleted.status, StatusCode::NO_CONTENT);
+        let moved = task(&app, &token, &id).await;
+        assert_eq!(moved["state_id"], done.as_str());
+        assert_eq!(moved["completed"], true);
+    }
+}
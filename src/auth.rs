@@ -4,20 +4,28 @@ use argon2::{
 };
 use axum::{
     async_trait,
-    extract::{FromRef, FromRequestParts},
+    extract::{FromRef, FromRequestParts, Request, State},
     http::request::Parts,
-    http::StatusCode,
+    http::{HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::Response,
 };
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::env;
 use sqlx::{Sqlite, SqlitePool};
+use uuid::Uuid;
 
-use crate::models::User;
+use crate::keys;
+use crate::models::{ApiToken, User};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // User ID
+    pub sid: String, // Session ID
     pub exp: usize,  // Expiration time
 }
 
@@ -38,24 +46,645 @@ pub fn verify_password(password: &str, hash: &str) -> bool {
         .is_ok()
 }
 
-pub fn create_jwt(user_id: &str) -> Result<String, String> {
-    let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "change_me_immediately".to_string());
+/// Re-authentication before sensitive account changes. Accounts that only
+/// use single sign-on have no password to confirm until they set one.
+pub fn confirm_password(user: &User, password: &str) -> Result<(), (StatusCode, String)> {
+    match user.password_hash.as_deref() {
+        Some(hash) if verify_password(password, hash) => Ok(()),
+        Some(_) => Err((StatusCode::UNAUTHORIZED, "Password is incorrect".to_string())),
+        None => Err((
+            StatusCode::FORBIDDEN,
+            "This account signs in with single sign-on; set a password through a password reset first".to_string(),
+        )),
+    }
+}
+
+/// Lifetime of an access token, configurable through `ACCESS_TOKEN_TTL_MINUTES`.
+pub fn access_token_ttl() -> chrono::Duration {
+    let minutes = env::var("ACCESS_TOKEN_TTL_MINUTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(15);
+    chrono::Duration::minutes(minutes)
+}
+
+/// Lifetime of a refresh token, configurable through `REFRESH_TOKEN_TTL_DAYS`.
+pub fn refresh_token_ttl() -> chrono::Duration {
+    let days = env::var("REFRESH_TOKEN_TTL_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30);
+    chrono::Duration::days(days)
+}
+
+pub fn create_jwt(user_id: &str, session_id: &str) -> Result<String, String> {
     let expiration = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::days(7))
+        .checked_add_signed(access_token_ttl())
         .expect("valid timestamp")
         .timestamp() as usize;
 
     let claims = Claims {
         sub: user_id.to_string(),
+        sid: session_id.to_string(),
         exp: expiration,
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
+    keys::manager().sign(&claims)
+}
+
+/// Generates a random, URL-safe opaque token.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Opaque tokens are only ever stored as their SHA-256 digest.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Longest user agent kept for the session list.
+const MAX_USER_AGENT_LENGTH: usize = 256;
+/// `last_seen_at` is refreshed at most this often per session.
+const SESSION_TOUCH_SECONDS: i64 = 60;
+
+/// Where a session was started from, shown in the session list.
+pub struct SessionMetadata {
+    pub ip: String,
+    pub user_agent: Option<String>,
+}
+
+impl SessionMetadata {
+    pub fn new(ip: &str, headers: &HeaderMap) -> Self {
+        let user_agent = headers
+            .get(axum::http::header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(|ua| ua.chars().take(MAX_USER_AGENT_LENGTH).collect());
+
+        Self {
+            ip: ip.to_string(),
+            user_agent,
+        }
+    }
+}
+
+/// Opens a new session for `user_id` and returns its id and refresh token.
+pub async fn create_session(
+    pool: &SqlitePool,
+    user_id: &str,
+    metadata: &SessionMetadata,
+) -> Result<(String, String), String> {
+    let session_id = Uuid::new_v4().to_string();
+    let refresh_token = generate_token();
+    let now = chrono::Utc::now();
+
+    // Drop sessions that can no longer be refreshed
+    sqlx::query("DELETE FROM sessions WHERE user_id = ? AND (expires_at <= ? OR revoked_at IS NOT NULL)")
+        .bind(user_id)
+        .bind(now)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    sqlx::query(
+        "INSERT INTO sessions (id, user_id, refresh_token_hash, user_agent, ip, last_seen_at, expires_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&session_id)
+    .bind(user_id)
+    .bind(hash_token(&refresh_token))
+    .bind(&metadata.user_agent)
+    .bind(&metadata.ip)
+    .bind(now)
+    .bind(now + refresh_token_ttl())
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok((session_id, refresh_token))
+}
+
+/// Exchanges a refresh token for a new one. The presented token is invalidated,
+/// so each refresh token can be used exactly once.
+///
+/// Returns the user id, session id and the replacement refresh token.
+pub async fn rotate_refresh_token(
+    pool: &SqlitePool,
+    refresh_token: &str,
+) -> Result<Option<(String, String, String)>, String> {
+    let old_hash = hash_token(refresh_token);
+    let now = chrono::Utc::now();
+
+    let session = sqlx::query_as::<Sqlite, (String, String)>(
+        "SELECT id, user_id FROM sessions WHERE refresh_token_hash = ? AND revoked_at IS NULL AND expires_at > ?",
+    )
+    .bind(&old_hash)
+    .bind(now)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let Some((session_id, user_id)) = session else {
+        return Ok(None);
+    };
+
+    let new_token = generate_token();
+    let result = sqlx::query(
+        "UPDATE sessions SET refresh_token_hash = ?, expires_at = ?, last_seen_at = ? WHERE id = ? AND refresh_token_hash = ?",
+    )
+    .bind(hash_token(&new_token))
+    .bind(now + refresh_token_ttl())
+    .bind(now)
+    .bind(&session_id)
+    .bind(&old_hash)
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    // Another request rotated the same token first
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some((user_id, session_id, new_token)))
+}
+
+pub async fn revoke_session(pool: &SqlitePool, user_id: &str, session_id: &str) -> Result<bool, String> {
+    let result = sqlx::query("UPDATE sessions SET revoked_at = ? WHERE id = ? AND user_id = ? AND revoked_at IS NULL")
+        .bind(chrono::Utc::now())
+        .bind(session_id)
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn revoke_all_sessions(pool: &SqlitePool, user_id: &str) -> Result<u64, String> {
+    let result = sqlx::query("UPDATE sessions SET revoked_at = ? WHERE user_id = ? AND revoked_at IS NULL")
+        .bind(chrono::Utc::now())
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(result.rows_affected())
+}
+
+/// Expires every API token of `user_id` that is still valid. Used when the
+/// password changes, as a token minted by whoever knew the old one would
+/// otherwise outlive it. Returns how many tokens were cut off.
+pub async fn expire_api_tokens(pool: &SqlitePool, user_id: &str) -> Result<u64, String> {
+    let now = chrono::Utc::now();
+    let result =
+        sqlx::query("UPDATE api_tokens SET expires_at = ? WHERE user_id = ? AND (expires_at IS NULL OR expires_at > ?)")
+            .bind(now)
+            .bind(user_id)
+            .bind(now)
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    Ok(result.rows_affected())
+}
+
+/// What an emailed link is allowed to do.
+#[derive(Debug, Clone, Copy)]
+pub enum EmailTokenPurpose {
+    VerifyEmail,
+    ResetPassword,
+    ChangeEmail,
+}
+
+impl EmailTokenPurpose {
+    fn as_str(self) -> &'static str {
+        match self {
+            EmailTokenPurpose::VerifyEmail => "verify_email",
+            EmailTokenPurpose::ResetPassword => "reset_password",
+            EmailTokenPurpose::ChangeEmail => "change_email",
+        }
+    }
+}
+
+fn sign_email_token(
+    id: &str,
+    purpose: EmailTokenPurpose,
+    user_id: &str,
+    payload: Option<&str>,
+    expires_at: i64,
+) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(keys::manager().link_secret()).expect("HMAC accepts any key length");
+    mac.update(format!("{}:{}:{}:{}:{}", id, purpose.as_str(), user_id, payload.unwrap_or(""), expires_at).as_bytes());
+    mac
+}
+
+/// Issues a signed, expiring token for an emailed link. The token is
+/// `<id>.<signature>`; the row it points to makes it single-use. `payload`
+/// carries purpose-specific data, such as the new address for `ChangeEmail`.
+pub async fn issue_email_token(
+    pool: &SqlitePool,
+    user_id: &str,
+    purpose: EmailTokenPurpose,
+    payload: Option<&str>,
+    ttl: chrono::Duration,
+) -> Result<String, String> {
+    let id = Uuid::new_v4().to_string();
+    let expires_at = chrono::Utc::now() + ttl;
+
+    // A new link supersedes any outstanding one for the same purpose
+    sqlx::query("DELETE FROM email_tokens WHERE user_id = ? AND purpose = ?")
+        .bind(user_id)
+        .bind(purpose.as_str())
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    sqlx::query("INSERT INTO email_tokens (id, user_id, purpose, payload, expires_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(&id)
+        .bind(user_id)
+        .bind(purpose.as_str())
+        .bind(payload)
+        .bind(expires_at)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let signature = sign_email_token(&id, purpose, user_id, payload, expires_at.timestamp())
+        .finalize()
+        .into_bytes();
+
+    Ok(format!("{}.{}", id, URL_SAFE_NO_PAD.encode(signature)))
+}
+
+/// Checks an emailed token and marks it used. Returns the user it was issued
+/// to and its payload.
+pub async fn consume_email_token(
+    pool: &SqlitePool,
+    token: &str,
+    purpose: EmailTokenPurpose,
+) -> Result<Option<(String, Option<String>)>, String> {
+    let Some((id, signature)) = token.split_once('.') else {
+        return Ok(None);
+    };
+    let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+        return Ok(None);
+    };
+
+    let row = sqlx::query_as::<Sqlite, (String, Option<String>, chrono::DateTime<chrono::Utc>)>(
+        "SELECT user_id, payload, expires_at FROM email_tokens WHERE id = ? AND purpose = ? AND used_at IS NULL",
+    )
+    .bind(id)
+    .bind(purpose.as_str())
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let Some((user_id, payload, expires_at)) = row else {
+        return Ok(None);
+    };
+    if expires_at <= chrono::Utc::now() {
+        return Ok(None);
+    }
+    if sign_email_token(id, purpose, &user_id, payload.as_deref(), expires_at.timestamp())
+        .verify_slice(&signature)
+        .is_err()
+    {
+        return Ok(None);
+    }
+
+    let result = sqlx::query("UPDATE email_tokens SET used_at = ? WHERE id = ? AND used_at IS NULL")
+        .bind(chrono::Utc::now())
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok((result.rows_affected() > 0).then_some((user_id, payload)))
+}
+
+/// What an account whose email is not yet verified may do, set through
+/// `UNVERIFIED_ACCESS`: `full` (default), `read_only` or `none`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnverifiedAccess {
+    Full,
+    ReadOnly,
+    None,
+}
+
+impl UnverifiedAccess {
+    pub fn from_env() -> Self {
+        match env::var("UNVERIFIED_ACCESS").as_deref() {
+            Ok("read_only") => UnverifiedAccess::ReadOnly,
+            Ok("none") => UnverifiedAccess::None,
+            _ => UnverifiedAccess::Full,
+        }
+    }
+}
+
+pub async fn record_security_event(
+    pool: &SqlitePool,
+    user_id: &str,
+    kind: &str,
+    ip: Option<&str>,
+    detail: &str,
+) -> Result<(), String> {
+    sqlx::query("INSERT INTO security_events (id, user_id, kind, ip, detail) VALUES (?, ?, ?, ?, ?)")
+        .bind(Uuid::new_v4().to_string())
+        .bind(user_id)
+        .bind(kind)
+        .bind(ip)
+        .bind(detail)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Permanently deletes a user. Their tasks, expenses, chat history and
+/// everything else they own go with them through `ON DELETE CASCADE`,
+/// except for their search index entries, which are removed here.
+pub async fn delete_user(pool: &SqlitePool, user_id: &str) -> Result<bool, String> {
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let result = sqlx::query("DELETE FROM users WHERE id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM search_index WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Days a self-deleted account can still be recovered by logging in,
+/// configurable through `ACCOUNT_DELETION_GRACE_DAYS` (0 deletes immediately).
+pub fn account_deletion_grace() -> chrono::Duration {
+    let days = env::var("ACCOUNT_DELETION_GRACE_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(14);
+    chrono::Duration::days(days)
+}
+
+/// Hard-deletes accounts whose deletion grace period has run out.
+pub fn spawn_account_purge(pool: SqlitePool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            let due: Result<Vec<String>, _> =
+                sqlx::query_scalar("SELECT id FROM users WHERE deletion_scheduled_at IS NOT NULL AND deletion_scheduled_at <= ?")
+                    .bind(chrono::Utc::now())
+                    .fetch_all(&pool)
+                    .await;
+
+            match due {
+                Ok(ids) => {
+                    for id in ids {
+                        match delete_user(&pool, &id).await {
+                            Ok(_) => tracing::info!("Deleted account {} after its grace period", id),
+                            Err(e) => tracing::error!("Failed to delete account {}: {}", id, e),
+                        }
+                    }
+                }
+                Err(e) => tracing::error!("Failed to look up accounts pending deletion: {}", e),
+            }
+        }
+    });
+}
+
+pub const ROLE_USER: &str = "user";
+pub const ROLE_ADMIN: &str = "admin";
+pub const ROLES: &[&str] = &[ROLE_USER, ROLE_ADMIN];
+
+/// Emails listed in `ADMIN_EMAILS` (comma separated) are made admins, which
+/// is how the first admin of an instance is bootstrapped.
+pub fn is_bootstrap_admin(email: &str) -> bool {
+    env::var("ADMIN_EMAILS")
+        .unwrap_or_default()
+        .split(',')
+        .any(|admin| !admin.trim().is_empty() && admin.trim().eq_ignore_ascii_case(email.trim()))
+}
+
+pub async fn promote_bootstrap_admins(pool: &SqlitePool) -> Result<(), String> {
+    let emails: Vec<String> = sqlx::query_scalar("SELECT email FROM users WHERE role != ?")
+        .bind(ROLE_ADMIN)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    for email in emails.iter().filter(|e| is_bootstrap_admin(e)) {
+        sqlx::query("UPDATE users SET role = ? WHERE email = ?")
+            .bind(ROLE_ADMIN)
+            .bind(email)
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        tracing::info!("Promoted {} to admin", email);
+    }
+
+    Ok(())
+}
+
+/// Scopes a personal access token can be granted.
+pub const API_TOKEN_SCOPES: &[&str] = &[
+    "tasks:read",
+    "tasks:write",
+    "expenses:read",
+    "expenses:write",
+    "ai:use",
+];
+
+/// Prefix that tells personal access tokens apart from JWTs.
+pub const API_TOKEN_PREFIX: &str = "pat_";
+
+/// How a request proved who it is.
+#[derive(Debug, Clone)]
+pub enum Credential {
+    Session(String),
+    ApiToken { scopes: Vec<String> },
+}
+
+/// The resolved identity behind a request.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub user: User,
+    pub credential: Credential,
+}
+
+/// Resolves the bearer token on a request to a user, accepting both session
+/// JWTs and personal access tokens.
+pub async fn authenticate(pool: &SqlitePool, headers: &HeaderMap) -> Result<Principal, (StatusCode, String)> {
+    let token = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "Missing authorization header".to_string()))?;
+
+    if token.starts_with(API_TOKEN_PREFIX) {
+        authenticate_api_token(pool, token).await
+    } else {
+        authenticate_jwt(pool, token).await
+    }
+}
+
+async fn authenticate_jwt(pool: &SqlitePool, token: &str) -> Result<Principal, (StatusCode, String)> {
+    let Claims { sub: user_id, sid: session_id, .. } = keys::manager()
+        .verify::<Claims>(token)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+    let now = chrono::Utc::now();
+
+    let user = sqlx::query_as::<Sqlite, User>(
+        "SELECT users.* FROM users
+         JOIN sessions ON sessions.user_id = users.id
+         WHERE users.id = ? AND sessions.id = ? AND sessions.revoked_at IS NULL AND sessions.expires_at > ?
+           AND users.disabled_at IS NULL",
     )
-    .map_err(|e| format!("Failed to create token: {}", e))
+    .bind(user_id)
+    .bind(&session_id)
+    .bind(now)
+    .fetch_one(pool)
+    .await
+    .map_err(|_| (StatusCode::UNAUTHORIZED, "Session expired or revoked".to_string()))?;
+
+    sqlx::query("UPDATE sessions SET last_seen_at = ? WHERE id = ? AND (last_seen_at IS NULL OR last_seen_at < ?)")
+        .bind(now)
+        .bind(&session_id)
+        .bind(now - chrono::Duration::seconds(SESSION_TOUCH_SECONDS))
+        .execute(pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Principal {
+        user,
+        credential: Credential::Session(session_id),
+    })
+}
+
+async fn authenticate_api_token(pool: &SqlitePool, token: &str) -> Result<Principal, (StatusCode, String)> {
+    let now = chrono::Utc::now();
+
+    let api_token = sqlx::query_as::<Sqlite, ApiToken>(
+        "SELECT * FROM api_tokens WHERE token_hash = ? AND (expires_at IS NULL OR expires_at > ?)",
+    )
+    .bind(hash_token(token))
+    .bind(now)
+    .fetch_one(pool)
+    .await
+    .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid or expired API token".to_string()))?;
+
+    let user = sqlx::query_as::<Sqlite, User>("SELECT * FROM users WHERE id = ? AND disabled_at IS NULL AND deletion_scheduled_at IS NULL")
+        .bind(&api_token.user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "User not found".to_string()))?;
+
+    sqlx::query("UPDATE api_tokens SET last_used_at = ? WHERE id = ?")
+        .bind(now)
+        .bind(&api_token.id)
+        .execute(pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Principal {
+        user,
+        credential: Credential::ApiToken {
+            scopes: api_token.scopes.0,
+        },
+    })
+}
+
+/// Scopes an API token needs to reach a group of routes: `read` for safe
+/// methods, `write` for everything else. Session JWTs are not restricted.
+#[derive(Clone)]
+pub struct ScopeGuard {
+    pub db: SqlitePool,
+    pub read: &'static str,
+    pub write: &'static str,
+}
+
+impl ScopeGuard {
+    pub fn new(db: &SqlitePool, read: &'static str, write: &'static str) -> Self {
+        Self {
+            db: db.clone(),
+            read,
+            write,
+        }
+    }
+}
+
+/// Route middleware enforcing a [`ScopeGuard`]. The resolved [`Principal`] is
+/// stashed in the request extensions so the handler's extractor can reuse it.
+pub async fn require_scope(
+    State(guard): State<ScopeGuard>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, (StatusCode, String)> {
+    let principal = authenticate(&guard.db, request.headers()).await?;
+
+    if let Credential::ApiToken { scopes, .. } = &principal.credential {
+        let needed = match *request.method() {
+            Method::GET | Method::HEAD => guard.read,
+            _ => guard.write,
+        };
+        if !scopes.iter().any(|s| s == needed) {
+            return Err((StatusCode::FORBIDDEN, format!("API token is missing the '{}' scope", needed)));
+        }
+    }
+
+    request.extensions_mut().insert(principal);
+    Ok(next.run(request).await)
+}
+
+async fn principal_from_parts(parts: &Parts, pool: &SqlitePool) -> Result<Principal, (StatusCode, String)> {
+    // Already resolved (and scope-checked) by `require_scope`
+    if let Some(principal) = parts.extensions.get::<Principal>() {
+        return Ok(principal.clone());
+    }
+
+    let principal = authenticate(pool, &parts.headers).await?;
+
+    // Routes without a scope guard are reserved for interactive sessions
+    if let Credential::ApiToken { .. } = principal.credential {
+        return Err((StatusCode::FORBIDDEN, "API tokens cannot access this endpoint".to_string()));
+    }
+
+    Ok(principal)
+}
+
+/// An authenticated user together with the session their token belongs to.
+pub struct AuthSession {
+    pub user: User,
+    pub session_id: String,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthSession
+where
+    SqlitePool: axum::extract::FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let pool = SqlitePool::from_ref(state);
+
+        match principal_from_parts(parts, &pool).await? {
+            Principal {
+                user,
+                credential: Credential::Session(session_id),
+            } => Ok(AuthSession { user, session_id }),
+            _ => Err((StatusCode::FORBIDDEN, "This endpoint requires a login session".to_string())),
+        }
+    }
 }
 
 pub struct AuthenticatedUser(pub User);
@@ -70,30 +699,102 @@ where
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let pool = SqlitePool::from_ref(state);
-        let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "change_me_immediately".to_string());
+        let principal = principal_from_parts(parts, &pool).await?;
 
-        let auth_header = parts
-            .headers
-            .get(axum::http::header::AUTHORIZATION)
-            .and_then(|h| h.to_str().ok())
-            .and_then(|h| h.strip_prefix("Bearer "))
-            .ok_or_else(|| (StatusCode::UNAUTHORIZED, "Missing authorization header".to_string()))?;
-
-        let token_data = decode::<Claims>(
-            auth_header,
-            &DecodingKey::from_secret(secret.as_bytes()),
-            &Validation::default(),
-        )
-        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+        if principal.user.email_verified_at.is_none() {
+            let allowed = match UnverifiedAccess::from_env() {
+                UnverifiedAccess::Full => true,
+                UnverifiedAccess::ReadOnly => matches!(parts.method, Method::GET | Method::HEAD),
+                UnverifiedAccess::None => false,
+            };
+            if !allowed {
+                return Err((StatusCode::FORBIDDEN, "Verify your email address to continue".to_string()));
+            }
+        }
 
-        let user_id = token_data.claims.sub;
+        Ok(AuthenticatedUser(principal.user))
+    }
+}
 
-        let user = sqlx::query_as::<Sqlite, User>("SELECT * FROM users WHERE id = ?")
-            .bind(user_id)
-            .fetch_one(&pool)
-            .await
-            .map_err(|_| (StatusCode::UNAUTHORIZED, "User not found".to_string()))?;
+/// An authenticated user with the admin role.
+pub struct AdminUser(pub User);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminUser
+where
+    SqlitePool: axum::extract::FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthenticatedUser(user) = AuthenticatedUser::from_request_parts(parts, state).await?;
+
+        if user.role != ROLE_ADMIN {
+            return Err((StatusCode::FORBIDDEN, "Admin access required".to_string()));
+        }
+
+        Ok(AdminUser(user))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{TestApp, TestResponse};
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    async fn refresh(app: &TestApp, refresh_token: &str) -> TestResponse {
+        let body = json!({ "refresh_token": refresh_token });
+        app.request(Method::POST, "/api/auth/refresh", None, Some(body)).await
+    }
 
-        Ok(AuthenticatedUser(user))
+    /// Registers and logs in, returning the access and refresh tokens.
+    async fn sign_in(app: &TestApp) -> (String, String) {
+        app.sign_up("ann@example.com").await;
+        let login = app.login("ann@example.com").await;
+        let token = login.body["token"].as_str().unwrap().to_string();
+        (token, login.body["refresh_token"].as_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn refresh_rotates_the_refresh_token() {
+        let app = TestApp::new().await;
+        let (_, first) = sign_in(&app).await;
+
+        let refreshed = refresh(&app, &first).await;
+        assert_eq!(refreshed.status, StatusCode::OK, "{}", refreshed.body);
+        let second = refreshed.body["refresh_token"].as_str().unwrap();
+        assert_ne!(second, first);
+        assert_eq!(app.get("/api/auth/me", refreshed.body["token"].as_str().unwrap()).await.status, StatusCode::OK);
+
+        // The replaced token is spent, the new one still works
+        assert_eq!(refresh(&app, &first).await.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(refresh(&app, second).await.status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn logout_revokes_the_session() {
+        let app = TestApp::new().await;
+        let (token, refresh_token) = sign_in(&app).await;
+
+        let logout = app.post("/api/auth/logout", &token, json!({})).await;
+        assert_eq!(logout.status, StatusCode::NO_CONTENT);
+        assert_eq!(app.get("/api/auth/me", &token).await.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(refresh(&app, &refresh_token).await.status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn rejects_an_expired_session() {
+        let app = TestApp::new().await;
+        let (token, refresh_token) = sign_in(&app).await;
+
+        sqlx::query("UPDATE sessions SET expires_at = ?")
+            .bind(chrono::Utc::now() - chrono::Duration::minutes(1))
+            .execute(&app.state.db)
+            .await
+            .unwrap();
+        assert_eq!(app.get("/api/auth/me", &token).await.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(refresh(&app, &refresh_token).await.status, StatusCode::UNAUTHORIZED);
     }
 }
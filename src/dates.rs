@@ -0,0 +1,58 @@
+//! Task dates. Instants are stored in UTC; a task's IANA time zone decides
+//! how local times are read and where its days begin.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+pub fn parse_time_zone(name: &str) -> Result<Tz, String> {
+    name.parse::<Tz>().map_err(|_| format!("Unknown time zone '{}'", name))
+}
+
+/// Accepts RFC 3339 (`2026-03-01T09:00:00+01:00`) or a local date and time
+/// without an offset (`2026-03-01T09:00`), which is read in `tz`.
+pub fn parse_datetime(input: &str, tz: Tz) -> Result<DateTime<Utc>, String> {
+    let input = input.trim();
+    if let Ok(instant) = DateTime::parse_from_rfc3339(input) {
+        return Ok(instant.with_timezone(&Utc));
+    }
+
+    let local = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+        .ok_or_else(|| format!("Invalid date '{}', expected RFC 3339 or YYYY-MM-DDTHH:MM", input))?;
+
+    local_to_utc(tz, local).ok_or_else(|| format!("'{}' does not exist in {}", input, tz))
+}
+
+/// The instant a local time falls on. Ambiguous times (clocks going back)
+/// take the earlier one; `None` for times skipped by a DST change.
+pub fn local_to_utc(tz: Tz, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    tz.from_local_datetime(&local).earliest().map(|t| t.with_timezone(&Utc))
+}
+
+/// Midnight at the start of `date` in `tz`. Days whose midnight is skipped
+/// by a DST change start at the first valid minute after it.
+pub fn start_of_day(tz: Tz, date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("midnight is valid");
+    (0..=120)
+        .find_map(|minutes| local_to_utc(tz, midnight + Duration::minutes(minutes)))
+        .expect("DST gaps are shorter than two hours")
+}
+
+/// `[start, end)` of the day containing `now` in `tz`.
+pub fn day_bounds(tz: Tz, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+    let today = now.with_timezone(&tz).date_naive();
+    (start_of_day(tz, today), start_of_day(tz, today + Duration::days(1)))
+}
+
+/// `[start, end)` of the Monday-to-Sunday week containing `now` in `tz`.
+pub fn week_bounds(tz: Tz, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+    let today = now.with_timezone(&tz).date_naive();
+    let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    (start_of_day(tz, monday), start_of_day(tz, monday + Duration::days(7)))
+}
+
+/// Human-readable local time for prompts, e.g. `Mon 2 Mar 2026 09:00 (Europe/Berlin)`.
+pub fn describe(instant: DateTime<Utc>, tz: Tz) -> String {
+    format!("{} ({})", instant.with_timezone(&tz).format("%a %-d %b %Y %H:%M"), tz)
+}
@@ -2,13 +2,82 @@ use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
 use std::env;
 use std::fs;
 
+/// Columns added to tables after their first release. `CREATE TABLE IF NOT EXISTS`
+/// leaves existing tables untouched, so databases created by an older build get
+/// these through `ALTER TABLE` on startup. New columns must also be added to the
+/// matching `CREATE TABLE` in schema.sql.
+const COLUMN_UPGRADES: &[(&str, &str, &str)] = &[
+    ("users", "totp_secret", "TEXT"),
+    ("users", "totp_enabled", "BOOLEAN NOT NULL DEFAULT FALSE"),
+    ("users", "totp_last_step", "INTEGER"),
+    ("users", "email_verified_at", "DATETIME"),
+    ("users", "role", "TEXT NOT NULL DEFAULT 'user'"),
+    ("users", "disabled_at", "DATETIME"),
+    ("users", "deletion_scheduled_at", "DATETIME"),
+    ("email_tokens", "payload", "TEXT"),
+    ("sessions", "user_agent", "TEXT"),
+    ("sessions", "ip", "TEXT"),
+    ("sessions", "last_seen_at", "DATETIME"),
+    ("tasks", "start_at", "DATETIME"),
+    ("tasks", "due_at", "DATETIME"),
+    ("tasks", "time_zone", "TEXT"),
+    ("tasks", "priority", "INTEGER NOT NULL DEFAULT 2"),
+    ("tasks", "project_id", "TEXT REFERENCES projects(id) ON DELETE SET NULL"),
+    ("tasks", "parent_id", "TEXT REFERENCES tasks(id) ON DELETE CASCADE"),
+    ("tasks", "series_id", "TEXT REFERENCES task_series(id) ON DELETE SET NULL"),
+    ("tasks", "notes", "TEXT"),
+    ("tasks", "description", "TEXT"),
+    ("expenses", "notes", "TEXT"),
+    ("tasks", "state_id", "TEXT REFERENCES workflow_states(id) ON DELETE SET NULL"),
+    ("tasks", "position", "TEXT"),
+    ("tasks", "deleted_at", "DATETIME"),
+    ("expenses", "deleted_at", "DATETIME"),
+    ("tasks", "updated_at", "DATETIME"),
+];
+
+/// Indexes on columns from `COLUMN_UPGRADES`. schema.sql runs before those
+/// columns exist on older databases, so these are created after the upgrade.
+const UPGRADED_INDEXES: &[&str] = &[
+    "CREATE INDEX IF NOT EXISTS idx_tasks_project ON tasks(project_id)",
+    "CREATE INDEX IF NOT EXISTS idx_tasks_parent ON tasks(parent_id)",
+    "CREATE INDEX IF NOT EXISTS idx_tasks_series ON tasks(series_id)",
+    "CREATE INDEX IF NOT EXISTS idx_tasks_state ON tasks(state_id)",
+    "CREATE INDEX IF NOT EXISTS idx_tasks_position ON tasks(user_id, position)",
+    "CREATE INDEX IF NOT EXISTS idx_tasks_deleted ON tasks(deleted_at) WHERE deleted_at IS NOT NULL",
+    "CREATE INDEX IF NOT EXISTS idx_expenses_deleted ON expenses(deleted_at) WHERE deleted_at IS NOT NULL",
+];
+
+/// Keep `tasks.updated_at` current, so undo can tell a task was changed after
+/// the operation it would revert. Moving a task in or out of the trash is what
+/// undo itself does, so it doesn't count. The timestamp is formatted the way
+/// chrono values are bound. Created last, as rebuilding a table drops its triggers.
+const TRIGGERS: &[&str] = &[
+    "CREATE TRIGGER IF NOT EXISTS tasks_updated_at AFTER UPDATE OF title, notes, description, completed, state_id,
+         priority, start_at, due_at, time_zone, project_id, parent_id, series_id, position ON tasks
+     BEGIN
+         UPDATE tasks SET updated_at = strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now') WHERE id = NEW.id;
+     END",
+    "CREATE TRIGGER IF NOT EXISTS task_tags_added AFTER INSERT ON task_tags
+     BEGIN
+         UPDATE tasks SET updated_at = strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now') WHERE id = NEW.task_id;
+     END",
+    "CREATE TRIGGER IF NOT EXISTS task_tags_removed AFTER DELETE ON task_tags
+     BEGIN
+         UPDATE tasks SET updated_at = strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now') WHERE id = OLD.task_id;
+     END",
+];
+
+/// Tables first created with a plain `REFERENCES users(id)`. Deleting an
+/// account relies on their rows going with it, so older databases get these
+/// rebuilt with `ON DELETE CASCADE`.
+const CASCADE_UPGRADES: &[&str] = &["tasks", "expenses", "chat_messages"];
+
 pub async fn init_db() -> Result<SqlitePool, String> {
     let database_url = env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:assistant.db".to_string());
 
     // Create database file if it doesn't exist
-    if database_url.starts_with("sqlite:") {
-        let path = &database_url[7..];
-        if !fs::metadata(path).is_ok() {
+    if let Some(path) = database_url.strip_prefix("sqlite:") {
+        if fs::metadata(path).is_err() {
             fs::File::create(path).map_err(|e| format!("Failed to create db file: {}", e))?;
         }
     }
@@ -19,14 +88,154 @@ pub async fn init_db() -> Result<SqlitePool, String> {
         .await
         .map_err(|e| format!("Failed to connect to database: {}", e))?;
 
-    // Run migrations (schema)
+    migrate(&pool).await?;
+    Ok(pool)
+}
+
+/// Creates the schema, or brings an existing database up to date with it.
+pub async fn migrate(pool: &SqlitePool) -> Result<(), String> {
     let schema = fs::read_to_string("schema.sql")
         .map_err(|e| format!("Failed to read schema.sql: {}", e))?;
 
     sqlx::query(&schema)
-        .execute(&pool)
+        .execute(pool)
         .await
         .map_err(|e| format!("Failed to initialize schema: {}", e))?;
 
-    Ok(pool)
+    upgrade_columns(pool).await?;
+    upgrade_user_foreign_keys(pool).await?;
+    upgrade_optional_password(pool).await?;
+
+    for trigger in TRIGGERS {
+        sqlx::query(trigger)
+            .execute(pool)
+            .await
+            .map_err(|e| format!("Failed to create trigger: {}", e))?;
+    }
+
+    Ok(())
+}
+
+async fn upgrade_columns(pool: &SqlitePool) -> Result<(), String> {
+    for (table, column, definition) in COLUMN_UPGRADES {
+        let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE name = ?")
+            .bind(table)
+            .bind(column)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| format!("Failed to inspect table {}: {}", table, e))?;
+
+        if !exists {
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                .execute(pool)
+                .await
+                .map_err(|e| format!("Failed to add column {}.{}: {}", table, column, e))?;
+        }
+    }
+
+    for index in UPGRADED_INDEXES {
+        sqlx::query(index)
+            .execute(pool)
+            .await
+            .map_err(|e| format!("Failed to create index: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// SQLite cannot alter a foreign key, so each affected table is rebuilt.
+async fn upgrade_user_foreign_keys(pool: &SqlitePool) -> Result<(), String> {
+    for table in CASCADE_UPGRADES {
+        let outdated: bool = sqlx::query_scalar(
+            "SELECT COUNT(*) > 0 FROM pragma_foreign_key_list(?) WHERE \"table\" = 'users' AND on_delete != 'CASCADE'",
+        )
+        .bind(table)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to inspect table {}: {}", table, e))?;
+
+        if outdated {
+            rebuild_table(pool, table, |sql| {
+                sql.replace("REFERENCES users(id)", "REFERENCES users(id) ON DELETE CASCADE")
+            })
+            .await?;
+            tracing::info!("Rebuilt {} with ON DELETE CASCADE", table);
+        }
+    }
+
+    Ok(())
+}
+
+/// Accounts that only sign in through OpenID Connect have no password, but
+/// `users.password_hash` started out `NOT NULL`.
+async fn upgrade_optional_password(pool: &SqlitePool) -> Result<(), String> {
+    let required: bool =
+        sqlx::query_scalar("SELECT COUNT(*) > 0 FROM pragma_table_info('users') WHERE name = 'password_hash' AND \"notnull\" = 1")
+            .fetch_one(pool)
+            .await
+            .map_err(|e| format!("Failed to inspect table users: {}", e))?;
+
+    if required {
+        rebuild_table(pool, "users", |sql| sql.replace("password_hash TEXT NOT NULL", "password_hash TEXT")).await?;
+        tracing::info!("Rebuilt users with an optional password_hash");
+    }
+
+    Ok(())
+}
+
+/// Rebuilds `table` from its current definition passed through `alter`,
+/// following https://www.sqlite.org/lang_altertable.html#otheralter. Column
+/// order must stay the same, as rows are copied with `SELECT *`.
+async fn rebuild_table(pool: &SqlitePool, table: &str, alter: impl Fn(&str) -> String) -> Result<(), String> {
+    let table_sql: String = sqlx::query_scalar("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?")
+        .bind(table)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to read definition of {}: {}", table, e))?;
+    let index_sql: Vec<String> =
+        sqlx::query_scalar("SELECT sql FROM sqlite_master WHERE type = 'index' AND tbl_name = ? AND sql IS NOT NULL")
+            .bind(table)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to read indexes of {}: {}", table, e))?;
+
+    // A table that was renamed before has its name quoted in sqlite_master
+    let rebuilt = format!("{}_rebuild", table);
+    let create_rebuilt = alter(&table_sql)
+        .replacen(&format!("CREATE TABLE \"{}\"", table), &format!("CREATE TABLE {}", rebuilt), 1)
+        .replacen(&format!("CREATE TABLE {} ", table), &format!("CREATE TABLE {} ", rebuilt), 1);
+
+    // Foreign keys must be off while the old table is dropped, or rows in
+    // tables referencing it would be cascaded away. The pragma is per
+    // connection and ignored inside a transaction, hence the dedicated one.
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut statements = vec![
+        "BEGIN".to_string(),
+        create_rebuilt,
+        format!("INSERT INTO {} SELECT * FROM {}", rebuilt, table),
+        format!("DROP TABLE {}", table),
+        format!("ALTER TABLE {} RENAME TO {}", rebuilt, table),
+    ];
+    statements.extend(index_sql);
+    statements.push("COMMIT".to_string());
+
+    let mut result = Ok(());
+    for statement in &statements {
+        if let Err(e) = sqlx::query(statement).execute(&mut *conn).await {
+            let _ = sqlx::query("ROLLBACK").execute(&mut *conn).await;
+            result = Err(format!("Failed to rebuild {}: {}", table, e));
+            break;
+        }
+    }
+
+    sqlx::query("PRAGMA foreign_keys = ON")
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    result
 }
@@ -0,0 +1,252 @@
+//! Sort keys that order tasks by plain string comparison, so a task can be
+//! moved between two others by giving it a key between theirs, without
+//! touching any other row.
+//!
+//! A key is an integer part followed by an optional fraction, both in base
+//! 62. The integer part's first character encodes its length ('a' is one
+//! digit, 'b' two, …; 'Z', 'Y', … are the negatives), so appending keeps keys
+//! short. The fraction only grows when moves keep landing in the same gap,
+//! and never ends in '0', so there is always room below it.
+
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const SMALLEST_INTEGER: &str = "A00000000000000000000000000";
+
+fn digit_value(digit: u8) -> usize {
+    DIGITS.iter().position(|d| *d == digit).unwrap_or(0)
+}
+
+/// Length of the integer part starting with `head`, head included.
+fn integer_length(head: u8) -> Option<usize> {
+    match head {
+        b'a'..=b'z' => Some((head - b'a') as usize + 2),
+        b'A'..=b'Z' => Some((b'Z' - head) as usize + 2),
+        _ => None,
+    }
+}
+
+/// Splits a key into its integer part and fraction, or `None` for a key
+/// this module didn't make. The smallest integer part on its own is never
+/// handed out, since nothing could be placed before it.
+fn split(key: &str) -> Option<(&str, &str)> {
+    let length = integer_length(*key.as_bytes().first()?)?;
+    if key.len() < length || !key.bytes().all(|b| DIGITS.contains(&b)) || key[length..].ends_with('0') {
+        return None;
+    }
+    if key == SMALLEST_INTEGER {
+        return None;
+    }
+    Some(key.split_at(length))
+}
+
+fn increment_integer(integer: &str) -> Option<String> {
+    let (head, digits) = integer.as_bytes().split_first()?;
+    let mut digits = digits.to_vec();
+    for digit in digits.iter_mut().rev() {
+        if *digit != b'z' {
+            *digit = DIGITS[digit_value(*digit) + 1];
+            return Some(format!("{}{}", *head as char, String::from_utf8_lossy(&digits)));
+        }
+        *digit = b'0';
+    }
+    // Every digit carried over, so the integer part grows by one digit
+    match head {
+        b'Z' => Some("a0".to_string()),
+        b'z' => None,
+        _ => {
+            let head = head + 1;
+            if head > b'a' {
+                digits.push(b'0');
+            } else {
+                digits.pop();
+            }
+            Some(format!("{}{}", head as char, String::from_utf8_lossy(&digits)))
+        }
+    }
+}
+
+fn decrement_integer(integer: &str) -> Option<String> {
+    let (head, digits) = integer.as_bytes().split_first()?;
+    let mut digits = digits.to_vec();
+    for digit in digits.iter_mut().rev() {
+        if *digit != b'0' {
+            *digit = DIGITS[digit_value(*digit) - 1];
+            return Some(format!("{}{}", *head as char, String::from_utf8_lossy(&digits)));
+        }
+        *digit = b'z';
+    }
+    match head {
+        b'a' => Some("Zz".to_string()),
+        b'A' => None,
+        _ => {
+            let head = head - 1;
+            if head < b'Z' {
+                digits.push(b'z');
+            } else {
+                digits.pop();
+            }
+            Some(format!("{}{}", head as char, String::from_utf8_lossy(&digits)))
+        }
+    }
+}
+
+/// A fraction between `low` and `high` (`None` meaning above every
+/// fraction). Needs `low < high`.
+fn midpoint(low: &str, high: Option<&str>) -> String {
+    if let Some(high) = high {
+        // Keep the shared prefix, padding `low` with zeros
+        let shared = high
+            .bytes()
+            .zip(low.bytes().chain(std::iter::repeat(b'0')))
+            .take_while(|(h, l)| h == l)
+            .count();
+        if shared > 0 {
+            let low = low.get(shared..).unwrap_or("");
+            return format!("{}{}", &high[..shared], midpoint(low, Some(&high[shared..])));
+        }
+    }
+
+    let low_digit = low.bytes().next().map(digit_value).unwrap_or(0);
+    let high_digit = high.and_then(|high| high.bytes().next()).map(digit_value).unwrap_or(DIGITS.len());
+    if high_digit - low_digit > 1 {
+        // Rounding half up, as the reference implementation does
+        return (DIGITS[(low_digit + high_digit).div_ceil(2)] as char).to_string();
+    }
+    match high {
+        // The high fraction's first digit alone already sorts below it
+        Some(high) if high.len() > 1 => high[..1].to_string(),
+        _ => format!("{}{}", DIGITS[low_digit] as char, midpoint(low.get(1..).unwrap_or(""), None)),
+    }
+}
+
+/// A key sorting after `low` and before `high`; `None` leaves that side
+/// open. Fails on keys this module didn't make or when `low` isn't below
+/// `high`.
+pub fn key_between(low: Option<&str>, high: Option<&str>) -> Result<String, String> {
+    let invalid = |key: &str| format!("Invalid sort key '{}'", key);
+    match (low, high) {
+        (None, None) => Ok("a0".to_string()),
+        (None, Some(high)) => {
+            let (integer, fraction) = split(high).ok_or_else(|| invalid(high))?;
+            if integer == SMALLEST_INTEGER {
+                return Ok(format!("{}{}", integer, midpoint("", Some(fraction))));
+            }
+            if !fraction.is_empty() {
+                return Ok(integer.to_string());
+            }
+            decrement_integer(integer).ok_or_else(|| "Sort keys ran out of room".to_string())
+        }
+        (Some(low), None) => {
+            let (integer, fraction) = split(low).ok_or_else(|| invalid(low))?;
+            Ok(increment_integer(integer).unwrap_or_else(|| format!("{}{}", integer, midpoint(fraction, None))))
+        }
+        (Some(low), Some(high)) => {
+            let (low_integer, low_fraction) = split(low).ok_or_else(|| invalid(low))?;
+            let (high_integer, high_fraction) = split(high).ok_or_else(|| invalid(high))?;
+            if low >= high {
+                return Err(format!("Sort key '{}' is not below '{}'", low, high));
+            }
+            if low_integer == high_integer {
+                return Ok(format!("{}{}", low_integer, midpoint(low_fraction, Some(high_fraction))));
+            }
+            match increment_integer(low_integer) {
+                Some(next) if next.as_str() < high => Ok(next),
+                _ => Ok(format!("{}{}", low_integer, midpoint(low_fraction, None))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn between(low: Option<&str>, high: Option<&str>) -> String {
+        key_between(low, high).unwrap()
+    }
+
+    #[test]
+    fn matches_the_reference_implementation() {
+        let cases = [
+            (None, None, "a0"),
+            (None, Some("a0"), "Zz"),
+            (None, Some("Zz"), "Zy"),
+            (Some("a0"), None, "a1"),
+            (Some("a1"), None, "a2"),
+            (Some("a0"), Some("a1"), "a0V"),
+            (Some("a1"), Some("a2"), "a1V"),
+            (Some("a0V"), Some("a1"), "a0l"),
+            (Some("Zz"), Some("a0"), "ZzV"),
+            (Some("Zz"), Some("a1"), "a0"),
+            (None, Some("Y00"), "Xzzz"),
+            (Some("bzz"), None, "c000"),
+            (Some("a0"), Some("a0V"), "a0G"),
+            (Some("a0"), Some("a0G"), "a08"),
+            (Some("b125"), Some("b129"), "b127"),
+            (Some("a0"), Some("a1V"), "a1"),
+            (Some("Zz"), Some("a01"), "a0"),
+            (None, Some("a0V"), "a0"),
+            (None, Some("b999"), "b99"),
+            (None, Some("A000000000000000000000000001"), "A000000000000000000000000000V"),
+            (Some("zzzzzzzzzzzzzzzzzzzzzzzzzzy"), None, "zzzzzzzzzzzzzzzzzzzzzzzzzzz"),
+            (Some("zzzzzzzzzzzzzzzzzzzzzzzzzzz"), None, "zzzzzzzzzzzzzzzzzzzzzzzzzzzV"),
+        ];
+        for (low, high, expected) in cases {
+            assert_eq!(between(low, high), expected, "between {:?} and {:?}", low, high);
+        }
+    }
+
+    #[test]
+    fn stays_ordered_across_repeated_inserts_into_one_gap() {
+        // Always just above the low end, then always just below the high end
+        let (mut low, high) = ("a0".to_string(), "a1".to_string());
+        for _ in 0..100 {
+            let key = between(Some(&low), Some(&high));
+            assert!(low < key && key < high, "{} not between {} and {}", key, low, high);
+            assert!(split(&key).is_some(), "{} is not a valid key", key);
+            low = key;
+        }
+        let (low, mut high) = ("a0".to_string(), "a1".to_string());
+        for _ in 0..100 {
+            let key = between(Some(&low), Some(&high));
+            assert!(low < key && key < high, "{} not between {} and {}", key, low, high);
+            assert!(split(&key).is_some(), "{} is not a valid key", key);
+            high = key;
+        }
+    }
+
+    #[test]
+    fn stays_ordered_when_appending_and_prepending() {
+        let mut keys = vec![between(None, None)];
+        for _ in 0..100 {
+            keys.push(between(keys.last().map(String::as_str), None));
+            keys.insert(0, between(None, keys.first().map(String::as_str)));
+        }
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn rolls_the_integer_part_over_in_both_directions() {
+        assert_eq!(between(Some("Zz"), None), "a0");
+        assert_eq!(between(None, Some("a0")), "Zz");
+        assert_eq!(between(Some("az"), None), "b00");
+        assert_eq!(between(None, Some("b00")), "az");
+        assert_eq!(between(Some("Yzz"), None), "Z0");
+        assert_eq!(between(None, Some("Z0")), "Yzz");
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        for key in ["", "a", "a00", "a0!", "0a", "b1", SMALLEST_INTEGER] {
+            assert!(key_between(Some(key), None).is_err(), "accepted {:?} as low", key);
+            assert!(key_between(None, Some(key)).is_err(), "accepted {:?} as high", key);
+        }
+        assert!(key_between(Some("a00"), Some("a1")).is_err());
+    }
+
+    #[test]
+    fn rejects_low_not_below_high() {
+        assert!(key_between(Some("a1"), Some("a0")).is_err());
+        assert!(key_between(Some("a0"), Some("a0")).is_err());
+        assert!(key_between(Some("a0V"), Some("a0G")).is_err());
+    }
+}
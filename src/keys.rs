@@ -0,0 +1,412 @@
+//! Keys for signing and verifying access tokens.
+//!
+//! Tokens are signed with one active key and carry its `kid`. Verification
+//! accepts the active key plus any previous keys still configured, so a key
+//! can be rotated without logging everybody out: add the new key, keep the
+//! old one as a verification key until its tokens have expired, then drop it.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm, OctetKeyPairParameters,
+    OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rsa::{pkcs1::DecodeRsaPublicKey, pkcs8::DecodePublicKey, traits::PublicKeyParts, RsaPublicKey};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::sync::OnceLock;
+
+/// The secret the server used to fall back to; refusing it stops a
+/// deployment from running with a publicly known signing key.
+const DEFAULT_SECRET: &str = "change_me_immediately";
+const MIN_SECRET_LENGTH: usize = 32;
+
+/// DER prefix of an Ed25519 `SubjectPublicKeyInfo`; the raw key follows it.
+const ED25519_SPKI_PREFIX: [u8; 12] = [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
+static KEYS: OnceLock<KeyManager> = OnceLock::new();
+
+/// Loads the keys from the environment. Must run once at startup, before
+/// any token is issued or checked.
+pub fn init() -> Result<&'static KeyManager, String> {
+    let manager = KeyManager::from_env()?;
+    Ok(KEYS.get_or_init(|| manager))
+}
+
+pub fn manager() -> &'static KeyManager {
+    KEYS.get().expect("keys::init must run at startup")
+}
+
+struct VerificationKey {
+    kid: String,
+    algorithm: Algorithm,
+    key: DecodingKey,
+    /// Public half for the JWKS document; `None` for shared secrets.
+    jwk: Option<Jwk>,
+}
+
+pub struct KeyManager {
+    kid: String,
+    algorithm: Algorithm,
+    signing_key: EncodingKey,
+    verification_keys: Vec<VerificationKey>,
+    link_secret: Vec<u8>,
+}
+
+impl KeyManager {
+    /// `JWT_ALGORITHM` picks `HS256` (the default, signed with `JWT_SECRET`),
+    /// `RS256` or `EdDSA` (signed with `JWT_PRIVATE_KEY_FILE`, verified with
+    /// `JWT_PUBLIC_KEY_FILE`). Old keys stay valid through
+    /// `JWT_PREVIOUS_SECRETS` and `JWT_VERIFICATION_KEY_FILES`.
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    /// `from_env` with the variables looked up through `var`.
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        // JWT_SECRET also signs emailed links, so it is needed with every algorithm
+        let secret = var("JWT_SECRET").unwrap_or_default();
+        check_secret("JWT_SECRET", &secret)?;
+
+        let algorithm = match var("JWT_ALGORITHM").unwrap_or_else(|| "HS256".to_string()).as_str() {
+            "HS256" => Algorithm::HS256,
+            "RS256" => Algorithm::RS256,
+            "EdDSA" => Algorithm::EdDSA,
+            other => return Err(format!("Unsupported JWT_ALGORITHM '{}', expected HS256, RS256 or EdDSA", other)),
+        };
+
+        let mut verification_keys = Vec::new();
+        let (derived_kid, signing_key) = match algorithm {
+            Algorithm::HS256 => {
+                let key = secret_key(&secret);
+                let kid = key.kid.clone();
+                verification_keys.push(key);
+                (kid, EncodingKey::from_secret(secret.as_bytes()))
+            }
+            _ => {
+                let private_path = required_var(&var, "JWT_PRIVATE_KEY_FILE")?;
+                let private_pem = read_key_file(&private_path)?;
+                let signing_key = match algorithm {
+                    Algorithm::RS256 => EncodingKey::from_rsa_pem(&private_pem),
+                    _ => EncodingKey::from_ed_pem(&private_pem),
+                }
+                .map_err(|e| format!("Invalid private key in {}: {}", private_path, e))?;
+
+                let public_key = public_key_from_file(&required_var(&var, "JWT_PUBLIC_KEY_FILE")?)?;
+                if public_key.algorithm != algorithm {
+                    return Err(format!("JWT_PUBLIC_KEY_FILE does not hold a {:?} key", algorithm));
+                }
+                let kid = public_key.kid.clone();
+                verification_keys.push(public_key);
+                (kid, signing_key)
+            }
+        };
+
+        // An explicit key id replaces the derived one for the active key only
+        let kid = var("JWT_KEY_ID").filter(|k| !k.is_empty()).unwrap_or(derived_kid);
+        let active = &mut verification_keys[0];
+        active.kid = kid.clone();
+        if let Some(jwk) = active.jwk.as_mut() {
+            jwk.common.key_id = Some(kid.clone());
+        }
+
+        for previous in list_var(&var, "JWT_PREVIOUS_SECRETS") {
+            check_secret("JWT_PREVIOUS_SECRETS", &previous)?;
+            verification_keys.push(secret_key(&previous));
+        }
+        for path in list_var(&var, "JWT_VERIFICATION_KEY_FILES") {
+            verification_keys.push(public_key_from_file(&path)?);
+        }
+
+        let manager = KeyManager {
+            kid,
+            algorithm,
+            signing_key,
+            verification_keys,
+            link_secret: secret.into_bytes(),
+        };
+
+        // Catch a private key that doesn't belong to the public key before serving
+        let probe = manager.sign(&serde_json::json!({ "exp": usize::MAX }))?;
+        manager
+            .verify::<serde_json::Value>(&probe)
+            .map_err(|_| "JWT_PRIVATE_KEY_FILE does not match JWT_PUBLIC_KEY_FILE".to_string())?;
+
+        Ok(manager)
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn verification_key_count(&self) -> usize {
+        self.verification_keys.len()
+    }
+
+    /// Secret for HMAC-signed links that never leave this server.
+    pub fn link_secret(&self) -> &[u8] {
+        &self.link_secret
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, String> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = Some(self.kid.clone());
+        encode(&header, claims, &self.signing_key).map_err(|e| format!("Failed to create token: {}", e))
+    }
+
+    /// Checks the signature with the key named by the token's `kid`. Tokens
+    /// without a known `kid` (such as ones issued before key ids existed) are
+    /// tried against every key of the same algorithm.
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, String> {
+        let header = decode_header(token).map_err(|e| e.to_string())?;
+
+        let by_kid: Vec<&VerificationKey> = self
+            .verification_keys
+            .iter()
+            .filter(|k| header.kid.as_deref() == Some(k.kid.as_str()))
+            .collect();
+        let candidates = if by_kid.is_empty() {
+            self.verification_keys.iter().filter(|k| k.algorithm == header.alg).collect()
+        } else {
+            by_kid
+        };
+
+        for key in candidates {
+            if let Ok(data) = decode::<T>(token, &key.key, &Validation::new(key.algorithm)) {
+                return Ok(data.claims);
+            }
+        }
+
+        Err("Invalid token".to_string())
+    }
+
+    /// The public verification keys, for other services checking our tokens.
+    /// Shared secrets are never published.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.verification_keys.iter().filter_map(|k| k.jwk.clone()).collect(),
+        }
+    }
+}
+
+fn check_secret(name: &str, secret: &str) -> Result<(), String> {
+    if secret.is_empty() {
+        return Err(format!("{} must be set", name));
+    }
+    if secret == DEFAULT_SECRET {
+        return Err(format!("{} is still the default value; generate a random secret", name));
+    }
+    if secret.len() < MIN_SECRET_LENGTH {
+        return Err(format!("{} must be at least {} characters", name, MIN_SECRET_LENGTH));
+    }
+    Ok(())
+}
+
+fn required_var(var: impl Fn(&str) -> Option<String>, name: &str) -> Result<String, String> {
+    var(name).ok_or_else(|| format!("{} must be set for JWT_ALGORITHM=RS256 or EdDSA", name))
+}
+
+fn list_var(var: impl Fn(&str) -> Option<String>, name: &str) -> Vec<String> {
+    var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+fn read_key_file(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("Failed to read key file {}: {}", path, e))
+}
+
+fn secret_key(secret: &str) -> VerificationKey {
+    // A short digest names the secret without revealing it
+    let digest = Sha256::digest(format!("kid:{}", secret).as_bytes());
+    VerificationKey {
+        kid: format!("hs-{}", URL_SAFE_NO_PAD.encode(&digest[..9])),
+        algorithm: Algorithm::HS256,
+        key: DecodingKey::from_secret(secret.as_bytes()),
+        jwk: None,
+    }
+}
+
+/// Loads an RSA (PKCS#1 or SPKI) or Ed25519 (SPKI) public key in PEM form.
+/// Its `kid` is the RFC 7638 thumbprint.
+fn public_key_from_file(path: &str) -> Result<VerificationKey, String> {
+    let bytes = read_key_file(path)?;
+    let parsed = pem::parse(&bytes).map_err(|e| format!("Invalid PEM in {}: {}", path, e))?;
+    let der = parsed.contents();
+
+    if parsed.tag() == "PUBLIC KEY" && der.len() == ED25519_SPKI_PREFIX.len() + 32 && der.starts_with(&ED25519_SPKI_PREFIX) {
+        let x = URL_SAFE_NO_PAD.encode(&der[ED25519_SPKI_PREFIX.len()..]);
+        let kid = thumbprint(&format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, x));
+        let key = DecodingKey::from_ed_pem(&bytes).map_err(|e| format!("Invalid Ed25519 key in {}: {}", path, e))?;
+        let params = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x,
+        });
+        return Ok(public_key(kid, Algorithm::EdDSA, KeyAlgorithm::EdDSA, key, params));
+    }
+
+    let rsa_key = match parsed.tag() {
+        "RSA PUBLIC KEY" => RsaPublicKey::from_pkcs1_der(der).map_err(|e| e.to_string()),
+        "PUBLIC KEY" => RsaPublicKey::from_public_key_der(der).map_err(|e| e.to_string()),
+        other => Err(format!("expected a public key, found '{}'", other)),
+    }
+    .map_err(|e| format!("Unsupported key in {}: {}", path, e))?;
+
+    let n = URL_SAFE_NO_PAD.encode(rsa_key.n().to_bytes_be());
+    let e = URL_SAFE_NO_PAD.encode(rsa_key.e().to_bytes_be());
+    let kid = thumbprint(&format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, e, n));
+    let key = DecodingKey::from_rsa_pem(&bytes).map_err(|e| format!("Invalid RSA key in {}: {}", path, e))?;
+    let params = AlgorithmParameters::RSA(RSAKeyParameters {
+        key_type: RSAKeyType::RSA,
+        n,
+        e,
+    });
+    Ok(public_key(kid, Algorithm::RS256, KeyAlgorithm::RS256, key, params))
+}
+
+fn public_key(
+    kid: String,
+    algorithm: Algorithm,
+    key_algorithm: KeyAlgorithm,
+    key: DecodingKey,
+    params: AlgorithmParameters,
+) -> VerificationKey {
+    let jwk = Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm),
+            key_id: Some(kid.clone()),
+            ..Default::default()
+        },
+        algorithm: params,
+    };
+    VerificationKey {
+        kid,
+        algorithm,
+        key,
+        jwk: Some(jwk),
+    }
+}
+
+/// RFC 7638 JWK thumbprint of the canonical (sorted, whitespace-free) JSON.
+fn thumbprint(canonical_jwk: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(canonical_jwk.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const SECRET: &str = "current-secret-0123456789abcdef0123456789";
+    const PREVIOUS_SECRET: &str = "previous-secret-0123456789abcdef012345678";
+
+    #[derive(Debug, PartialEq, serde::Deserialize, Serialize)]
+    struct Claims {
+        sub: String,
+        exp: usize,
+    }
+
+    fn claims() -> Claims {
+        Claims {
+            sub: "ann".to_string(),
+            exp: (chrono::Utc::now() + chrono::Duration::minutes(5)).timestamp() as usize,
+        }
+    }
+
+    fn key_file(name: &str) -> String {
+        format!("{}/tests/keys/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn manager(vars: &[(&str, String)]) -> KeyManager {
+        let vars: HashMap<&str, String> = vars.iter().cloned().collect();
+        KeyManager::from_vars(|name| vars.get(name).cloned()).unwrap()
+    }
+
+    fn hs256(secret: &str) -> KeyManager {
+        manager(&[("JWT_SECRET", secret.to_string())])
+    }
+
+    fn rs256() -> KeyManager {
+        manager(&[
+            ("JWT_SECRET", SECRET.to_string()),
+            ("JWT_ALGORITHM", "RS256".to_string()),
+            ("JWT_PRIVATE_KEY_FILE", key_file("rsa_private.pem")),
+            ("JWT_PUBLIC_KEY_FILE", key_file("rsa_public.pem")),
+        ])
+    }
+
+    fn eddsa(extra: &[(&str, String)]) -> KeyManager {
+        let mut vars = vec![
+            ("JWT_SECRET", SECRET.to_string()),
+            ("JWT_ALGORITHM", "EdDSA".to_string()),
+            ("JWT_PRIVATE_KEY_FILE", key_file("ed25519_private.pem")),
+            ("JWT_PUBLIC_KEY_FILE", key_file("ed25519_public.pem")),
+        ];
+        vars.extend_from_slice(extra);
+        manager(&vars)
+    }
+
+    #[test]
+    fn signs_and_verifies_with_each_algorithm() {
+        for (keys, algorithm) in [
+            (hs256(SECRET), Algorithm::HS256),
+            (rs256(), Algorithm::RS256),
+            (eddsa(&[]), Algorithm::EdDSA),
+        ] {
+            let token = keys.sign(&claims()).unwrap();
+            let header = decode_header(&token).unwrap();
+            assert_eq!(header.alg, algorithm);
+            assert_eq!(header.kid.as_deref(), Some(keys.kid.as_str()));
+            assert_eq!(keys.verify::<Claims>(&token).unwrap(), claims());
+        }
+        // Nor does one key accept another's tokens
+        assert!(hs256(SECRET).verify::<Claims>(&rs256().sign(&claims()).unwrap()).is_err());
+        assert!(rs256().verify::<Claims>(&eddsa(&[]).sign(&claims()).unwrap()).is_err());
+    }
+
+    #[test]
+    fn accepts_tokens_signed_by_a_previous_key() {
+        let old_token = hs256(PREVIOUS_SECRET).sign(&claims()).unwrap();
+        let rotated = manager(&[
+            ("JWT_SECRET", SECRET.to_string()),
+            ("JWT_PREVIOUS_SECRETS", PREVIOUS_SECRET.to_string()),
+        ]);
+        assert_eq!(rotated.verify::<Claims>(&old_token).unwrap(), claims());
+        assert!(hs256(SECRET).verify::<Claims>(&old_token).is_err());
+
+        // Moving from RS256 to EdDSA keeps the RSA key for verification
+        let rsa_token = rs256().sign(&claims()).unwrap();
+        let rotated = eddsa(&[("JWT_VERIFICATION_KEY_FILES", key_file("rsa_public.pem"))]);
+        assert_eq!(rotated.verification_key_count(), 2);
+        assert_eq!(rotated.verify::<Claims>(&rsa_token).unwrap(), claims());
+        assert!(eddsa(&[]).verify::<Claims>(&rsa_token).is_err());
+    }
+
+    #[test]
+    fn publishes_only_public_keys() {
+        assert!(hs256(SECRET).jwks().keys.is_empty());
+
+        let keys = eddsa(&[
+            ("JWT_VERIFICATION_KEY_FILES", key_file("rsa_public.pem")),
+            ("JWT_PREVIOUS_SECRETS", PREVIOUS_SECRET.to_string()),
+        ]);
+        let jwks = serde_json::to_value(keys.jwks()).unwrap();
+        let published = jwks["keys"].as_array().unwrap();
+        assert_eq!(published.len(), 2, "{}", jwks);
+        assert_eq!(published[0]["kty"], "OKP");
+        assert_eq!(published[0]["crv"], "Ed25519");
+        assert_eq!(published[0]["kid"], keys.kid.as_str());
+        assert_eq!(published[1]["kty"], "RSA");
+        assert_eq!(published[1]["kid"], rs256().kid.as_str());
+        for key in published {
+            assert_eq!(key["use"], "sig");
+            assert!(key.get("d").is_none() && key.get("k").is_none(), "{}", key);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use axum::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport, Message,
+    Tokio1Executor,
+};
+use std::env;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Outgoing mail. Handlers only see this trait so deployments can pick SMTP
+/// while local setups write messages to a file or stdout.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), String>;
+}
+
+/// Builds the mailer selected by `MAILER` (`smtp`, `file` or `stdout`, the default).
+pub fn mailer_from_env() -> Result<Arc<dyn Mailer>, String> {
+    let from = env::var("MAIL_FROM").unwrap_or_else(|_| "Personal Assistant <no-reply@localhost>".to_string());
+
+    match env::var("MAILER").unwrap_or_else(|_| "stdout".to_string()).as_str() {
+        "smtp" => Ok(Arc::new(SmtpMailer::from_env(&from)?)),
+        "file" => {
+            let path = env::var("MAIL_FILE").unwrap_or_else(|_| "mail.log".to_string());
+            Ok(Arc::new(FileMailer { path: Some(path), from }))
+        }
+        "stdout" => Ok(Arc::new(FileMailer { path: None, from })),
+        other => Err(format!("Unknown MAILER '{}', expected smtp, file or stdout", other)),
+    }
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    fn from_env(from: &str) -> Result<Self, String> {
+        let host = env::var("SMTP_HOST").map_err(|_| "SMTP_HOST is required when MAILER=smtp".to_string())?;
+        let port = env::var("SMTP_PORT")
+            .ok()
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(587);
+
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+            .map_err(|e| format!("Invalid SMTP host: {}", e))?
+            .port(port);
+
+        if let (Ok(username), Ok(password)) = (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from: from.parse().map_err(|e| format!("Invalid MAIL_FROM: {}", e))?,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<(), String> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(email.to.parse().map_err(|e| format!("Invalid recipient: {}", e))?)
+            .subject(email.subject)
+            .body(email.body)
+            .map_err(|e| format!("Failed to build email: {}", e))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| format!("Failed to send email: {}", e))?;
+
+        Ok(())
+    }
+}
+
+/// Writes messages to a file (or stdout when `path` is `None`) instead of
+/// delivering them, for local development.
+pub struct FileMailer {
+    path: Option<String>,
+    from: String,
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<(), String> {
+        let rendered = format!(
+            "From: {}\nTo: {}\nDate: {}\nSubject: {}\n\n{}\n\n---\n",
+            self.from,
+            email.to,
+            chrono::Utc::now().to_rfc2822(),
+            email.subject,
+            email.body
+        );
+
+        match &self.path {
+            Some(path) => {
+                let mut file = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await
+                    .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+                file.write_all(rendered.as_bytes())
+                    .await
+                    .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+            }
+            None => println!("📧 {}", rendered),
+        }
+
+        Ok(())
+    }
+}
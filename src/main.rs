@@ -1,8 +1,22 @@
 mod api;
 mod auth;
+mod dates;
+mod fractional_index;
 mod db;
 mod groq;
+mod keys;
+mod mailer;
+mod markdown;
 mod models;
+mod oidc;
+mod quick_add;
+mod recurrence;
+mod reminders;
+mod search_index;
+#[cfg(test)]
+mod test_support;
+mod throttle;
+mod totp;
 
 use std::net::SocketAddr;
 use tower_http::cors::CorsLayer;
@@ -12,6 +26,9 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use crate::api::{create_router, AppState};
 use crate::db::init_db;
 use crate::groq::GroqClient;
+use crate::mailer::mailer_from_env;
+use crate::oidc::OidcClient;
+use crate::reminders::ReminderScheduler;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -27,6 +44,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("🚀 Starting SaaS Personal Assistant Backend...");
 
+    // Refuse to start without usable signing keys
+    let keys = keys::init().map_err(|e| {
+        eprintln!("❌ Signing key error: {}", e);
+        e
+    })?;
+    println!(
+        "✅ Signing keys loaded ({:?}, {} verification key(s)).",
+        keys.algorithm(),
+        keys.verification_key_count()
+    );
+
     // Initialize Database
     let pool = init_db().await.map_err(|e| {
         eprintln!("❌ Database error: {}", e);
@@ -34,6 +62,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     })?;
     println!("✅ Database connected & initialized.");
 
+    auth::promote_bootstrap_admins(&pool).await?;
+    search_index::backfill(&pool).await?;
+    api::seed_workflows(&pool).await?;
+    api::backfill_positions(&pool).await?;
+
     // Initialize Groq AI
     let groq_client = match GroqClient::new() {
         Ok(client) => {
@@ -46,9 +79,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
+    // Initialize single sign-on
+    let oidc_client = match OidcClient::new() {
+        Ok(client) => {
+            println!("✅ Single sign-on enabled ({}).", client.provider_name);
+            Some(client)
+        }
+        Err(e) => {
+            println!("ℹ️  Single sign-on disabled - {}", e);
+            None
+        }
+    };
+
+    let mailer = mailer_from_env().map_err(|e| {
+        eprintln!("❌ Mailer error: {}", e);
+        e
+    })?;
+
+    throttle::spawn_cleanup(pool.clone());
+    auth::spawn_account_purge(pool.clone());
+    api::spawn_trash_purge(pool.clone());
+    ReminderScheduler::new(pool.clone(), mailer.clone()).spawn();
+
     let state = AppState {
         db: pool,
         groq: groq_client,
+        oidc: oidc_client,
+        mailer,
     };
 
     // Build router
@@ -61,7 +118,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("📡 Server listening on http://{}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
 
     Ok(())
 }
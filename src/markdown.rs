@@ -0,0 +1,28 @@
+//! Markdown for task descriptions and comments. Text is stored as written and
+//! rendered on request; the HTML is sanitized because it is user input.
+
+use pulldown_cmark::{html, Event, Options, Parser};
+
+/// CommonMark plus tables, strikethrough and task lists, as sanitized HTML.
+pub fn render(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    // Task list items get a ballot box rather than an <input> the sanitizer would strip
+    let events = Parser::new_ext(markdown, options).map(|event| match event {
+        Event::TaskListMarker(true) => Event::Text("☑ ".into()),
+        Event::TaskListMarker(false) => Event::Text("☐ ".into()),
+        event => event,
+    });
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, events);
+    ammonia::clean(&unsafe_html)
+}
+
+/// The text on one line and cut to `max_chars`, for prompts and previews.
+pub fn excerpt(markdown: &str, max_chars: usize) -> String {
+    let text = markdown.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= max_chars {
+        return text;
+    }
+    let cut: String = text.chars().take(max_chars).collect();
+    format!("{}…", cut.trim_end())
+}
@@ -1,13 +1,24 @@
 use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
 use sqlx::FromRow;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct User {
     pub id: String,
     pub email: String,
+    /// `None` for accounts that only sign in through OpenID Connect.
+    #[serde(skip_serializing)]
+    pub password_hash: Option<String>,
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
     #[serde(skip_serializing)]
-    pub password_hash: String,
+    pub totp_last_step: Option<i64>,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub role: String,
+    pub disabled_at: Option<DateTime<Utc>>,
+    pub deletion_scheduled_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -16,7 +27,247 @@ pub struct Task {
     pub id: String,
     pub user_id: String,
     pub title: String,
+    pub notes: Option<String>,
+    /// Markdown.
+    pub description: Option<String>,
+    /// Whether the task's workflow state is done or cancelled.
     pub completed: bool,
+    pub state_id: Option<String>,
+    /// Name of the workflow state, filled by queries that select `TASK_COLUMNS`.
+    #[sqlx(default)]
+    pub state: Option<String>,
+    pub priority: Priority,
+    pub start_at: Option<DateTime<Utc>>,
+    pub due_at: Option<DateTime<Utc>>,
+    /// IANA zone the dates were entered in, e.g. `Europe/Berlin`.
+    pub time_zone: Option<String>,
+    pub project_id: Option<String>,
+    pub parent_id: Option<String>,
+    /// Links the occurrences of a recurring task.
+    pub series_id: Option<String>,
+    /// Sort key for manual ordering; compare as plain strings.
+    pub position: Option<String>,
+    /// Set while the task is in the trash.
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    /// Tag names, filled by queries that select `TASK_COLUMNS`.
+    #[sqlx(default)]
+    pub tags: Json<Vec<String>>,
+    /// Direct subtasks, filled by queries that select `TASK_COLUMNS`.
+    #[sqlx(default)]
+    pub subtask_count: i64,
+    #[sqlx(default)]
+    pub completed_subtask_count: i64,
+    /// Share of direct subtasks completed, from 0 to 1; `None` without subtasks.
+    #[sqlx(default)]
+    pub progress: Option<f64>,
+    /// RRULE of the task's series while it keeps recurring.
+    #[sqlx(default)]
+    pub recurrence: Option<String>,
+    /// Waiting on an open task that blocks it.
+    #[sqlx(default)]
+    pub blocked: bool,
+    /// Time logged on the task, a running timer included.
+    #[sqlx(default)]
+    pub tracked_seconds: i64,
+}
+
+#[derive(Debug, FromRow)]
+pub struct TaskSeries {
+    pub id: String,
+    pub rrule: String,
+    pub dtstart: DateTime<Utc>,
+    pub time_zone: String,
+    pub exdates: Json<Vec<DateTime<Utc>>>,
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecurrenceInfo {
+    pub series_id: String,
+    pub rule: String,
+    pub time_zone: String,
+    pub starts_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub skipped: Vec<DateTime<Utc>>,
+    /// Occurrences after this task's, not yet created as tasks.
+    pub upcoming: Vec<DateTime<Utc>>,
+}
+
+/// A task with its subtasks, nested to any depth.
+#[derive(Debug, Serialize)]
+pub struct TaskNode {
+    #[serde(flatten)]
+    pub task: Task,
+    pub subtasks: Vec<TaskNode>,
+}
+
+/// Stored as an integer so tasks sort by urgency.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[repr(i64)]
+pub enum Priority {
+    Low = 1,
+    #[default]
+    Medium = 2,
+    High = 3,
+    Urgent = 4,
+}
+
+impl Priority {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "low" => Some(Priority::Low),
+            "medium" => Some(Priority::Medium),
+            "high" => Some(Priority::High),
+            "urgent" => Some(Priority::Urgent),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct TaskComment {
+    pub id: String,
+    pub task_id: String,
+    pub author_id: String,
+    pub author_email: String,
+    /// Markdown, as written.
+    pub body: String,
+    /// `body` rendered and sanitized.
+    #[sqlx(skip)]
+    pub html: String,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+    pub task_count: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What reaching a workflow state means for a task; everything but `open`
+/// counts as completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum StateCategory {
+    Open,
+    Done,
+    Cancelled,
+}
+
+impl StateCategory {
+    pub fn is_closed(self) -> bool {
+        self != StateCategory::Open
+    }
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct WorkflowState {
+    pub id: String,
+    pub name: String,
+    pub category: StateCategory,
+    pub position: i64,
+    pub task_count: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct WorkflowTransition {
+    #[serde(rename = "from")]
+    pub from_state_id: String,
+    #[serde(rename = "to")]
+    pub to_state_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Workflow {
+    pub states: Vec<WorkflowState>,
+    pub transitions: Vec<WorkflowTransition>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BoardColumn {
+    pub state: WorkflowState,
+    pub tasks: Vec<Task>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Project {
+    pub id: String,
+    pub name: String,
+    /// `#rrggbb`.
+    pub color: String,
+    pub position: i64,
+    pub archived_at: Option<DateTime<Utc>>,
+    pub task_count: i64,
+    pub completed_count: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectStats {
+    pub project_id: String,
+    pub total: i64,
+    pub completed: i64,
+    pub open: i64,
+    /// Open tasks whose due date has passed.
+    pub overdue: i64,
+    /// Share of tasks completed, from 0 to 1; 0 for an empty project.
+    pub completion_rate: f64,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Reminder {
+    pub id: String,
+    /// `None` for a free-standing reminder.
+    pub task_id: Option<String>,
+    /// Defaults to the task's title when delivered.
+    pub message: Option<String>,
+    pub remind_at: DateTime<Utc>,
+    pub channel: ReminderChannel,
+    pub webhook_url: Option<String>,
+    pub status: ReminderStatus,
+    /// Failed deliveries so far; retried with backoff until the limit.
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum ReminderChannel {
+    #[default]
+    InApp,
+    Webhook,
+    Email,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum ReminderStatus {
+    Pending,
+    Sent,
+    /// The task was completed before the reminder came due.
+    Skipped,
+    /// Delivery kept failing.
+    Failed,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Notification {
+    pub id: String,
+    pub reminder_id: Option<String>,
+    pub title: String,
+    pub body: String,
+    pub read_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -26,6 +277,66 @@ pub struct Expense {
     pub user_id: String,
     pub category: String,
     pub amount: f64,
+    pub notes: Option<String>,
+    /// Set while the expense is in the trash.
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Time spent on a task; `ended_at` is `None` while its timer runs.
+#[derive(Debug, Serialize, FromRow)]
+pub struct TimeEntry {
+    pub id: String,
+    pub task_id: String,
+    pub task_title: String,
+    pub project_id: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    /// Up to now for a running timer.
+    pub duration_seconds: i64,
+    pub note: Option<String>,
+    pub billable: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimeReportRow {
+    /// Task or project id, or the day as `YYYY-MM-DD`; `None` for time outside any project.
+    pub key: Option<String>,
+    pub label: String,
+    pub seconds: i64,
+    pub billable_seconds: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimeReport {
+    pub group: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub total_seconds: i64,
+    pub billable_seconds: i64,
+    pub rows: Vec<TimeReportRow>,
+}
+
+/// An undoable change and the rows it touched. Deleting a task takes its
+/// subtasks along, so task changes carry every id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum OperationKind {
+    CreateTask { task_id: String },
+    DeleteTasks { task_ids: Vec<String> },
+    RestoreTasks { task_ids: Vec<String> },
+    CreateExpense { expense_id: String },
+    DeleteExpense { expense_id: String },
+    RestoreExpense { expense_id: String },
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Operation {
+    pub id: String,
+    #[serde(flatten)]
+    pub payload: Json<OperationKind>,
+    pub undone_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -38,6 +349,27 @@ pub struct ChatMessage {
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub scopes: Json<Vec<String>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct SecurityEvent {
+    pub id: String,
+    pub user_id: String,
+    pub kind: String,
+    pub ip: Option<String>,
+    pub detail: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RegisterRequest {
     pub email: String,
@@ -53,24 +385,608 @@ pub struct LoginRequest {
 #[derive(Debug, Serialize)]
 pub struct AuthResponse {
     pub token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
     pub user: User,
 }
 
+/// Returned by login when the account has two-factor authentication enabled.
+#[derive(Debug, Serialize)]
+pub struct TwoFactorChallenge {
+    pub two_factor_required: bool,
+    pub challenge_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Authenticated(Box<AuthResponse>),
+    TwoFactorRequired(TwoFactorChallenge),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorVerifyRequest {
+    pub challenge_token: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TwoFactorSetupResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorCodeRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorSetupRequest {
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EnableTwoFactorRequest {
+    pub password: String,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DisableTwoFactorRequest {
+    pub password: String,
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangeEmailRequest {
+    pub new_email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccountRequest {
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteAccountResponse {
+    /// When the account will be permanently deleted; `None` if it already was.
+    pub deletion_scheduled_at: Option<DateTime<Utc>>,
+}
+
+/// A login session as shown to its owner.
+#[derive(Debug, Serialize, FromRow)]
+pub struct SessionInfo {
+    pub id: String,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    /// Whether this is the session making the request.
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SsoConfig {
+    pub enabled: bool,
+    pub provider_name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SsoStartResponse {
+    pub authorization_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SsoCallbackRequest {
+    pub code: String,
+    pub state: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedApiToken {
+    /// The plaintext token; it is only ever shown once.
+    pub token: String,
+    pub api_token: ApiToken,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateTaskRequest {
     pub title: String,
+    pub notes: Option<String>,
+    /// Markdown.
+    pub description: Option<String>,
+    /// RFC 3339, or a local `YYYY-MM-DDTHH:MM` read in `time_zone`.
+    pub start_at: Option<String>,
+    pub due_at: Option<String>,
+    pub time_zone: Option<String>,
+    pub priority: Option<Priority>,
+    pub tags: Option<Vec<String>>,
+    /// Defaults to the parent's project for subtasks.
+    pub project_id: Option<String>,
+    /// Makes the new task a subtask.
+    pub parent_id: Option<String>,
+    /// RRULE, e.g. `FREQ=WEEKLY;BYDAY=MO,WE`; needs a due or start date.
+    pub recurrence: Option<String>,
+    /// Defaults to the first open workflow state.
+    pub state_id: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Omitted fields are left alone; `null` clears the notes, the description, a
+/// date or the time zone.
+#[derive(Debug, Default, Deserialize)]
 pub struct UpdateTaskRequest {
+    /// Moves the task to the first done (`true`) or open (`false`) state.
     pub completed: Option<bool>,
+    /// Moves the task to a workflow state the current one allows.
+    pub state_id: Option<String>,
     pub title: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub notes: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub description: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub start_at: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub due_at: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub time_zone: Option<Option<String>>,
+    pub priority: Option<Priority>,
+    /// Replaces every tag on the task.
+    pub tags: Option<Vec<String>>,
+    /// `null` takes the task out of its project.
+    #[serde(default, deserialize_with = "nullable")]
+    pub project_id: Option<Option<String>>,
+    /// `null` makes the task a top-level task again.
+    #[serde(default, deserialize_with = "nullable")]
+    pub parent_id: Option<Option<String>>,
+    /// With `completed: true`, also completes every subtask below the task.
+    #[serde(default)]
+    pub complete_subtasks: bool,
+}
+
+/// Tells an explicit `null` (`Some(None)`) apart from a missing field (`None`).
+fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TaskListQuery {
+    /// `overdue`, `today`, `week` or `none`.
+    pub due: Option<String>,
+    /// IANA zone that decides where today and this week begin; defaults to UTC.
+    pub tz: Option<String>,
+    /// Comma separated; tasks must carry every tag listed.
+    pub tag: Option<String>,
+    /// Comma separated; tasks may have any of the priorities listed.
+    pub priority: Option<String>,
+    pub completed: Option<bool>,
+    /// Case-insensitive match on the title.
+    pub q: Option<String>,
+    /// A project id, or `none` for tasks outside any project.
+    pub project: Option<String>,
+    /// A task id for its direct subtasks, or `none` for top-level tasks.
+    pub parent: Option<String>,
+    pub blocked: Option<bool>,
+    /// A workflow state id.
+    pub state: Option<String>,
+    pub sort: Option<String>,
+    /// `asc` or `desc`.
+    pub order: Option<String>,
+    pub limit: Option<i64>,
+    /// Opaque value from the previous page's `X-Next-Cursor` header.
+    pub cursor: Option<String>,
+}
+
+/// The task's new neighbors in the list it was dropped into; either may be
+/// left out at the ends of the list.
+#[derive(Debug, Deserialize)]
+pub struct MoveTaskRequest {
+    /// The task that should come right before it.
+    pub after_id: Option<String>,
+    /// The task that should come right after it.
+    pub before_id: Option<String>,
+    /// Also moves the task to this workflow state, as dropping it into another board column.
+    pub state_id: Option<String>,
+}
+
+/// One change applied to every task in `POST /api/tasks/batch`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BatchAction {
+    /// Moves the tasks to the first done (`true`) or open (`false`) state.
+    Complete {
+        #[serde(default = "default_completed")]
+        completed: bool,
+        #[serde(default)]
+        complete_subtasks: bool,
+    },
+    /// Moves the tasks and their subtasks to the trash, as one change to undo.
+    Delete,
+    Retag {
+        #[serde(default)]
+        add: Vec<String>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+    /// `project_id: null` takes the tasks out of their project.
+    Move {
+        #[serde(default, deserialize_with = "nullable")]
+        project_id: Option<Option<String>>,
+        state_id: Option<String>,
+    },
+    /// New dates, read in each task's zone, or `shift_days` moving both of
+    /// a task's dates by that many days.
+    Reschedule {
+        #[serde(default, deserialize_with = "nullable")]
+        start_at: Option<Option<String>>,
+        #[serde(default, deserialize_with = "nullable")]
+        due_at: Option<Option<String>>,
+        shift_days: Option<i64>,
+    },
+}
+
+fn default_completed() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchTaskRequest {
+    pub ids: Vec<String>,
+    #[serde(flatten)]
+    pub action: BatchAction,
+}
+
+/// The outcome for one task of a batch: its updated version, or the status
+/// and error it would have had on its own. Deleted tasks carry no task.
+#[derive(Debug, Serialize)]
+pub struct BatchItemResult {
+    pub id: String,
+    pub status: u16,
+    pub error: Option<String>,
+    pub task: Option<Task>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchTaskResponse {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<BatchItemResult>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DependencyRequest {
+    /// The task that has to be done first.
+    pub blocked_by: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaskDependencies {
+    pub blocked_by: Vec<Task>,
+    pub blocking: Vec<Task>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NextTasksQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecurrenceRequest {
+    pub rule: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecurrenceQuery {
+    pub limit: Option<u16>,
+}
+
+/// Without `at`, skips the task's own occurrence and moves it to the next.
+#[derive(Debug, Default, Deserialize)]
+pub struct SkipOccurrenceRequest {
+    pub at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TaskTreeQuery {
+    pub project: Option<String>,
+    pub completed: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommentRequest {
+    /// Markdown.
+    pub body: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MarkdownPreviewRequest {
+    pub markdown: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MarkdownPreview {
+    /// Sanitized HTML.
+    pub html: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ParseTaskRequest {
+    /// One line such as `call dentist tomorrow 3pm #health !high every month`.
+    pub text: String,
+    /// Zone dates in the text are read in; UTC by default.
+    pub time_zone: Option<String>,
+    /// Lets the AI read text the parser can't, when AI features are enabled.
+    #[serde(default = "default_ai")]
+    pub ai: bool,
+}
+
+fn default_ai() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParseSource {
+    Parser,
+    Ai,
+}
+
+/// A task read from a line of text, shaped as a `POST /api/tasks` body so
+/// the client can confirm it as is or edit it first.
+#[derive(Debug, Serialize)]
+pub struct TaskPreview {
+    pub title: String,
+    pub due_at: Option<DateTime<Utc>>,
+    pub time_zone: Option<String>,
+    pub recurrence: Option<String>,
+    pub tags: Vec<String>,
+    pub priority: Option<Priority>,
+    pub source: ParseSource,
+    /// Words left in the title that look like dates the parser couldn't read.
+    pub unparsed: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateProjectRequest {
+    pub name: String,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateProjectRequest {
+    pub name: Option<String>,
+    pub color: Option<String>,
+    pub archived: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProjectListQuery {
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+/// Every project id of the user, in the order they should be listed.
+#[derive(Debug, Deserialize)]
+pub struct ReorderProjectsRequest {
+    pub ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateStateRequest {
+    pub name: String,
+    pub category: StateCategory,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateStateRequest {
+    pub name: Option<String>,
+    pub category: Option<StateCategory>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteStateQuery {
+    /// State that takes over the deleted state's tasks; required while it has any.
+    pub move_to: Option<String>,
+}
+
+/// Every state id of the user, in the order the board shows them.
+#[derive(Debug, Deserialize)]
+pub struct ReorderStatesRequest {
+    pub ids: Vec<String>,
+}
+
+/// Replaces every allowed move between the user's states.
+#[derive(Debug, Deserialize)]
+pub struct TransitionsRequest {
+    pub transitions: Vec<WorkflowTransition>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BoardQuery {
+    /// A project id, or `none` for tasks outside any project.
+    pub project: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateReminderRequest {
+    /// Attaches the reminder to a task; without one, `message` is required.
+    pub task_id: Option<String>,
+    pub message: Option<String>,
+    /// RFC 3339, or a local `YYYY-MM-DDTHH:MM` read in `time_zone`.
+    pub remind_at: String,
+    /// Defaults to the task's zone, then UTC.
+    pub time_zone: Option<String>,
+    #[serde(default)]
+    pub channel: ReminderChannel,
+    /// Required for the webhook channel.
+    pub webhook_url: Option<String>,
+}
+
+/// Omitted fields are left alone. Changing anything puts a delivered or
+/// failed reminder back in line.
+#[derive(Debug, Deserialize)]
+pub struct UpdateReminderRequest {
+    #[serde(default, deserialize_with = "nullable")]
+    pub message: Option<Option<String>>,
+    pub remind_at: Option<String>,
+    pub time_zone: Option<String>,
+    pub channel: Option<ReminderChannel>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub webhook_url: Option<Option<String>>,
+}
+
+/// A manual entry for time already spent.
+#[derive(Debug, Deserialize)]
+pub struct CreateTimeEntryRequest {
+    pub task_id: String,
+    /// RFC 3339, or a local `YYYY-MM-DDTHH:MM` read in `time_zone`.
+    pub started_at: String,
+    pub ended_at: String,
+    /// Defaults to the task's zone, then UTC.
+    pub time_zone: Option<String>,
+    pub note: Option<String>,
+    #[serde(default = "default_billable")]
+    pub billable: bool,
+}
+
+fn default_billable() -> bool {
+    true
+}
+
+/// Omitted fields are left alone; `null` clears the note. A running timer's
+/// end can't be set here; stop it instead.
+#[derive(Debug, Deserialize)]
+pub struct UpdateTimeEntryRequest {
+    pub started_at: Option<String>,
+    pub ended_at: Option<String>,
+    pub time_zone: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub note: Option<Option<String>>,
+    pub billable: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartTimerRequest {
+    pub note: Option<String>,
+    #[serde(default = "default_billable")]
+    pub billable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimeEntryListQuery {
+    pub task_id: Option<String>,
+    /// RFC 3339; entries started at or after it.
+    pub from: Option<DateTime<Utc>>,
+    /// RFC 3339; entries started before it.
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimeReportQuery {
+    /// `task`, `project` or `day`; defaults to `task`.
+    pub group: Option<String>,
+    /// First and last day, `YYYY-MM-DD`; default to the current week.
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    /// IANA zone that decides where days begin; defaults to UTC.
+    pub tz: Option<String>,
+    /// A project id, or `none` for time on tasks outside any project.
+    pub project: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReminderListQuery {
+    pub status: Option<ReminderStatus>,
+    pub task_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationListQuery {
+    #[serde(default)]
+    pub unread: bool,
+    pub limit: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CreateExpenseRequest {
     pub category: String,
     pub amount: f64,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    /// Comma separated `task`, `expense` and `chat`; all of them by default.
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// `title` and `snippet` are HTML-escaped, with matches wrapped in `<mark>`.
+#[derive(Debug, Serialize, FromRow)]
+pub struct SearchResult {
+    pub kind: String,
+    pub id: String,
+    pub title: String,
+    pub snippet: String,
+    /// Higher is a better match.
+    pub score: f64,
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -78,6 +994,48 @@ pub struct ChatRequest {
     pub message: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct AdminUserQuery {
+    pub q: Option<String>,
+    pub role: Option<String>,
+    pub disabled: Option<bool>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminUserList {
+    pub users: Vec<User>,
+    pub total: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminUpdateUserRequest {
+    pub role: Option<String>,
+    pub disabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminResetPasswordRequest {
+    /// Set this password directly; when omitted a reset link is emailed instead.
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InstanceStats {
+    pub users: i64,
+    pub admins: i64,
+    pub disabled_users: i64,
+    pub verified_users: i64,
+    pub signups_last_7_days: i64,
+    pub active_sessions: i64,
+    pub tasks: i64,
+    pub completed_tasks: i64,
+    pub expenses: i64,
+    pub total_spending: f64,
+    pub chat_messages: i64,
+}
+
 #[derive(Debug, Serialize)]
 pub struct BudgetSummary {
     pub total_spending: f64,
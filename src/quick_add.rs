@@ -0,0 +1,493 @@
+//! Quick add: reads a task from one line of text such as
+//! `call dentist tomorrow 3pm #health !high every month`. The words it
+//! understands become the due date, recurrence, tags and priority, and the
+//! rest is the title. Purely rule based, so it works without the AI features.
+
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+
+use crate::dates;
+use crate::models::Priority;
+
+/// Time given to dates without one, so a task due today isn't overdue
+/// before the day is over.
+const END_OF_DAY: (u32, u32) = (23, 59);
+/// Time "tonight" stands for.
+const EVENING: (u32, u32) = (20, 0);
+
+const MONTHS: [&str; 12] = [
+    "january", "february", "march", "april", "may", "june", "july", "august", "september", "october", "november",
+    "december",
+];
+
+/// Words that only ever mean a date or time, flagged when left in a title.
+const SCHEDULING_WORDS: &[&str] = &[
+    "today", "tonight", "tomorrow", "yesterday", "every", "noon", "midday", "midnight", "weekend", "fortnight",
+    "daily", "weekly", "monthly", "yearly", "annually",
+];
+
+#[derive(Debug, Default)]
+pub struct ParsedTask {
+    pub title: String,
+    pub due_at: Option<DateTime<Utc>>,
+    /// An RRULE without the `RRULE:` prefix.
+    pub recurrence: Option<String>,
+    pub tags: Vec<String>,
+    pub priority: Option<Priority>,
+    /// Words left in the title that look like dates the parser couldn't read.
+    pub unparsed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Unit {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+struct Rule {
+    freq: &'static str,
+    interval: u32,
+    by_day: Vec<Weekday>,
+}
+
+impl Rule {
+    fn to_rrule(&self) -> String {
+        let mut rule = format!("FREQ={}", self.freq);
+        if self.interval > 1 {
+            rule.push_str(&format!(";INTERVAL={}", self.interval));
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<&str> = self.by_day.iter().map(|day| weekday_code(*day)).collect();
+            rule.push_str(&format!(";BYDAY={}", days.join(",")));
+        }
+        rule
+    }
+}
+
+pub fn parse(input: &str, now: DateTime<Utc>, tz: Tz) -> ParsedTask {
+    let today = now.with_timezone(&tz).date_naive();
+    let tokens: Vec<&str> = input.split_whitespace().collect();
+    let words: Vec<String> = tokens
+        .iter()
+        .map(|token| token.trim_end_matches([',', '.', ';']).to_lowercase())
+        .collect();
+
+    let mut parsed = ParsedTask::default();
+    let (mut date, mut time, mut rule) = (None, None, None);
+    let mut title = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let rest = &words[i..];
+        let token = tokens[i].trim_end_matches([',', '.', ';']);
+        if let Some(tag) = token.strip_prefix('#').filter(|tag| !tag.is_empty()) {
+            parsed.tags.push(tag.to_string());
+            i += 1;
+            continue;
+        }
+        if parsed.priority.is_none() {
+            if let Some(priority) = rest[0].strip_prefix('!').and_then(Priority::parse) {
+                parsed.priority = Some(priority);
+                i += 1;
+                continue;
+            }
+        }
+        if rule.is_none() {
+            if let Some((read, used)) = read_rule(rest) {
+                rule = Some(read);
+                i += used;
+                continue;
+            }
+        }
+        // "on friday", "by 5pm", "due tomorrow", "at noon"
+        let skip = usize::from(rest.len() > 1 && matches!(rest[0].as_str(), "on" | "by" | "at" | "due"));
+        if date.is_none() {
+            if let Some((read, used)) = read_date(&rest[skip..], today, skip > 0) {
+                if rest[skip] == "tonight" && time.is_none() {
+                    time = NaiveTime::from_hms_opt(EVENING.0, EVENING.1, 0);
+                }
+                date = Some(read);
+                i += skip + used;
+                continue;
+            }
+        }
+        if time.is_none() {
+            if let Some((read, used)) = read_time(&rest[skip..]) {
+                time = Some(read);
+                i += skip + used;
+                continue;
+            }
+        }
+        title.push(tokens[i]);
+        if looks_like_date(&words[i]) {
+            parsed.unparsed.push(tokens[i].to_string());
+        }
+        i += 1;
+    }
+
+    // A time or rule without a date starts on the first day that fits and isn't over yet
+    let end_of_day = NaiveTime::from_hms_opt(END_OF_DAY.0, END_OF_DAY.1, 0).expect("end of day is valid");
+    if date.is_none() && (time.is_some() || rule.is_some()) {
+        let by_day = rule.as_ref().map(|rule: &Rule| rule.by_day.clone()).unwrap_or_default();
+        date = (0..=7).map(|offset| today + Duration::days(offset)).find(|day| {
+            (by_day.is_empty() || by_day.contains(&day.weekday())) && at(tz, *day, time.unwrap_or(end_of_day)) > now
+        });
+    }
+
+    parsed.title = title.join(" ");
+    parsed.due_at = date.map(|date| at(tz, date, time.unwrap_or(end_of_day)));
+    parsed.recurrence = rule.map(|rule| rule.to_rrule());
+    parsed
+}
+
+/// A local date and time as an instant, moving times skipped by a DST
+/// change an hour later.
+fn at(tz: Tz, date: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+    let local = date.and_time(time);
+    dates::local_to_utc(tz, local)
+        .or_else(|| dates::local_to_utc(tz, local + Duration::hours(1)))
+        .unwrap_or_else(|| dates::start_of_day(tz, date))
+}
+
+/// "daily", "every other week", "every 3 months", "every mon, wed",
+/// "every weekday". Returns the rule and the number of words it took.
+fn read_rule(words: &[String]) -> Option<(Rule, usize)> {
+    let simple = |freq| Some((Rule { freq, interval: 1, by_day: Vec::new() }, 1));
+    match words.first()?.as_str() {
+        "daily" => return simple("DAILY"),
+        "weekly" => return simple("WEEKLY"),
+        "monthly" => return simple("MONTHLY"),
+        "yearly" | "annually" => return simple("YEARLY"),
+        "every" => {}
+        _ => return None,
+    }
+
+    let rest = &words[1..];
+    let weekly = |by_day: Vec<Weekday>, used| Some((Rule { freq: "WEEKLY", interval: 1, by_day }, used));
+    match rest.first()?.as_str() {
+        "weekday" | "weekdays" => {
+            return weekly(vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri], 2);
+        }
+        "weekend" | "weekends" => return weekly(vec![Weekday::Sat, Weekday::Sun], 2),
+        _ => {}
+    }
+
+    let mut by_day = Vec::new();
+    let mut used = 0;
+    for (index, word) in rest.iter().enumerate() {
+        if word == "and" && !by_day.is_empty() {
+            continue;
+        }
+        let days: Option<Vec<Weekday>> = word.split(',').filter(|part| !part.is_empty()).map(weekday).collect();
+        match days {
+            Some(days) if !days.is_empty() => {
+                by_day.extend(days);
+                used = index + 1;
+            }
+            _ => break,
+        }
+    }
+    if !by_day.is_empty() {
+        return weekly(by_day, 1 + used);
+    }
+
+    let (interval, unit_at) = match rest[0].as_str() {
+        "other" => (2, 1),
+        word => match number(word) {
+            Some(n) if n > 0 => (n, 1),
+            _ => (1, 0),
+        },
+    };
+    let freq = match unit(rest.get(unit_at)?)? {
+        Unit::Day => "DAILY",
+        Unit::Week => "WEEKLY",
+        Unit::Month => "MONTHLY",
+        Unit::Year => "YEARLY",
+    };
+    Some((Rule { freq, interval, by_day: Vec::new() }, 2 + unit_at))
+}
+
+/// "today", "tomorrow", "friday", "next week", "in 3 days", "the 1st",
+/// "2026-03-14", "14 march", "march 14th 2027". Abbreviated weekdays only count after a
+/// word like "on" or "next", so "buy sun cream" keeps its title.
+fn read_date(words: &[String], today: NaiveDate, after_connector: bool) -> Option<(NaiveDate, usize)> {
+    let first = words.first()?.as_str();
+    let second = words.get(1).map(String::as_str);
+    match first {
+        "today" | "tonight" => return Some((today, 1)),
+        "tomorrow" | "tmrw" => return Some((today + Duration::days(1), 1)),
+        "next" => {
+            let date = match second? {
+                "week" => next_weekday(today, Weekday::Mon),
+                "month" => today.with_day(1)?.checked_add_months(Months::new(1))?,
+                "year" => NaiveDate::from_ymd_opt(today.year() + 1, 1, 1)?,
+                word => next_weekday(today, weekday(word)?),
+            };
+            return Some((date, 2));
+        }
+        "this" => {
+            let day = weekday(second?)?;
+            return Some((next_weekday(today - Duration::days(1), day), 2));
+        }
+        // "on the 1st": the next time the month gets to that day
+        "the" => {
+            let day = second.filter(|word| !word.bytes().all(|b| b.is_ascii_digit())).and_then(day_of_month)?;
+            let month = today.with_day(1)?;
+            return (0..=12)
+                .filter_map(|offset| month.checked_add_months(Months::new(offset))?.with_day(day))
+                .find(|date| *date >= today)
+                .map(|date| (date, 2));
+        }
+        "in" => {
+            let n = number(second?)?;
+            let date = match unit(words.get(2)?)? {
+                Unit::Day => today.checked_add_signed(Duration::days(n.into()))?,
+                Unit::Week => today.checked_add_signed(Duration::weeks(n.into()))?,
+                Unit::Month => today.checked_add_months(Months::new(n))?,
+                Unit::Year => today.checked_add_months(Months::new(n.checked_mul(12)?))?,
+            };
+            return Some((date, 3));
+        }
+        _ => {}
+    }
+
+    let day = if after_connector { weekday(first) } else { full_weekday(first) };
+    if let Some(day) = day {
+        return Some((next_weekday(today, day), 1));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(first, "%Y-%m-%d") {
+        return Some((date, 1));
+    }
+
+    let (day, month) = match (day_of_month(first), second.and_then(month)) {
+        (Some(day), Some(month)) => (day, month),
+        _ => (second.and_then(day_of_month)?, month(first)?),
+    };
+    let year = words.get(2).and_then(|word| word.parse::<i32>().ok()).filter(|year| (2000..=2100).contains(year));
+    if let Some(year) = year {
+        return NaiveDate::from_ymd_opt(year, month, day).map(|date| (date, 3));
+    }
+    // The next time that day comes around
+    let date = NaiveDate::from_ymd_opt(today.year(), month, day)?;
+    if date >= today {
+        return Some((date, 2));
+    }
+    NaiveDate::from_ymd_opt(today.year() + 1, month, day).map(|date| (date, 2))
+}
+
+/// "3pm", "3:30 pm", "15:00", "noon".
+fn read_time(words: &[String]) -> Option<(NaiveTime, usize)> {
+    let first = words.first()?.as_str();
+    if first == "noon" || first == "midday" {
+        return NaiveTime::from_hms_opt(12, 0, 0).map(|time| (time, 1));
+    }
+
+    let (clock, pm, used) = if let Some(clock) = first.strip_suffix("am") {
+        (clock, Some(false), 1)
+    } else if let Some(clock) = first.strip_suffix("pm") {
+        (clock, Some(true), 1)
+    } else {
+        match words.get(1).map(String::as_str) {
+            Some("am") => (first, Some(false), 2),
+            Some("pm") => (first, Some(true), 2),
+            _ => (first, None, 1),
+        }
+    };
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) if digits(hour) && digits(minute) && minute.len() == 2 => {
+            (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?)
+        }
+        // A bare number is only a time with am or pm
+        None if pm.is_some() && digits(clock) => (clock.parse::<u32>().ok()?, 0),
+        _ => return None,
+    };
+    let hour = match pm {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(pm) => hour % 12 + if pm { 12 } else { 0 },
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, 0).map(|time| (time, used))
+}
+
+/// The first `day` after `today`.
+fn next_weekday(today: NaiveDate, day: Weekday) -> NaiveDate {
+    let ahead = (day.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+    today + Duration::days(if ahead == 0 { 7 } else { ahead.into() })
+}
+
+fn full_weekday(word: &str) -> Option<Weekday> {
+    let word = word.strip_suffix('s').unwrap_or(word);
+    match word {
+        "monday" => Some(Weekday::Mon),
+        "tuesday" => Some(Weekday::Tue),
+        "wednesday" => Some(Weekday::Wed),
+        "thursday" => Some(Weekday::Thu),
+        "friday" => Some(Weekday::Fri),
+        "saturday" => Some(Weekday::Sat),
+        "sunday" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+fn weekday(word: &str) -> Option<Weekday> {
+    full_weekday(word).or(match word {
+        "mon" => Some(Weekday::Mon),
+        "tue" | "tues" => Some(Weekday::Tue),
+        "wed" => Some(Weekday::Wed),
+        "thu" | "thur" | "thurs" => Some(Weekday::Thu),
+        "fri" => Some(Weekday::Fri),
+        "sat" => Some(Weekday::Sat),
+        "sun" => Some(Weekday::Sun),
+        _ => None,
+    })
+}
+
+fn weekday_code(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+/// A month's name or the start of it: "mar", "sept", "december".
+fn month(word: &str) -> Option<u32> {
+    if word.len() < 3 {
+        return None;
+    }
+    MONTHS.iter().position(|name| name.starts_with(word)).map(|index| index as u32 + 1)
+}
+
+/// "14", "14th", "1st".
+fn day_of_month(word: &str) -> Option<u32> {
+    let digits = ["st", "nd", "rd", "th"].iter().find_map(|suffix| word.strip_suffix(suffix)).unwrap_or(word);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok().filter(|day| (1..=31).contains(day))
+}
+
+fn number(word: &str) -> Option<u32> {
+    const WORDS: [&str; 10] = ["one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten"];
+    match word {
+        "a" | "an" => Some(1),
+        _ => word
+            .parse()
+            .ok()
+            .or_else(|| WORDS.iter().position(|name| *name == word).map(|index| index as u32 + 1)),
+    }
+}
+
+fn unit(word: &str) -> Option<Unit> {
+    match word.strip_suffix('s').unwrap_or(word) {
+        "day" => Some(Unit::Day),
+        "week" => Some(Unit::Week),
+        "month" => Some(Unit::Month),
+        "year" => Some(Unit::Year),
+        _ => None,
+    }
+}
+
+fn looks_like_date(word: &str) -> bool {
+    SCHEDULING_WORDS.contains(&word)
+        || full_weekday(word).is_some()
+        || (word != "may" && MONTHS.contains(&word))
+        || read_time(&[word.to_string()]).is_some()
+        || NaiveDate::parse_from_str(word, "%Y-%m-%d").is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const TZ: Tz = chrono_tz::Europe::Berlin;
+
+    /// Thursday 5 March 2026, 13:00 in Berlin.
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 3, 5, 12, 0, 0).unwrap()
+    }
+
+    fn berlin(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> Option<DateTime<Utc>> {
+        Some(TZ.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap().with_timezone(&Utc))
+    }
+
+    #[test]
+    fn reads_every_part_of_a_full_line() {
+        let parsed = parse("call dentist tomorrow 3pm #health !high every month", now(), TZ);
+        assert_eq!(parsed.title, "call dentist");
+        assert_eq!(parsed.due_at, berlin(2026, 3, 6, 15, 0));
+        assert_eq!(parsed.recurrence.as_deref(), Some("FREQ=MONTHLY"));
+        assert_eq!(parsed.tags, ["health"]);
+        assert_eq!(parsed.priority, Some(Priority::High));
+        assert!(parsed.unparsed.is_empty());
+    }
+
+    #[test]
+    fn reads_every_other_week() {
+        let parsed = parse("water plants every other week", now(), TZ);
+        assert_eq!(parsed.title, "water plants");
+        assert_eq!(parsed.recurrence.as_deref(), Some("FREQ=WEEKLY;INTERVAL=2"));
+        // Starts today, as the day isn't over yet
+        assert_eq!(parsed.due_at, berlin(2026, 3, 5, 23, 59));
+    }
+
+    #[test]
+    fn reads_a_list_of_weekdays() {
+        let parsed = parse("gym every mon, wed", now(), TZ);
+        assert_eq!(parsed.title, "gym");
+        assert_eq!(parsed.recurrence.as_deref(), Some("FREQ=WEEKLY;BYDAY=MO,WE"));
+        assert_eq!(parsed.due_at, berlin(2026, 3, 9, 23, 59));
+    }
+
+    #[test]
+    fn reads_the_next_day_of_the_month() {
+        let parsed = parse("pay rent the 1st", now(), TZ);
+        assert_eq!(parsed.title, "pay rent");
+        // After the switch to summer time
+        assert_eq!(parsed.due_at, berlin(2026, 4, 1, 23, 59));
+        assert_eq!(parse("read the book", now(), TZ).title, "read the book");
+    }
+
+    #[test]
+    fn reads_a_date_with_its_year() {
+        let parsed = parse("conference march 14th 2027", now(), TZ);
+        assert_eq!(parsed.title, "conference");
+        assert_eq!(parsed.due_at, berlin(2027, 3, 14, 23, 59));
+    }
+
+    #[test]
+    fn moves_a_time_in_the_dst_gap_an_hour_later() {
+        // Berlin skips from 02:00 to 03:00 on 29 March 2026
+        let parsed = parse("change clocks march 29 2:30am", now(), TZ);
+        assert_eq!(parsed.title, "change clocks");
+        assert_eq!(parsed.due_at, berlin(2026, 3, 29, 3, 30));
+    }
+
+    #[test]
+    fn keeps_abbreviated_weekdays_in_the_title() {
+        let parsed = parse("buy sun cream", now(), TZ);
+        assert_eq!(parsed.title, "buy sun cream");
+        assert_eq!(parsed.due_at, None);
+        assert!(parsed.unparsed.is_empty());
+        assert_eq!(parse("buy cream on sun", now(), TZ).due_at, berlin(2026, 3, 8, 23, 59));
+    }
+
+    #[test]
+    fn flags_date_words_it_could_not_use() {
+        let parsed = parse("call mom tomorrow or friday", now(), TZ);
+        assert_eq!(parsed.title, "call mom or friday");
+        assert_eq!(parsed.due_at, berlin(2026, 3, 6, 23, 59));
+        assert_eq!(parsed.unparsed, ["friday"]);
+
+        let parsed = parse("review notes from yesterday", now(), TZ);
+        assert_eq!(parsed.due_at, None);
+        assert_eq!(parsed.unparsed, ["yesterday"]);
+    }
+}
@@ -0,0 +1,115 @@
+//! Recurring tasks. Rules use RFC 5545 RRULE semantics and are evaluated in
+//! the series' time zone, so a 09:00 routine stays at 09:00 across DST changes.
+
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use rrule::{Frequency, RRule, RRuleSet, Unvalidated};
+
+/// Most upcoming occurrences listed at once.
+pub const MAX_UPCOMING: u16 = 50;
+
+/// A rule anchored at its first occurrence, minus the skipped ones.
+pub struct Series<'a> {
+    pub rule: &'a str,
+    pub dtstart: DateTime<Utc>,
+    pub tz: Tz,
+    pub exdates: &'a [DateTime<Utc>],
+}
+
+/// Checks a rule such as `FREQ=MONTHLY;BYDAY=2TU`, with or without the
+/// `RRULE:` prefix, and returns it without the prefix.
+pub fn normalize_rule(rule: &str, dtstart: DateTime<Utc>, tz: Tz) -> Result<String, String> {
+    let rule = rule.trim();
+    let rule = rule.strip_prefix("RRULE:").unwrap_or(rule);
+    let parsed: RRule<Unvalidated> = rule.parse().map_err(|e| format!("Invalid recurrence rule: {}", e))?;
+
+    // A task per hour (or faster) would bury everything else
+    if matches!(parsed.get_freq(), Frequency::Hourly | Frequency::Minutely | Frequency::Secondly) {
+        return Err("Tasks can recur at most daily".to_string());
+    }
+
+    parsed
+        .validate(dtstart.with_timezone(&rrule::Tz::from(tz)))
+        .map_err(|e| format!("Invalid recurrence rule: {}", e))?;
+    Ok(rule.to_string())
+}
+
+impl Series<'_> {
+    /// Occurrences strictly after `after`, at most `limit` of them.
+    pub fn upcoming(&self, after: DateTime<Utc>, limit: u16) -> Result<Vec<DateTime<Utc>>, String> {
+        let tz = rrule::Tz::from(self.tz);
+        let rule: RRule<Unvalidated> = self.rule.parse().map_err(|e| format!("Invalid stored rule: {}", e))?;
+        let mut set: RRuleSet = rule
+            .build(self.dtstart.with_timezone(&tz))
+            .map_err(|e| format!("Invalid stored rule: {}", e))?;
+        for exdate in self.exdates {
+            set = set.exdate(exdate.with_timezone(&tz));
+        }
+
+        // `after` is inclusive and occurrences fall on whole seconds
+        let dates = set.after((after + Duration::seconds(1)).with_timezone(&tz)).all(limit).dates;
+        Ok(dates.into_iter().map(|date| date.with_timezone(&Utc)).collect())
+    }
+
+    pub fn next_after(&self, after: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, String> {
+        Ok(self.upcoming(after, 1)?.into_iter().next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(rfc3339: &str) -> DateTime<Utc> {
+        rfc3339.parse().unwrap()
+    }
+
+    #[test]
+    fn lists_the_next_occurrences() {
+        let series = Series {
+            rule: "FREQ=WEEKLY;BYDAY=MO,WE",
+            dtstart: utc("2026-01-05T09:00:00Z"),
+            tz: Tz::UTC,
+            exdates: &[],
+        };
+        // Strictly after the given instant
+        let upcoming = series.upcoming(utc("2026-01-05T09:00:00Z"), 3).unwrap();
+        assert_eq!(upcoming, [utc("2026-01-07T09:00:00Z"), utc("2026-01-12T09:00:00Z"), utc("2026-01-14T09:00:00Z")]);
+        assert_eq!(series.next_after(utc("2026-01-13T00:00:00Z")).unwrap(), Some(utc("2026-01-14T09:00:00Z")));
+    }
+
+    #[test]
+    fn leaves_out_skipped_occurrences_and_stops_at_the_count() {
+        let exdates = [utc("2026-01-02T09:00:00Z")];
+        let series = Series {
+            rule: "FREQ=DAILY;COUNT=3",
+            dtstart: utc("2026-01-01T09:00:00Z"),
+            tz: Tz::UTC,
+            exdates: &exdates,
+        };
+        assert_eq!(series.upcoming(utc("2026-01-01T09:00:00Z"), 10).unwrap(), [utc("2026-01-03T09:00:00Z")]);
+        assert_eq!(series.next_after(utc("2026-01-03T09:00:00Z")).unwrap(), None);
+    }
+
+    #[test]
+    fn keeps_the_local_time_across_a_dst_change() {
+        // Berlin moves from UTC+1 to UTC+2 on 29 March 2026
+        let series = Series {
+            rule: "FREQ=DAILY",
+            dtstart: utc("2026-03-27T08:00:00Z"),
+            tz: chrono_tz::Europe::Berlin,
+            exdates: &[],
+        };
+        let upcoming = series.upcoming(utc("2026-03-27T08:00:00Z"), 3).unwrap();
+        assert_eq!(upcoming, [utc("2026-03-28T08:00:00Z"), utc("2026-03-29T07:00:00Z"), utc("2026-03-30T07:00:00Z")]);
+    }
+
+    #[test]
+    fn checks_rules() {
+        let dtstart = utc("2026-01-01T09:00:00Z");
+        let rule = normalize_rule(" RRULE:FREQ=MONTHLY;BYDAY=2TU ", dtstart, Tz::UTC).unwrap();
+        assert_eq!(rule, "FREQ=MONTHLY;BYDAY=2TU");
+        assert!(normalize_rule("FREQ=HOURLY", dtstart, Tz::UTC).is_err());
+        assert!(normalize_rule("FREQ=SOMETIMES", dtstart, Tz::UTC).is_err());
+    }
+}
@@ -0,0 +1,426 @@
+//! Reminder delivery. Reminders live in the database and a background job
+//! sends out the ones that have come due, so nothing is lost on restart:
+//! reminders that came due while the server was down go out on its first run.
+//! Delivery is at least once; a crash mid-delivery sends that reminder again.
+
+use axum::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use reqwest::{redirect, Client, Url};
+use sqlx::{FromRow, SqlitePool};
+use std::collections::HashMap;
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::dates;
+use crate::mailer::{Email, Mailer};
+use crate::models::ReminderChannel;
+
+/// Reminders loaded per query while catching up.
+const BATCH_SIZE: i64 = 100;
+/// Deliveries tried before a reminder is marked failed.
+const MAX_ATTEMPTS: i64 = 5;
+/// Reminders sent later than this say when they were meant for.
+const LATE_AFTER_MINUTES: i64 = 5;
+const WEBHOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How often due reminders are looked for (`REMINDER_POLL_SECONDS`, default 30).
+fn poll_interval() -> std::time::Duration {
+    let seconds = env::var("REMINDER_POLL_SECONDS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|&s: &u64| s > 0)
+        .unwrap_or(30);
+    std::time::Duration::from_secs(seconds)
+}
+
+/// Wait before retrying after the `attempts`th failure: 1, 2, 4, 8... minutes.
+fn retry_delay(attempts: i64) -> Duration {
+    Duration::minutes(1 << (attempts - 1).clamp(0, 10))
+}
+
+/// Hosts webhooks may call even though they resolve to a loopback, private
+/// or link-local address, from the comma-separated `WEBHOOK_ALLOWED_HOSTS`.
+fn allowed_webhook_hosts() -> Vec<String> {
+    env::var("WEBHOOK_ALLOWED_HOSTS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|host| host.trim().to_ascii_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect()
+}
+
+/// Whether `ip` is on the public internet, as opposed to this machine, the
+/// local network or a reserved range.
+fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_public_address(IpAddr::V4(v4)),
+            None => {
+                !(ip.is_unspecified()
+                    || ip.is_loopback()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+                    || ip.is_multicast())
+            }
+        },
+    }
+}
+
+/// Webhook targets must be plain http(s) URLs on the public internet, so a
+/// reminder can't be used to reach services only the server can see. The
+/// host is resolved and every address it has must be public, unless it is
+/// listed in `WEBHOOK_ALLOWED_HOSTS`.
+pub async fn check_webhook_url(url: &str) -> Result<(), String> {
+    resolve_webhook_url(url).await.map(|_| ())
+}
+
+/// Checks `url` as `check_webhook_url` does and returns the addresses that
+/// passed, or none for an allowed host.
+async fn resolve_webhook_url(url: &str) -> Result<(Url, Vec<SocketAddr>), String> {
+    let invalid = || format!("Invalid webhook URL '{}', expected http(s)://...", url);
+    let parsed = Url::parse(url).map_err(|_| invalid())?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(invalid());
+    }
+    let host = parsed.host_str().ok_or_else(invalid)?;
+    if allowed_webhook_hosts().iter().any(|allowed| allowed.eq_ignore_ascii_case(host)) {
+        return Ok((parsed, Vec::new()));
+    }
+
+    let port = parsed.port_or_known_default().ok_or_else(invalid)?;
+    // IPv6 literals keep their brackets in the URL
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.trim_start_matches('[').trim_end_matches(']'), port))
+        .await
+        .map_err(|e| format!("Cannot resolve webhook host '{}': {}", host, e))?
+        .collect();
+    if addresses.is_empty() || !addresses.iter().all(|address| is_public_address(address.ip())) {
+        return Err(format!("Webhook host '{}' is not a public address", host));
+    }
+    Ok((parsed, addresses))
+}
+
+/// A client that connects to `url`'s host only at `addresses`, so the name
+/// can't be pointed somewhere else between the check and the request.
+fn pinned_client(url: &Url, addresses: &[SocketAddr]) -> Result<Client, String> {
+    let mut builder = Client::builder().timeout(WEBHOOK_TIMEOUT).redirect(redirect::Policy::none());
+    if let (Some(domain), false) = (url.domain(), addresses.is_empty()) {
+        builder = builder.resolve_to_addrs(domain, addresses);
+    }
+    builder.build().map_err(|e| format!("Cannot build webhook client: {}", e))
+}
+
+/// A reminder that has come due, with what its delivery needs to know.
+#[derive(Debug, FromRow)]
+pub struct DueReminder {
+    pub id: String,
+    pub user_id: String,
+    pub email: String,
+    pub task_id: Option<String>,
+    pub task_title: Option<String>,
+    pub task_completed: Option<bool>,
+    pub task_due_at: Option<DateTime<Utc>>,
+    pub task_time_zone: Option<String>,
+    pub message: Option<String>,
+    pub remind_at: DateTime<Utc>,
+    pub channel: ReminderChannel,
+    pub webhook_url: Option<String>,
+    pub attempts: i64,
+}
+
+impl DueReminder {
+    fn tz(&self) -> Tz {
+        self.task_time_zone
+            .as_deref()
+            .and_then(|name| dates::parse_time_zone(name).ok())
+            .unwrap_or(Tz::UTC)
+    }
+
+    fn is_late(&self, now: DateTime<Utc>) -> bool {
+        now - self.remind_at > Duration::minutes(LATE_AFTER_MINUTES)
+    }
+
+    pub fn title(&self) -> String {
+        let subject = self.message.as_deref().or(self.task_title.as_deref()).unwrap_or("Reminder");
+        format!("Reminder: {}", subject)
+    }
+
+    pub fn body(&self, now: DateTime<Utc>) -> String {
+        let tz = self.tz();
+        let mut lines = Vec::new();
+        if let Some(message) = &self.message {
+            lines.push(message.clone());
+        }
+        if let Some(title) = &self.task_title {
+            lines.push(format!("Task: {}", title));
+        }
+        if let Some(due_at) = self.task_due_at {
+            lines.push(format!("Due {}", dates::describe(due_at, tz)));
+        }
+        if self.is_late(now) {
+            lines.push(format!("This reminder was set for {}.", dates::describe(self.remind_at, tz)));
+        }
+        lines.join("\n")
+    }
+}
+
+/// A way of getting a reminder to its owner. Each `ReminderChannel` maps to
+/// one of these in the scheduler.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn deliver(&self, reminder: &DueReminder) -> Result<(), String>;
+}
+
+/// Stores the reminder as a notification for the web app to show.
+pub struct InAppNotifier {
+    pool: SqlitePool,
+}
+
+#[async_trait]
+impl Notifier for InAppNotifier {
+    async fn deliver(&self, reminder: &DueReminder) -> Result<(), String> {
+        sqlx::query("INSERT INTO notifications (id, user_id, reminder_id, title, body) VALUES (?, ?, ?, ?, ?)")
+            .bind(Uuid::new_v4().to_string())
+            .bind(&reminder.user_id)
+            .bind(&reminder.id)
+            .bind(reminder.title())
+            .bind(reminder.body(Utc::now()))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| format!("Failed to store notification: {}", e))?;
+        Ok(())
+    }
+}
+
+/// POSTs the reminder as JSON to the reminder's `webhook_url`. Any non-2xx
+/// response counts as a failure and is retried. The URL is checked again on
+/// each delivery and the request goes to the addresses that were checked.
+/// Redirects aren't followed, since their target wouldn't have been checked.
+pub struct WebhookNotifier;
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn deliver(&self, reminder: &DueReminder) -> Result<(), String> {
+        let url = reminder.webhook_url.as_deref().ok_or("Reminder has no webhook URL")?;
+        let (url, addresses) = resolve_webhook_url(url).await?;
+        let client = pinned_client(&url, &addresses)?;
+        let now = Utc::now();
+        let payload = serde_json::json!({
+            "id": reminder.id,
+            "task_id": reminder.task_id,
+            "title": reminder.title(),
+            "body": reminder.body(now),
+            "remind_at": reminder.remind_at,
+            "late": reminder.is_late(now),
+        });
+
+        let response = client
+            .post(url)
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| format!("Webhook request failed: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Webhook responded with {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Mails the reminder to the account's address through the configured
+/// mailer, which writes to a file or stdout in local setups.
+pub struct EmailNotifier {
+    mailer: Arc<dyn Mailer>,
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+    async fn deliver(&self, reminder: &DueReminder) -> Result<(), String> {
+        self.mailer
+            .send(Email {
+                to: reminder.email.clone(),
+                subject: reminder.title(),
+                body: reminder.body(Utc::now()),
+            })
+            .await
+    }
+}
+
+pub struct ReminderScheduler {
+    pool: SqlitePool,
+    notifiers: HashMap<ReminderChannel, Arc<dyn Notifier>>,
+}
+
+impl ReminderScheduler {
+    pub fn new(pool: SqlitePool, mailer: Arc<dyn Mailer>) -> Self {
+        let mut notifiers: HashMap<ReminderChannel, Arc<dyn Notifier>> = HashMap::new();
+        notifiers.insert(ReminderChannel::InApp, Arc::new(InAppNotifier { pool: pool.clone() }));
+        notifiers.insert(ReminderChannel::Webhook, Arc::new(WebhookNotifier));
+        notifiers.insert(ReminderChannel::Email, Arc::new(EmailNotifier { mailer }));
+        Self { pool, notifiers }
+    }
+
+    /// Sends due reminders now (catching up on any missed while the server
+    /// was down) and then every poll interval.
+    pub fn spawn(self) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(poll_interval());
+            loop {
+                interval.tick().await;
+                match self.deliver_due().await {
+                    Ok(0) => {}
+                    Ok(count) => tracing::info!("Processed {} due reminder(s)", count),
+                    Err(e) => tracing::error!("Failed to process due reminders: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Works through every reminder due by now. Each one ends up sent,
+    /// skipped, failed or rescheduled for a retry, so the loop terminates.
+    async fn deliver_due(&self) -> Result<usize, sqlx::Error> {
+        let mut processed = 0;
+        loop {
+            let due = sqlx::query_as::<_, DueReminder>(
+                "SELECT reminders.id, reminders.user_id, users.email, reminders.task_id,
+                        tasks.title AS task_title, tasks.completed AS task_completed,
+                        tasks.due_at AS task_due_at, tasks.time_zone AS task_time_zone,
+                        reminders.message, reminders.remind_at, reminders.channel, reminders.webhook_url,
+                        reminders.attempts
+                 FROM reminders
+                 JOIN users ON users.id = reminders.user_id
+                 LEFT JOIN tasks ON tasks.id = reminders.task_id
+                 WHERE reminders.status = 'pending' AND reminders.next_attempt_at <= ?
+                   AND users.disabled_at IS NULL AND tasks.deleted_at IS NULL
+                 ORDER BY reminders.next_attempt_at LIMIT ?",
+            )
+            .bind(Utc::now())
+            .bind(BATCH_SIZE)
+            .fetch_all(&self.pool)
+            .await?;
+
+            let batch = due.len();
+            for reminder in due {
+                self.process(reminder).await?;
+            }
+            processed += batch;
+
+            if (batch as i64) < BATCH_SIZE {
+                return Ok(processed);
+            }
+        }
+    }
+
+    async fn process(&self, reminder: DueReminder) -> Result<(), sqlx::Error> {
+        // Nothing left to remind about
+        if reminder.task_completed == Some(true) {
+            sqlx::query("UPDATE reminders SET status = 'skipped' WHERE id = ?")
+                .bind(&reminder.id)
+                .execute(&self.pool)
+                .await?;
+            return Ok(());
+        }
+
+        let result = match self.notifiers.get(&reminder.channel) {
+            Some(notifier) => notifier.deliver(&reminder).await,
+            None => Err(format!("No notifier for the {:?} channel", reminder.channel)),
+        };
+
+        let now = Utc::now();
+        match result {
+            Ok(()) => {
+                sqlx::query("UPDATE reminders SET status = 'sent', sent_at = ?, last_error = NULL WHERE id = ?")
+                    .bind(now)
+                    .bind(&reminder.id)
+                    .execute(&self.pool)
+                    .await?;
+            }
+            Err(error) => {
+                let attempts = reminder.attempts + 1;
+                tracing::warn!("Reminder {} failed (attempt {}): {}", reminder.id, attempts, error);
+                let status = if attempts >= MAX_ATTEMPTS { "failed" } else { "pending" };
+                sqlx::query(
+                    "UPDATE reminders SET status = ?, attempts = ?, last_error = ?, next_attempt_at = ? WHERE id = ?",
+                )
+                .bind(status)
+                .bind(attempts)
+                .bind(&error)
+                .bind(now + retry_delay(attempts))
+                .bind(&reminder.id)
+                .execute(&self.pool)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_public_from_internal_addresses() {
+        for ip in ["93.184.215.14", "2606:2800:21f:cb07:6820:80da:af6b:8b2c"] {
+            assert!(is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_webhooks_to_internal_hosts() {
+        for url in ["http://127.0.0.1:8080/hook", "http://localhost/hook", "http://[::1]/hook", "ftp://example.com/"] {
+            assert!(check_webhook_url(url).await.is_err(), "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_webhooks_to_the_checked_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 1024];
+            let read = tokio::io::AsyncReadExt::read(&mut socket, &mut request).await.unwrap();
+            tokio::io::AsyncWriteExt::write_all(&mut socket, b"HTTP/1.1 204 No Content\r\n\r\n").await.unwrap();
+            String::from_utf8_lossy(&request[..read]).into_owned()
+        });
+
+        // The name doesn't resolve at all, so only the pinned address can be reached
+        let url = Url::parse(&format!("http://hooks.invalid:{}/hook", address.port())).unwrap();
+        let response = pinned_client(&url, &[address]).unwrap().post(url).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /hook "), "{}", request);
+        assert!(request.to_ascii_lowercase().contains("host: hooks.invalid"), "{}", request);
+    }
+}
@@ -0,0 +1,117 @@
+//! Full-text search over tasks, expenses and chat history, backed by one
+//! FTS5 table. The index is not tied to its sources by triggers: whatever
+//! writes a searchable row calls `sync` for it in the same transaction.
+
+use sqlx::{SqliteConnection, SqlitePool};
+
+/// Marks matches in `highlight()`/`snippet()` output; replaced by `<mark>`
+/// once the text around them has been escaped.
+pub const MATCH_START: &str = "\u{2}";
+pub const MATCH_END: &str = "\u{3}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchKind {
+    Task,
+    Expense,
+    Chat,
+}
+
+impl SearchKind {
+    pub const ALL: [SearchKind; 3] = [SearchKind::Task, SearchKind::Expense, SearchKind::Chat];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "task" => Some(SearchKind::Task),
+            "expense" => Some(SearchKind::Expense),
+            "chat" => Some(SearchKind::Chat),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SearchKind::Task => "task",
+            SearchKind::Expense => "expense",
+            SearchKind::Chat => "chat",
+        }
+    }
+
+    /// The index rows for this kind, in `search_index` column order. Rows in
+    /// the trash are left out.
+    fn source(self) -> &'static str {
+        match self {
+            SearchKind::Task => {
+                "SELECT title, COALESCE(notes || char(10) || description, notes, description, ''),
+                        'task', id, user_id, created_at FROM tasks WHERE deleted_at IS NULL"
+            }
+            SearchKind::Expense => {
+                "SELECT category, COALESCE(notes, ''), 'expense', id, user_id, created_at FROM expenses
+                 WHERE deleted_at IS NULL"
+            }
+            SearchKind::Chat => "SELECT '', content, 'chat', id, user_id, created_at FROM chat_messages WHERE 1",
+        }
+    }
+}
+
+/// Brings the index entry for one row up to date: re-indexes it, or drops
+/// it if the row is gone or in the trash.
+pub async fn sync(conn: &mut SqliteConnection, kind: SearchKind, id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM search_index WHERE kind = ? AND item_id = ?")
+        .bind(kind.as_str())
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(&format!("INSERT INTO search_index {} AND id = ?", kind.source()))
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Fills an empty index from its sources, which is how databases created
+/// before search existed get indexed.
+pub async fn backfill(pool: &SqlitePool) -> Result<(), String> {
+    let empty: bool = sqlx::query_scalar("SELECT NOT EXISTS (SELECT 1 FROM search_index)")
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to inspect search index: {}", e))?;
+    if !empty {
+        return Ok(());
+    }
+
+    for kind in SearchKind::ALL {
+        sqlx::query(&format!("INSERT INTO search_index {}", kind.source()))
+            .execute(pool)
+            .await
+            .map_err(|e| format!("Failed to index {}s: {}", kind.as_str(), e))?;
+    }
+    Ok(())
+}
+
+/// Turns free text into an FTS5 query in which every word has to match,
+/// the last one as a prefix so results show up while typing. Quoting each
+/// word keeps FTS5 operators in the input from being interpreted.
+pub fn match_query(input: &str) -> Option<String> {
+    let mut words: Vec<String> = input
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect();
+    words.last_mut()?.push('*');
+    Some(words.join(" "))
+}
+
+/// Escapes indexed text for HTML and wraps the matches in `<mark>`.
+pub fn render_highlights(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html.replace(MATCH_START, "<mark>").replace(MATCH_END, "</mark>")
+}
@@ -0,0 +1,145 @@
+//! Shared setup for tests: the real router over an in-memory database, and
+//! helpers to call it the way a client would.
+
+use axum::{
+    async_trait,
+    body::{to_bytes, Body},
+    extract::connect_info::MockConnectInfo,
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    Router,
+};
+use serde_json::Value;
+use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, Once};
+use tower::ServiceExt;
+
+use crate::api::{create_router, AppState};
+use crate::mailer::{Email, Mailer};
+use crate::oidc::OidcClient;
+
+pub const PASSWORD: &str = "password123";
+
+/// Keeps every message, so tests can follow emailed links.
+#[derive(Default)]
+pub struct TestMailer {
+    pub sent: Mutex<Vec<Email>>,
+}
+
+#[async_trait]
+impl Mailer for TestMailer {
+    async fn send(&self, email: Email) -> Result<(), String> {
+        self.sent.lock().unwrap().push(email);
+        Ok(())
+    }
+}
+
+/// Loads the signing keys once per test binary, as `main` does at startup.
+pub fn init_keys() {
+    static KEYS: Once = Once::new();
+    KEYS.call_once(|| {
+        std::env::set_var("JWT_SECRET", "test-secret-0123456789abcdef0123456789abcdef");
+        crate::keys::init().unwrap();
+    });
+}
+
+/// A fresh, migrated database. One connection, as each would open its own
+/// in-memory database.
+pub async fn test_pool() -> SqlitePool {
+    let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+    crate::db::migrate(&pool).await.unwrap();
+    pool
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// The body as JSON, or as a JSON string when it isn't JSON.
+    pub body: Value,
+}
+
+pub struct TestApp {
+    pub state: AppState,
+    pub mailer: Arc<TestMailer>,
+    router: Router,
+}
+
+impl TestApp {
+    pub async fn new() -> Self {
+        Self::with_oidc(None).await
+    }
+
+    pub async fn with_oidc(oidc: Option<OidcClient>) -> Self {
+        init_keys();
+        let mailer = Arc::new(TestMailer::default());
+        let state = AppState {
+            db: test_pool().await,
+            groq: None,
+            oidc,
+            mailer: mailer.clone(),
+        };
+        let router = create_router(state.clone()).layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 50000))));
+        Self { state, mailer, router }
+    }
+
+    pub async fn request(&self, method: Method, path: &str, token: Option<&str>, body: Option<Value>) -> TestResponse {
+        let mut request = Request::builder().method(method).uri(path);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+        TestResponse { status, headers, body }
+    }
+
+    pub async fn get(&self, path: &str, token: &str) -> TestResponse {
+        self.request(Method::GET, path, Some(token), None).await
+    }
+
+    pub async fn post(&self, path: &str, token: &str, body: Value) -> TestResponse {
+        self.request(Method::POST, path, Some(token), Some(body)).await
+    }
+
+    pub async fn patch(&self, path: &str, token: &str, body: Value) -> TestResponse {
+        self.request(Method::PATCH, path, Some(token), Some(body)).await
+    }
+
+    pub async fn delete(&self, path: &str, token: &str) -> TestResponse {
+        self.request(Method::DELETE, path, Some(token), None).await
+    }
+
+    /// Logs in with `PASSWORD` and returns the whole login response.
+    pub async fn login(&self, email: &str) -> TestResponse {
+        let body = serde_json::json!({ "email": email, "password": PASSWORD });
+        self.request(Method::POST, "/api/auth/login", None, Some(body)).await
+    }
+
+    /// Registers `email` with `PASSWORD` and returns an access token.
+    pub async fn sign_up(&self, email: &str) -> String {
+        let body = serde_json::json!({ "email": email, "password": PASSWORD });
+        let registered = self.request(Method::POST, "/api/auth/register", None, Some(body)).await;
+        assert_eq!(registered.status, StatusCode::CREATED, "{}", registered.body);
+        let login = self.login(email).await;
+        assert_eq!(login.status, StatusCode::OK, "{}", login.body);
+        login.body["token"].as_str().unwrap().to_string()
+    }
+
+    /// Creates a task and returns its id.
+    pub async fn create_task(&self, token: &str, body: Value) -> String {
+        let created = self.post("/api/tasks", token, body).await;
+        assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+        created.body["id"].as_str().unwrap().to_string()
+    }
+}
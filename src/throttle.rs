@@ -0,0 +1,273 @@
+//! Brute-force protection for logins: exponential backoff and temporary
+//! lockout per account, plus a coarser cap per client IP.
+
+use axum::http::HeaderMap;
+use sqlx::{Sqlite, SqlitePool};
+use std::env;
+use std::net::SocketAddr;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::auth::record_security_event;
+
+/// Attempts older than this are deleted by the cleanup task.
+const ATTEMPT_RETENTION_HOURS: i64 = 24;
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+#[derive(Debug, Clone)]
+pub struct LoginPolicy {
+    /// Failures allowed before backoff kicks in.
+    pub free_attempts: i64,
+    /// Upper bound for the exponential delay between attempts.
+    pub max_backoff_seconds: i64,
+    /// Failures that lock the account.
+    pub lockout_threshold: i64,
+    /// How long a lockout lasts; also the window failures are counted in.
+    pub lockout_minutes: i64,
+    /// Failures from one IP (across all accounts) that block it for the window.
+    pub ip_threshold: i64,
+}
+
+impl LoginPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            free_attempts: env_or("LOGIN_FREE_ATTEMPTS", 3),
+            max_backoff_seconds: env_or("LOGIN_MAX_BACKOFF_SECONDS", 60),
+            lockout_threshold: env_or("LOGIN_LOCKOUT_THRESHOLD", 10),
+            lockout_minutes: env_or("LOGIN_LOCKOUT_MINUTES", 15),
+            ip_threshold: env_or("LOGIN_IP_THRESHOLD", 50),
+        }
+    }
+
+    fn window_start(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc::now() - chrono::Duration::minutes(self.lockout_minutes)
+    }
+
+    /// Seconds an account with `failures` recent failures has to wait after the last one.
+    fn delay_seconds(&self, failures: i64) -> i64 {
+        if failures >= self.lockout_threshold {
+            self.lockout_minutes * 60
+        } else if failures >= self.free_attempts {
+            let exponent = (failures - self.free_attempts).min(30) as u32;
+            2i64.pow(exponent).min(self.max_backoff_seconds)
+        } else {
+            0
+        }
+    }
+}
+
+/// Best-effort client address. `X-Forwarded-For` is only trusted when
+/// `TRUST_PROXY_HEADERS=true`, i.e. when running behind a reverse proxy.
+pub fn client_ip(addr: &SocketAddr, headers: &HeaderMap) -> String {
+    if env::var("TRUST_PROXY_HEADERS").as_deref() == Ok("true") {
+        if let Some(forwarded) = headers
+            .get("x-forwarded-for")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.split(',').next())
+        {
+            return forwarded.trim().to_string();
+        }
+    }
+    addr.ip().to_string()
+}
+
+/// Whole seconds from now until `at`, rounded up so a wait of under a second
+/// still counts; `None` once it has passed.
+fn seconds_until(at: chrono::DateTime<chrono::Utc>) -> Option<i64> {
+    let milliseconds = (at - chrono::Utc::now()).num_milliseconds();
+    (milliseconds > 0).then(|| (milliseconds + 999) / 1000)
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Failures for `email` since its last successful login, within the policy window.
+async fn account_failures(
+    pool: &SqlitePool,
+    policy: &LoginPolicy,
+    email: &str,
+) -> Result<(i64, Option<chrono::DateTime<chrono::Utc>>), String> {
+    sqlx::query_as::<Sqlite, (i64, Option<chrono::DateTime<chrono::Utc>>)>(
+        "SELECT COUNT(*), MAX(created_at) FROM login_attempts
+         WHERE email = ? AND succeeded = FALSE AND created_at > ?
+           AND created_at > COALESCE((SELECT MAX(created_at) FROM login_attempts WHERE email = ? AND succeeded = TRUE), '')",
+    )
+    .bind(email)
+    .bind(policy.window_start())
+    .bind(email)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Returns how many seconds the client must wait before trying to log in
+/// to `email` again, or `None` if an attempt is allowed now.
+pub async fn check(pool: &SqlitePool, email: &str, ip: &str) -> Result<Option<i64>, String> {
+    let policy = LoginPolicy::from_env();
+    let email = normalize_email(email);
+
+    let (ip_failures, ip_last) = sqlx::query_as::<Sqlite, (i64, Option<chrono::DateTime<chrono::Utc>>)>(
+        "SELECT COUNT(*), MAX(created_at) FROM login_attempts WHERE ip = ? AND succeeded = FALSE AND created_at > ?",
+    )
+    .bind(ip)
+    .bind(policy.window_start())
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if let (true, Some(last)) = (ip_failures >= policy.ip_threshold, ip_last) {
+        if let Some(retry) = seconds_until(last + chrono::Duration::minutes(policy.lockout_minutes)) {
+            return Ok(Some(retry));
+        }
+    }
+
+    let (failures, last_failure) = account_failures(pool, &policy, &email).await?;
+    Ok(last_failure.and_then(|last| seconds_until(last + chrono::Duration::seconds(policy.delay_seconds(failures)))))
+}
+
+/// Records a login attempt. The failure that reaches the lockout threshold
+/// also leaves an `account_locked` security event for the account owner.
+pub async fn record_attempt(pool: &SqlitePool, email: &str, ip: &str, succeeded: bool) -> Result<(), String> {
+    let policy = LoginPolicy::from_env();
+    let email = normalize_email(email);
+
+    sqlx::query("INSERT INTO login_attempts (id, email, ip, succeeded, created_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(Uuid::new_v4().to_string())
+        .bind(&email)
+        .bind(ip)
+        .bind(succeeded)
+        .bind(chrono::Utc::now())
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if succeeded {
+        return Ok(());
+    }
+
+    let (failures, _) = account_failures(pool, &policy, &email).await?;
+    if failures == policy.lockout_threshold {
+        let user_id: Option<String> = sqlx::query_scalar("SELECT id FROM users WHERE lower(email) = ?")
+            .bind(&email)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if let Some(user_id) = user_id {
+            tracing::warn!("Locking account {} after {} failed logins", user_id, failures);
+            record_security_event(
+                pool,
+                &user_id,
+                "account_locked",
+                Some(ip),
+                &format!("{} failed login attempts; locked for {} minutes", failures, policy.lockout_minutes),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Periodically deletes login attempts that no longer affect any decision.
+pub fn spawn_cleanup(pool: SqlitePool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let cutoff = chrono::Utc::now() - chrono::Duration::hours(ATTEMPT_RETENTION_HOURS);
+            match sqlx::query("DELETE FROM login_attempts WHERE created_at < ?")
+                .bind(cutoff)
+                .execute(&pool)
+                .await
+            {
+                Ok(result) if result.rows_affected() > 0 => {
+                    tracing::info!("Purged {} old login attempts", result.rows_affected());
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Failed to purge login attempts: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_pool;
+
+    async fn fail(pool: &SqlitePool, email: &str, ip: &str, times: i64) {
+        for _ in 0..times {
+            record_attempt(pool, email, ip, false).await.unwrap();
+        }
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_lockout() {
+        let policy = LoginPolicy {
+            free_attempts: 3,
+            max_backoff_seconds: 60,
+            lockout_threshold: 10,
+            lockout_minutes: 15,
+            ip_threshold: 50,
+        };
+        let delays: Vec<i64> = (0..=10).map(|failures| policy.delay_seconds(failures)).collect();
+        assert_eq!(delays, [0, 0, 0, 1, 2, 4, 8, 16, 32, 60, 900]);
+    }
+
+    #[tokio::test]
+    async fn locks_the_account_after_too_many_failures() {
+        let pool = test_pool().await;
+        let policy = LoginPolicy::from_env();
+        sqlx::query("INSERT INTO users (id, email, password_hash) VALUES ('ann', 'ann@example.com', '')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        fail(&pool, "Ann@Example.com", "10.0.0.1", policy.free_attempts - 1).await;
+        assert_eq!(check(&pool, "ann@example.com", "10.0.0.1").await.unwrap(), None);
+
+        fail(&pool, "ann@example.com", "10.0.0.1", policy.lockout_threshold - policy.free_attempts + 1).await;
+        // Locked for the whole window, from any address
+        let retry = check(&pool, "ann@example.com", "10.0.0.2").await.unwrap().unwrap();
+        assert!(retry > policy.max_backoff_seconds && retry <= policy.lockout_minutes * 60, "{}", retry);
+        let locked: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM security_events WHERE kind = 'account_locked'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(locked, 1);
+    }
+
+    #[tokio::test]
+    async fn a_successful_login_resets_the_count() {
+        let pool = test_pool().await;
+        let policy = LoginPolicy::from_env();
+
+        fail(&pool, "ann@example.com", "10.0.0.1", policy.free_attempts).await;
+        assert!(check(&pool, "ann@example.com", "10.0.0.1").await.unwrap().is_some());
+
+        record_attempt(&pool, "ann@example.com", "10.0.0.1", true).await.unwrap();
+        assert_eq!(check(&pool, "ann@example.com", "10.0.0.1").await.unwrap(), None);
+        // Backoff starts over rather than continuing from the earlier failures
+        fail(&pool, "ann@example.com", "10.0.0.1", policy.free_attempts - 1).await;
+        assert_eq!(check(&pool, "ann@example.com", "10.0.0.1").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn blocks_an_address_failing_across_accounts() {
+        let pool = test_pool().await;
+        let policy = LoginPolicy::from_env();
+
+        // Few enough failures per account that none of them backs off
+        for i in 0..policy.ip_threshold {
+            fail(&pool, &format!("user{}@example.com", i % 25), "10.0.0.1", 1).await;
+        }
+        assert!(check(&pool, "someone@example.com", "10.0.0.1").await.unwrap().is_some());
+        assert_eq!(check(&pool, "someone@example.com", "10.0.0.2").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,152 @@
+//! RFC 6238 time-based one-time passwords (HMAC-SHA1, 6 digits, 30 second steps),
+//! the variant every common authenticator app understands.
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use reqwest::Url;
+use sha1::Sha1;
+
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+/// Accept codes from one step before or after the current one to absorb clock drift.
+const ALLOWED_DRIFT: i64 = 1;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Generates a new 160-bit shared secret, base32 encoded.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base32_encode(&bytes)
+}
+
+/// Builds the `otpauth://` URI that authenticator apps scan as a QR code.
+pub fn otpauth_uri(secret: &str, account: &str, issuer: &str) -> String {
+    let mut url = Url::parse("otpauth://totp/").expect("valid base URI");
+    url.path_segments_mut()
+        .expect("otpauth URI has a path")
+        .pop_if_empty()
+        .push(&format!("{}:{}", issuer, account));
+    url.query_pairs_mut()
+        .append_pair("secret", secret)
+        .append_pair("issuer", issuer)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &STEP_SECONDS.to_string());
+    url.to_string()
+}
+
+/// Checks `code` against `secret` at `unix_time`.
+///
+/// Returns the matching time step so callers can reject a code that was
+/// already used (`last_step`) — a TOTP code must not be replayable.
+pub fn verify(secret: &str, code: &str, unix_time: i64, last_step: Option<i64>) -> Option<i64> {
+    let code = code.trim().replace(' ', "");
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let key = base32_decode(secret)?;
+    let current = unix_time / STEP_SECONDS;
+
+    (current - ALLOWED_DRIFT..=current + ALLOWED_DRIFT)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| hotp(&key, *step as u64) == code)
+}
+
+/// The code an authenticator app shows for `secret` at `unix_time`.
+#[cfg(test)]
+pub fn code_at(secret: &str, unix_time: i64) -> String {
+    hotp(&base32_decode(secret).expect("valid secret"), (unix_time / STEP_SECONDS) as u64)
+}
+
+/// RFC 4226 HOTP value for `counter`, zero-padded to [`DIGITS`].
+fn hotp(key: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+
+    format!("{:0width$}", binary % 10u32.pow(DIGITS), width = DIGITS as usize)
+}
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+fn base32_decode(input: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in input.trim_end_matches('=').chars().filter(|c| !c.is_whitespace()) {
+        let value = BASE32_ALPHABET.iter().position(|&a| a == c.to_ascii_uppercase() as u8)? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA-1 seed from RFC 6238 Appendix B.
+    fn rfc_secret() -> String {
+        base32_encode(b"12345678901234567890")
+    }
+
+    #[test]
+    fn matches_rfc_6238_test_vectors() {
+        // Appendix B lists 8-digit values; a 6-digit code is their last six digits
+        let vectors = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ];
+        for (time, code) in vectors {
+            assert_eq!(verify(&rfc_secret(), code, time, None), Some(time / STEP_SECONDS), "at {}", time);
+        }
+    }
+
+    #[test]
+    fn accepts_one_step_of_drift() {
+        assert_eq!(verify(&rfc_secret(), "287082", 59 + STEP_SECONDS, None), Some(1));
+        assert_eq!(verify(&rfc_secret(), "287082", 59 - STEP_SECONDS, None), Some(1));
+        assert_eq!(verify(&rfc_secret(), "287082", 59 + 2 * STEP_SECONDS, None), None);
+    }
+
+    #[test]
+    fn rejects_a_used_step() {
+        assert_eq!(verify(&rfc_secret(), "287082", 59, Some(1)), None);
+        assert_eq!(verify(&rfc_secret(), "287082", 59, Some(0)), Some(1));
+    }
+
+    #[test]
+    fn rejects_malformed_codes() {
+        assert_eq!(verify(&rfc_secret(), "94287082", 59, None), None);
+        assert_eq!(verify(&rfc_secret(), "28708a", 59, None), None);
+        assert_eq!(verify(&rfc_secret(), "287 082", 59, None), Some(1));
+    }
+}